@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX properties_graphs;
+
+ALTER TABLE public.properties
+    DROP COLUMN graph_id;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+ALTER TABLE public.properties
+    ADD COLUMN graph_id uuid;
+ALTER TABLE public.properties
+    ADD CONSTRAINT properties_graph_id FOREIGN KEY (graph_id)
+        REFERENCES public.objects (hash) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE NO ACTION
+        DEFERRABLE INITIALLY DEFERRED;
+
+CREATE INDEX properties_graphs
+    ON public.properties USING btree
+        (graph_id ASC NULLS LAST);
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX objects_timestamp_value;
+DROP INDEX objects_numeric_value;
+DROP INDEX objects_value_trigram;
+DROP INDEX objects_value_prefix;
+
+DROP FUNCTION apex_try_timestamptz(text);
+DROP FUNCTION apex_try_numeric(text);
@@ -0,0 +1,50 @@
+-- Your SQL goes here
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Casts which return NULL for values which aren't valid numbers or timestamps. Timestamps without
+-- an offset are read as UTC regardless of the session, which makes the cast immutable so it can be
+-- indexed.
+CREATE FUNCTION apex_try_numeric(value text) RETURNS numeric AS $$
+BEGIN
+    IF value !~ '^[+-]?([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?$' THEN
+        RETURN NULL;
+    END IF;
+    BEGIN
+        RETURN value::numeric;
+    EXCEPTION WHEN others THEN
+        RETURN NULL;
+    END;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT;
+
+CREATE FUNCTION apex_try_timestamptz(value text) RETURNS timestamptz AS $$
+BEGIN
+    IF value !~ '^[0-9]{4}-[0-9]{2}-[0-9]{2}' THEN
+        RETURN NULL;
+    END IF;
+    BEGIN
+        RETURN value::timestamptz;
+    EXCEPTION WHEN others THEN
+        RETURN NULL;
+    END;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT SET TimeZone = 'UTC';
+
+-- Values can be too long for a btree index, prefix queries match on the first characters.
+CREATE INDEX objects_value_prefix
+    ON public.objects USING btree
+        ((left(value, 256)) text_pattern_ops);
+
+CREATE INDEX objects_value_trigram
+    ON public.objects USING gin
+        (value gin_trgm_ops);
+
+CREATE INDEX objects_numeric_value
+    ON public.objects USING btree
+        (apex_try_numeric(value))
+    WHERE apex_try_numeric(value) IS NOT NULL;
+
+CREATE INDEX objects_timestamp_value
+    ON public.objects USING btree
+        (apex_try_timestamptz(value))
+    WHERE apex_try_timestamptz(value) IS NOT NULL;
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX properties_search;
+
+DROP TRIGGER properties_search_vector ON public.properties;
+DROP FUNCTION apex_update_search_vector();
+
+ALTER TABLE public.properties
+    DROP COLUMN search_vector;
+
+DROP FUNCTION apex_search_vector(uuid, integer, integer);
+DROP FUNCTION apex_search_config(text);
@@ -0,0 +1,57 @@
+-- Your SQL goes here
+
+-- The text search configuration for a language tag, `simple` when there is no dictionary.
+CREATE FUNCTION apex_search_config(language text) RETURNS regconfig AS $$
+    SELECT CASE lower(split_part(coalesce(language, ''), '-', 1))
+        WHEN 'da' THEN 'danish'
+        WHEN 'de' THEN 'german'
+        WHEN 'en' THEN 'english'
+        WHEN 'es' THEN 'spanish'
+        WHEN 'fi' THEN 'finnish'
+        WHEN 'fr' THEN 'french'
+        WHEN 'hu' THEN 'hungarian'
+        WHEN 'it' THEN 'italian'
+        WHEN 'nl' THEN 'dutch'
+        WHEN 'no' THEN 'norwegian'
+        WHEN 'pt' THEN 'portuguese'
+        WHEN 'ro' THEN 'romanian'
+        WHEN 'ru' THEN 'russian'
+        WHEN 'sv' THEN 'swedish'
+        WHEN 'tr' THEN 'turkish'
+        ELSE 'simple'
+    END::regconfig
+$$ LANGUAGE sql IMMUTABLE;
+
+-- Literals are indexed both stemmed for their language and as plain words.
+CREATE FUNCTION apex_search_vector(object_id uuid, datatype_id integer, language_id integer)
+    RETURNS tsvector AS $$
+    SELECT to_tsvector(apex_search_config(l.value), o.value) || to_tsvector('simple', o.value)
+    FROM objects o
+    JOIN datatypes d ON d.id = datatype_id
+    LEFT JOIN languages l ON l.id = language_id
+    WHERE o.hash = object_id
+      AND d.value NOT IN ('http://www.w3.org/1999/02/22-rdf-syntax-ns#namedNode',
+                          'http://www.w3.org/1999/02/22-rdf-syntax-ns#blankNode')
+$$ LANGUAGE sql STABLE;
+
+ALTER TABLE public.properties
+    ADD COLUMN search_vector tsvector;
+
+CREATE FUNCTION apex_update_search_vector() RETURNS trigger AS $$
+BEGIN
+    NEW.search_vector := apex_search_vector(NEW.object_id, NEW.datatype_id, NEW.language_id);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER properties_search_vector
+    BEFORE INSERT OR UPDATE OF object_id, datatype_id, language_id
+    ON public.properties
+    FOR EACH ROW EXECUTE PROCEDURE apex_update_search_vector();
+
+UPDATE public.properties
+    SET search_vector = apex_search_vector(object_id, datatype_id, language_id);
+
+CREATE INDEX properties_search
+    ON public.properties USING gin
+        (search_vector);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- Previous states of documents, kept by IRI so they outlive the document itself.
+CREATE TABLE public.document_revisions (
+    iri character varying NOT NULL,
+    version bigint NOT NULL,
+    language character varying NOT NULL,
+    recorded_at timestamp without time zone NOT NULL DEFAULT NOW(),
+    deleted boolean NOT NULL DEFAULT false,
+    statements text NOT NULL,
+    PRIMARY KEY (iri, version)
+);
+
+CREATE INDEX document_revisions_recorded_at
+    ON public.document_revisions USING btree
+        (recorded_at);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+-- Committed document changes which haven't been relayed yet, in commit order.
+CREATE TABLE public.outbox_events (
+    id bigserial PRIMARY KEY,
+    iri character varying NOT NULL,
+    operation character varying NOT NULL,
+    updated_at timestamp without time zone NOT NULL,
+    delta text,
+    created_at timestamp without time zone NOT NULL DEFAULT NOW()
+);
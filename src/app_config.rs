@@ -1,4 +1,5 @@
 use redis::IntoConnectionInfo;
+use std::collections::BTreeMap;
 use std::env;
 use std::str::FromStr;
 
@@ -41,6 +42,61 @@ pub struct AppConfig {
     pub device_id_cookie_sig_name: Option<String>,
     /// Key for checking cookie signatures
     pub session_secret: Option<String>,
+    /// Where documents are stored
+    pub storage_backend: StorageBackend,
+    /// How long previous revisions of documents are kept
+    pub history_retention: HistoryRetention,
+    /// Where the outbox of committed changes is relayed to, disabled when unset
+    pub outbox: Option<OutboxConfig>,
+    /// The redis channel committed deltas are published on, disables the change feed when unset
+    pub changes_channel: Option<String>,
+    /// The prefixes used to compact IRIs in Turtle, by prefix name
+    pub turtle_prefixes: BTreeMap<String, String>,
+}
+
+const DEFAULT_TURTLE_PREFIXES: &[(&str, &str)] = &[
+    ("dcterms", "http://purl.org/dc/terms/"),
+    ("foaf", "http://xmlns.com/foaf/0.1/"),
+    ("hydra", "http://www.w3.org/ns/hydra/core#"),
+    ("ontola", "https://ns.ontola.io/core#"),
+    ("owl", "http://www.w3.org/2002/07/owl#"),
+    ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+    ("rdfs", "http://www.w3.org/2000/01/rdf-schema#"),
+    ("schema", "http://schema.org/"),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+];
+
+#[derive(Clone, PartialEq, PartialOrd, Ord, Eq, Debug)]
+pub enum StorageBackend {
+    Postgres,
+    /// Keeps all documents in memory, lost on restart. Only serves documents and `/tpf`.
+    Memory,
+    /// Stores documents in an embedded database at the given path. Only serves documents and
+    /// `/tpf`.
+    Sled(String),
+}
+
+#[derive(Clone, PartialEq, PartialOrd, Ord, Eq, Debug)]
+pub struct HistoryRetention {
+    /// The maximum amount of revisions per document, the default of 0 disables the history
+    pub max_versions: usize,
+    /// The maximum age of revisions in days, the latest revision is always kept
+    pub max_age_days: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, PartialOrd, Ord, Eq, Debug)]
+pub struct OutboxConfig {
+    pub target: OutboxTarget,
+    /// Whether the events include the applied delta
+    pub include_delta: bool,
+}
+
+#[derive(Clone, PartialEq, PartialOrd, Ord, Eq, Debug)]
+pub enum OutboxTarget {
+    /// Publishes the events on the Kafka topic
+    Kafka(String),
+    /// Appends the events to the Redis stream
+    Redis(String),
 }
 
 #[derive(Clone, PartialEq, PartialOrd, Ord, Eq, Debug)]
@@ -131,6 +187,17 @@ impl Default for AppConfig {
             device_id_cookie_name: env::var("DEVICE_ID_COOKIE_NAME").ok(),
             device_id_cookie_sig_name: env::var("DEVICE_ID_COOKIE_SIGNATURE_NAME").ok(),
             session_secret: env::var("SESSION_SECRET").ok(),
+            storage_backend: match env::var("STORAGE_BACKEND").as_deref() {
+                Ok("memory") => StorageBackend::Memory,
+                Ok("sled") => StorageBackend::Sled(
+                    env::var("STORAGE_PATH").unwrap_or_else(|_| String::from("apex.sled")),
+                ),
+                _ => StorageBackend::Postgres,
+            },
+            history_retention: HistoryRetention::default(),
+            outbox: outbox_config(),
+            changes_channel: env::var("CHANGES_CHANNEL").ok(),
+            turtle_prefixes: turtle_prefixes(env::var("TURTLE_PREFIXES").ok()),
         }
     }
 }
@@ -150,6 +217,44 @@ impl AppConfig {
     }
 }
 
+fn outbox_config() -> Option<OutboxConfig> {
+    let target = match (
+        env::var("OUTBOX_KAFKA_TOPIC"),
+        env::var("OUTBOX_REDIS_STREAM"),
+    ) {
+        (Ok(topic), _) => OutboxTarget::Kafka(topic),
+        (_, Ok(stream)) => OutboxTarget::Redis(stream),
+        _ => return None,
+    };
+
+    Some(OutboxConfig {
+        target,
+        include_delta: env::var("OUTBOX_INCLUDE_DELTA")
+            .map(|v| v == "true")
+            .unwrap_or(false),
+    })
+}
+
+/// Adds the prefixes from a comma separated `prefix=namespace` list to the defaults.
+fn turtle_prefixes(config: Option<String>) -> BTreeMap<String, String> {
+    let mut prefixes: BTreeMap<String, String> = DEFAULT_TURTLE_PREFIXES
+        .iter()
+        .map(|(prefix, namespace)| (prefix.to_string(), namespace.to_string()))
+        .collect();
+
+    for pair in config.unwrap_or_default().split(',') {
+        match pair.trim().splitn(2, '=').collect::<Vec<&str>>().as_slice() {
+            [prefix, namespace] if !namespace.trim().is_empty() => {
+                prefixes.insert(prefix.trim().into(), namespace.trim().into());
+            }
+            [""] => (),
+            _ => warn!(target: "apex", "Ignoring invalid turtle prefix '{}'", pair),
+        }
+    }
+
+    prefixes
+}
+
 fn dot_prefix(value: &str) -> String {
     if value.len() > 0 {
         format!(".{}", value)
@@ -158,6 +263,20 @@ fn dot_prefix(value: &str) -> String {
     }
 }
 
+impl Default for HistoryRetention {
+    fn default() -> HistoryRetention {
+        HistoryRetention {
+            max_versions: env::var("HISTORY_MAX_VERSIONS")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0),
+            max_age_days: env::var("HISTORY_MAX_AGE_DAYS")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok()),
+        }
+    }
+}
+
 impl Default for ClusterConfig {
     fn default() -> ClusterConfig {
         let default_port = 3000u16;
@@ -0,0 +1,21 @@
+extern crate apex_rs;
+extern crate dotenv;
+#[macro_use]
+extern crate log;
+
+use apex_rs::importing::outbox::relay;
+use dotenv::dotenv;
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    debug!(target: "apex", "Booting");
+    if cfg!(debug_assertions) {
+        match dotenv() {
+            Ok(_) => info!(target: "apex", "Initialized .env"),
+            Err(e) => warn!(target: "apex", "Error loading .env: {}", e),
+        }
+    }
+
+    relay().await.unwrap();
+}
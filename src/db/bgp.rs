@@ -0,0 +1,434 @@
+//! Basic graph pattern database implementation
+//!
+//! A basic graph pattern is a set of hex patterns which share named variables. The patterns are
+//! compiled into a single query which joins the properties table onto itself once per pattern,
+//! so clients don't have to join the results of separate HPF requests themselves.
+//!
+//! Every solution consists of the statements matched by each of the patterns, the variable
+//! bindings can be read from those statements.
+
+use crate::db::db_context::DbContext;
+use crate::db::hpf::{properties_to_statements, HPFQuery, HPFQueryRequest, Position};
+use crate::db::models::Property;
+use crate::db::properties::MAX_PROPERTY_INSERT_SIZE;
+use crate::db::schema::properties;
+use crate::db::schema::resources::dsl as resources;
+use crate::errors::ErrorKind;
+use crate::hashtuple::{Statement, BLANK_NODE_IRI, NAMED_NODE_IRI};
+use actix_web::Either;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+const MAX_PATTERNS: usize = 16;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BGPQueryRequest {
+    patterns: Vec<HPFQueryRequest>,
+    #[serde(default)]
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: Option<i64>,
+}
+
+/// The statements matched by each of the patterns, in pattern order.
+pub(crate) type Solution = Vec<Statement>;
+
+/// A named variable occurring in a pattern.
+struct Occurrence {
+    name: String,
+    pattern: usize,
+    position: Position,
+}
+
+pub(crate) struct BGPQuery {
+    limit: i64,
+    offset: i64,
+
+    patterns: Vec<HPFQuery>,
+    occurrences: Vec<Occurrence>,
+    /// Whether a constant in the patterns doesn't occur in the store at all.
+    unsatisfiable: bool,
+}
+
+#[derive(QueryableByName)]
+struct SolutionRow {
+    #[sql_type = "Array<BigInt>"]
+    property_ids: Vec<i64>,
+}
+
+impl BGPQuery {
+    pub fn parse(db_ctx: &mut DbContext, request: &BGPQueryRequest) -> Result<BGPQuery, ErrorKind> {
+        if request.patterns.is_empty() || request.patterns.len() > MAX_PATTERNS {
+            return Err(ErrorKind::ParserError(format!(
+                "A basic graph pattern needs between 1 and {} patterns",
+                MAX_PATTERNS
+            )));
+        }
+
+        let mut occurrences = vec![];
+        for (pattern, request) in request.patterns.iter().enumerate() {
+            for (position, term) in request.terms().iter() {
+                if let Some(name) = term.as_ref().and_then(|t| t.strip_prefix('?')) {
+                    if !name.is_empty() {
+                        occurrences.push(Occurrence {
+                            name: String::from(name),
+                            pattern,
+                            position: *position,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut patterns = vec![];
+        let mut unsatisfiable = false;
+        for pattern in &request.patterns {
+            match HPFQuery::parse(db_ctx, pattern) {
+                Ok(query) => patterns.push(query),
+                Err(ErrorKind::NoResources) => unsatisfiable = true,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(BGPQuery {
+            limit: request.limit.unwrap_or(500).abs().max(1).min(10_000),
+            offset: request.offset.unwrap_or(0).max(0),
+
+            patterns,
+            occurrences,
+            unsatisfiable,
+        })
+    }
+
+    /// The named variables in order of appearance, with the pattern and position binding them.
+    pub fn variables(&self) -> Vec<(String, usize, Position)> {
+        let mut seen = HashSet::new();
+
+        self.occurrences
+            .iter()
+            .filter(|o| seen.insert(&o.name))
+            .map(|o| (o.name.clone(), o.pattern, o.position))
+            .collect()
+    }
+
+    pub fn execute(&self, db_ctx: &mut DbContext) -> Result<Vec<Solution>, ErrorKind> {
+        let sql = match self.to_sql(db_ctx) {
+            Some(sql) => sql,
+            None => return Ok(vec![]),
+        };
+
+        debug!(target: "apex", "Executing BGP query: {}", sql);
+        let rows = diesel::sql_query(sql)
+            .load::<SolutionRow>(&*db_ctx.get_conn())
+            .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+        let property_ids: HashSet<i64> = rows
+            .iter()
+            .flat_map(|row| row.property_ids.iter().cloned())
+            .collect();
+        let by_id = statements_by_id(db_ctx, property_ids)?;
+
+        // A property removed since the solutions were matched drops its solution.
+        let solutions = rows
+            .iter()
+            .filter_map(|row| {
+                row.property_ids
+                    .iter()
+                    .map(|id| by_id.get(id).copied())
+                    .collect()
+            })
+            .collect();
+
+        Ok(solutions)
+    }
+
+    /// Compiles the patterns into a self-join on properties, `None` if there can't be any matches.
+    ///
+    /// Only ids and hashes are interpolated into the query, IRIs and values are resolved up front.
+    fn to_sql(&self, db_ctx: &DbContext) -> Option<String> {
+        if self.unsatisfiable {
+            return None;
+        }
+
+        let mut from = vec![];
+        let mut filters = vec![];
+
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            from.push(format!(
+                "properties p{i} JOIN resources r{i} ON r{i}.id = p{i}.resource_id",
+                i = i
+            ));
+
+            if let Either::B(iri) = &pattern.subject {
+                let resource_ids = resources::resources
+                    .filter(resources::iri.eq(iri))
+                    .select(resources::id)
+                    .load::<i64>(&*db_ctx.get_conn())
+                    .ok()?;
+                if resource_ids.is_empty() {
+                    return None;
+                }
+                filters.push(format!("p{}.resource_id IN ({})", i, join(&resource_ids)));
+            }
+            filters.extend(pattern.constant_filters(i));
+        }
+
+        let mut bound: HashMap<&String, &Occurrence> = HashMap::new();
+        for occurrence in &self.occurrences {
+            match bound.get(&occurrence.name) {
+                Some(first) => filters.push(join_filter(first, occurrence)),
+                None => {
+                    bound.insert(&occurrence.name, occurrence);
+                }
+            }
+        }
+
+        let ids = (0..self.patterns.len())
+            .map(|i| format!("p{}.id", i))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let filters = if filters.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", filters.join(" AND "))
+        };
+
+        Some(format!(
+            "SELECT ARRAY[{ids}] AS property_ids FROM {from}{filters} ORDER BY {ids} LIMIT {limit} OFFSET {offset}",
+            ids = ids,
+            from = from.join(", "),
+            filters = filters,
+            limit = self.limit,
+            offset = self.offset,
+        ))
+    }
+}
+
+/// Loads the statements of the given properties by their id.
+pub(crate) fn statements_by_id(
+    db_ctx: &mut DbContext,
+    property_ids: HashSet<i64>,
+) -> Result<HashMap<i64, Statement>, ErrorKind> {
+    let mut matches = vec![];
+    for chunk in property_ids
+        .into_iter()
+        .collect::<Vec<i64>>()
+        .chunks(MAX_PROPERTY_INSERT_SIZE)
+    {
+        matches.extend(
+            properties::table
+                .filter(properties::id.eq_any(chunk))
+                .load::<Property>(&*db_ctx.get_conn())
+                .map_err(|e| ErrorKind::Unexpected(e.to_string()))?,
+        );
+    }
+
+    let statements = properties_to_statements(db_ctx, &matches);
+
+    Ok(matches.iter().map(|p| p.id).zip(statements).collect())
+}
+
+/// The condition for a variable occurring in both `a` and `b` to be bound to the same term.
+fn join_filter(a: &Occurrence, b: &Occurrence) -> String {
+    if a.position == Position::Value && b.position == Position::Value {
+        // An object is only the same term when its datatype and language match as well.
+        return ["object_id", "datatype_id", "language_id"]
+            .iter()
+            .map(|column| {
+                format!(
+                    "p{a}.{c} IS NOT DISTINCT FROM p{b}.{c}",
+                    a = a.pattern,
+                    b = b.pattern,
+                    c = column
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" AND ");
+    }
+    if a.position == b.position {
+        let column = |o: &Occurrence| match o.position {
+            Position::Subject => format!("r{}.iri", o.pattern),
+            Position::Predicate => format!("p{}.predicate_id", o.pattern),
+            Position::Value => format!("p{}.object_id", o.pattern),
+            Position::Datatype => format!("p{}.datatype_id", o.pattern),
+            Position::Language => format!("p{}.language_id", o.pattern),
+            Position::Graph => format!("p{}.graph_id", o.pattern),
+        };
+
+        return format!("{} IS NOT DISTINCT FROM {}", column(a), column(b));
+    }
+
+    // Terms in different positions are stored in different tables, so compare their text.
+    let text = |o: &Occurrence| match o.position {
+        Position::Subject => format!("r{}.iri", o.pattern),
+        Position::Predicate => format!(
+            "(SELECT value FROM predicates WHERE id = p{}.predicate_id)",
+            o.pattern
+        ),
+        Position::Value => format!(
+            "(SELECT value FROM objects WHERE hash = p{}.object_id)",
+            o.pattern
+        ),
+        Position::Datatype => format!(
+            "(SELECT value FROM datatypes WHERE id = p{}.datatype_id)",
+            o.pattern
+        ),
+        Position::Language => format!(
+            "COALESCE((SELECT value FROM languages WHERE id = p{}.language_id), '')",
+            o.pattern
+        ),
+        Position::Graph => format!(
+            "COALESCE((SELECT value FROM objects WHERE hash = p{}.graph_id), '')",
+            o.pattern
+        ),
+    };
+
+    // Only nodes can be bound to a term in a position which holds IRIs.
+    let node = |o: &Occurrence, other: &Occurrence| {
+        if o.position == Position::Value && other.position != Position::Language {
+            Some(format!(
+                "p{}.datatype_id IN (SELECT id FROM datatypes WHERE value IN ('{}', '{}'))",
+                o.pattern, NAMED_NODE_IRI, BLANK_NODE_IRI
+            ))
+        } else {
+            None
+        }
+    };
+
+    let mut conditions = vec![format!("{} = {}", text(a), text(b))];
+    conditions.extend(node(a, b));
+    conditions.extend(node(b, a));
+
+    conditions.join(" AND ")
+}
+
+fn join(ids: &[i64]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db_context::{test_lock, test_pool};
+    use crate::db::storage::StorageContext;
+    use crate::hashtuple::{HashModel, LookupTable, STRING_IRI};
+
+    #[test]
+    #[ignore]
+    fn test_execute_subject_object_join() {
+        let _lock = test_lock();
+        let pool = test_pool();
+        let mut ctx = DbContext::new_with_lang(&pool, Some(String::from("en")));
+        let mut statement = |s: &str, p: &str, o: &str, datatype: &str| {
+            let table = &mut ctx.lookup_table;
+            Statement::new(
+                table.ensure_value(s),
+                table.ensure_value(p),
+                table.ensure_value(o),
+                table.ensure_value(datatype),
+                table.ensure_value(""),
+                table.ensure_value(""),
+            )
+        };
+        let knows = "https://example.com/bgp/knows";
+        let name = "https://example.com/bgp/name";
+        let documents = vec![
+            (
+                "https://example.com/bgp/a",
+                vec![
+                    statement(
+                        "https://example.com/bgp/a",
+                        knows,
+                        "https://example.com/bgp/b",
+                        NAMED_NODE_IRI,
+                    ),
+                    // A literal with the text of an IRI isn't the resource itself.
+                    statement(
+                        "https://example.com/bgp/a",
+                        knows,
+                        "https://example.com/bgp/c",
+                        STRING_IRI,
+                    ),
+                ],
+            ),
+            (
+                "https://example.com/bgp/b",
+                vec![statement(
+                    "https://example.com/bgp/b",
+                    name,
+                    "B",
+                    STRING_IRI,
+                )],
+            ),
+            (
+                "https://example.com/bgp/c",
+                vec![statement(
+                    "https://example.com/bgp/c",
+                    name,
+                    "C",
+                    STRING_IRI,
+                )],
+            ),
+        ];
+        for (iri, model) in documents {
+            ctx.update_document(iri, &mut |_: &LookupTable, _: &HashModel| model.clone())
+                .unwrap();
+        }
+
+        let request: BGPQueryRequest = serde_json::from_value(serde_json::json!({
+            "patterns": [
+                { "subject": "?s", "predicate": knows, "value": "?o" },
+                { "subject": "?o", "predicate": name, "value": "?name" },
+            ]
+        }))
+        .unwrap();
+        let mut ctx = DbContext::new(&pool);
+        let query = BGPQuery::parse(&mut ctx, &request).unwrap();
+        let names: Vec<String> = query.variables().into_iter().map(|(n, _, _)| n).collect();
+        assert_eq!(names, vec!["s", "o", "name"]);
+
+        let solutions = query.execute(&mut ctx).unwrap();
+        assert_eq!(solutions.len(), 1);
+        let table = &ctx.lookup_table;
+        assert_eq!(
+            table.get_by_hash(solutions[0][0].value).unwrap(),
+            "https://example.com/bgp/b"
+        );
+        assert_eq!(table.get_by_hash(solutions[0][1].value).unwrap(), "B");
+    }
+
+    #[test]
+    fn test_join_filter() {
+        let occurrence = |pattern, position| Occurrence {
+            name: String::from("x"),
+            pattern,
+            position,
+        };
+
+        assert_eq!(
+            join_filter(
+                &occurrence(0, Position::Value),
+                &occurrence(1, Position::Value)
+            ),
+            "p0.object_id IS NOT DISTINCT FROM p1.object_id \
+             AND p0.datatype_id IS NOT DISTINCT FROM p1.datatype_id \
+             AND p0.language_id IS NOT DISTINCT FROM p1.language_id"
+        );
+        assert_eq!(
+            join_filter(
+                &occurrence(0, Position::Value),
+                &occurrence(1, Position::Subject)
+            ),
+            format!(
+                "(SELECT value FROM objects WHERE hash = p0.object_id) = r1.iri \
+                 AND p0.datatype_id IN (SELECT id FROM datatypes WHERE value IN ('{}', '{}'))",
+                NAMED_NODE_IRI, BLANK_NODE_IRI
+            )
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::app_config::HistoryRetention;
 use crate::db::models::{ConfigItem, Datatype, Language, Predicate};
 use crate::db::schema;
 use crate::db::schema::documents::dsl::documents;
@@ -8,6 +9,7 @@ use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use diesel::{r2d2, PgConnection};
 use std::hash::Hash;
+use std::rc::Rc;
 
 pub type IRIMapping = BiMap<String, i32>;
 
@@ -20,6 +22,10 @@ pub struct DbContext<'a> {
     pub resource_map: BiMap<String, i64>,
     pub lang: Option<String>,
     pub lookup_table: LookupTable,
+    /// How long previous revisions of the documents are kept.
+    pub retention: HistoryRetention,
+    /// The connection of the running transaction, all queries use it until it ends.
+    pub(crate) transaction_conn: Option<Rc<DbConnection>>,
 }
 
 pub struct DbCounts {
@@ -32,12 +38,19 @@ pub struct Config {
 }
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+pub type DbConnection = PooledConnection<ConnectionManager<PgConnection>>;
 
 impl<'a> DbContext<'a> {
-    pub fn get_conn(&self) -> PooledConnection<ConnectionManager<PgConnection>> {
-        self.db_pool
-            .get()
-            .expect("Failed to get connection from pool")
+    /// The connection of the running transaction, or else one from the pool.
+    pub fn get_conn(&self) -> Rc<DbConnection> {
+        match &self.transaction_conn {
+            Some(conn) => Rc::clone(conn),
+            None => Rc::new(
+                self.db_pool
+                    .get()
+                    .expect("Failed to get connection from pool"),
+            ),
+        }
     }
 
     pub fn new(db_pool: &'a DbPool) -> DbContext<'a> {
@@ -56,9 +69,18 @@ impl<'a> DbContext<'a> {
             lookup_table: LookupTable::new(config.seed),
             lang,
             config,
+            retention: HistoryRetention::default(),
+            transaction_conn: None,
         }
     }
 
+    /// Reloads the mappings, which may hold the ids of rows of a rolled back transaction.
+    pub(crate) fn reload_mappings(&mut self) {
+        self.property_map = get_predicates(self.db_pool);
+        self.datatype_map = get_datatypes(self.db_pool);
+        self.language_map = get_languages(self.db_pool);
+    }
+
     pub(crate) fn custom_pool(connspec: &str, max_size: u32) -> DbPool {
         let manager = ConnectionManager::<PgConnection>::new(connspec);
 
@@ -93,6 +115,25 @@ impl<'a> DbContext<'a> {
     }
 }
 
+/// A pool for the database at `DATABASE_URL`, used by the tests which need postgres.
+#[cfg(test)]
+pub(crate) fn test_pool() -> DbPool {
+    let database_url = std::env::var("DATABASE_URL").expect("The postgres tests need DATABASE_URL");
+
+    DbContext::custom_pool(&database_url, 4)
+}
+
+/// Keeps the postgres tests from changing the database at the same time.
+#[cfg(test)]
+pub(crate) fn test_lock() -> std::sync::MutexGuard<'static, ()> {
+    lazy_static::lazy_static! {
+        static ref LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    }
+
+    LOCK.lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
 /// Parses the _apex_config table into a config object.
 pub(crate) fn get_config(db_conn: &DbPool) -> Result<Config, ()> {
     use schema::_apex_config::dsl;
@@ -1,5 +1,7 @@
 use crate::db::db_context::DbContext;
 use crate::db::models::*;
+use crate::db::properties::{insert_properties, MAX_PROPERTY_INSERT_SIZE};
+use crate::db::resources::insert_resources;
 use crate::db::schema;
 use crate::db::schema::objects::dsl as objects;
 use crate::errors::ErrorKind;
@@ -8,20 +10,41 @@ use diesel::debug_query;
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+
+/// The stored state of a document together with the database ids of its resources and properties.
+pub(crate) struct StoredDocument {
+    pub doc: Document,
+    /// Resource ids by resource IRI.
+    pub resources: HashMap<String, i64>,
+    /// Property ids with their statement.
+    pub properties: Vec<(i64, Statement)>,
+}
 
 pub fn doc_by_iri<'a>(
-    mut ctx: &'a mut DbContext,
+    ctx: &'a mut DbContext,
     iri: &str,
 ) -> Result<(Document, HashModel), ErrorKind> {
+    let stored = stored_doc_by_iri(ctx, iri)?;
+    let props = stored.properties.into_iter().map(|(_, s)| s).collect();
+
+    Ok((stored.doc, props))
+}
+
+pub(crate) fn stored_doc_by_iri<'a>(
+    mut ctx: &'a mut DbContext,
+    iri: &str,
+) -> Result<StoredDocument, ErrorKind> {
     let docs = get_document(&mut ctx, iri);
-    let first = docs.first();
+    let first = docs.into_iter().next();
 
-    if docs.is_empty() {
+    if first.is_none() {
         warn!(target: "apex", "Doc with iri '{}' is empty", iri);
         return Err(ErrorKind::EmptyDocument);
     }
 
-    let mut props: HashModel = vec![];
+    let mut props = vec![];
+    let mut resource_ids = HashMap::new();
     let (doc, resources) = first.unwrap();
     for (resource, resource_properties) in resources {
         for p in resource_properties {
@@ -42,18 +65,27 @@ pub fn doc_by_iri<'a>(
                 None => &empty,
             };
 
-            props.push(Statement::new(
+            let statement = Statement::new(
                 ctx.lookup_table.ensure_value(&resource.iri),
                 ctx.lookup_table.ensure_value(predicate),
                 p.object_id.expect("Property without object_id").into(),
                 ctx.lookup_table.ensure_value(datatype),
                 ctx.lookup_table.ensure_value(language),
-                ctx.lookup_table.ensure_value(&String::from(EMPTY_STRING)),
-            ));
+                match p.graph_id {
+                    Some(graph) => graph.into(),
+                    None => ctx.lookup_table.ensure_value(EMPTY_STRING),
+                },
+            );
+            props.push((p.id, statement));
         }
+        resource_ids.insert(resource.iri, resource.id);
     }
 
-    Ok((doc.clone(), props))
+    Ok(StoredDocument {
+        doc,
+        resources: resource_ids,
+        properties: props,
+    })
 }
 
 const RANDOM_DOC_ID: &str = "SELECT *
@@ -65,7 +97,7 @@ JOIN documents USING (id)
 LIMIT  1;";
 
 pub fn random_doc(ctx: &mut DbContext) -> Result<(Document, HashModel), ErrorKind> {
-    let random_iri = match diesel::sql_query(RANDOM_DOC_ID).get_result::<Document>(&ctx.get_conn())
+    let random_iri = match diesel::sql_query(RANDOM_DOC_ID).get_result::<Document>(&*ctx.get_conn())
     {
         Ok(doc) => doc.iri,
         Err(e) => {
@@ -79,10 +111,11 @@ pub fn random_doc(ctx: &mut DbContext) -> Result<(Document, HashModel), ErrorKin
 
 const EMPTY_STRING: &str = "";
 
-pub(crate) fn reset_document<'a>(mut ctx: &'a mut DbContext, iri: &str) -> (Document, HashModel) {
-    match doc_by_iri(&mut ctx, iri) {
+/// Fetches the stored document, inserting an empty one if it doesn't exist yet.
+pub(crate) fn find_or_create_document(ctx: &mut DbContext, iri: &str) -> StoredDocument {
+    match stored_doc_by_iri(ctx, iri) {
         Err(e) => {
-            debug!(target: "apex", "Error resetting document: {}", e);
+            debug!(target: "apex", "Error fetching document: {}", e);
             trace!(target: "apex", "Document iri {} not yet in db", iri);
             let doc = &NewDocument {
                 iri: String::from(iri),
@@ -90,23 +123,103 @@ pub(crate) fn reset_document<'a>(mut ctx: &'a mut DbContext, iri: &str) -> (Docu
             };
             let doc = diesel::insert_into(schema::documents::table)
                 .values(doc)
-                .get_result::<Document>(&ctx.get_conn())
+                .get_result::<Document>(&*ctx.get_conn())
                 .expect("Error while inserting into documents");
 
-            (doc, vec![])
+            StoredDocument {
+                doc,
+                resources: HashMap::new(),
+                properties: vec![],
+            }
+        }
+        Ok(stored) => {
+            trace!(target: "apex", "Document with iri {} has id {}", stored.doc.iri, stored.doc.id);
+            stored
         }
-        Ok(model) => {
-            trace!(target: "apex", "Document with iri {} has id {}", model.0.iri, model.0.id);
-            delete_document_data(&ctx.get_conn(), iri);
-            model
+    }
+}
+
+/// Persists `next` as the new contents of `stored`, only touching the properties and resources
+/// which changed.
+///
+/// Returns whether anything changed.
+pub(crate) fn update_document_data(
+    ctx: &mut DbContext,
+    stored: StoredDocument,
+    next: &HashModel,
+) -> bool {
+    use schema::properties;
+    use schema::resources;
+
+    let next_set: HashSet<Statement> = next.iter().cloned().collect();
+    let current_set: HashSet<Statement> = stored.properties.iter().map(|(_, s)| *s).collect();
+
+    let removed: Vec<i64> = stored
+        .properties
+        .iter()
+        .filter(|(_, s)| !next_set.contains(s))
+        .map(|(id, _)| *id)
+        .collect();
+    let mut seen = HashSet::new();
+    let added: HashModel = next
+        .iter()
+        .filter(|s| !current_set.contains(s) && seen.insert(**s))
+        .cloned()
+        .collect();
+
+    if removed.is_empty() && added.is_empty() {
+        return false;
+    }
+
+    let db_conn = ctx.get_conn();
+    for chunk in removed.chunks(MAX_PROPERTY_INSERT_SIZE) {
+        diesel::delete(properties::table.filter(properties::id.eq_any(chunk)))
+            .execute(&*db_conn)
+            .expect("Couldn't delete removed properties");
+    }
+
+    let subjects: HashSet<&String> = next_set
+        .iter()
+        .filter_map(|s| ctx.lookup_table.get_by_hash(s.subject))
+        .collect();
+    let orphaned: Vec<i64> = stored
+        .resources
+        .iter()
+        .filter(|(iri, _)| !subjects.contains(iri))
+        .map(|(_, id)| *id)
+        .collect();
+    if !orphaned.is_empty() {
+        diesel::delete(resources::table.filter(resources::id.eq_any(&orphaned)))
+            .execute(&*db_conn)
+            .expect("Couldn't delete orphaned resources");
+    }
+
+    let mut resource_id_map = stored.resources;
+    let new_resources: HashModel = added
+        .iter()
+        .filter(|s| {
+            let iri = ctx.lookup_table.get_by_hash(s.subject).unwrap();
+            !resource_id_map.contains_key(iri)
+        })
+        .cloned()
+        .collect();
+    if !new_resources.is_empty() {
+        let inserted = insert_resources(&db_conn, &ctx.lookup_table, &new_resources, stored.doc.id);
+        for resource in inserted {
+            resource_id_map.insert(resource.iri, resource.id);
         }
     }
+
+    insert_properties(ctx, &added, resource_id_map);
+    touch_document(&ctx.get_conn(), stored.doc.id).expect("Couldn't update document timestamp");
+
+    true
 }
 
-pub(crate) fn update_cache_control(db_conn: &PgConnection, docs: &Vec<crate::models::Document>) {
+pub(crate) fn update_cache_control(db_conn: &PgConnection, docs: &[crate::models::Document]) {
     use schema::documents::dsl::*;
 
-    for (cc, group) in &docs.into_iter().group_by(|d| d.cache_control) {
+    for (cc, group) in &docs.iter().group_by(|d| d.cache_control) {
         let iris = group.map(|d| d.iri.clone()).collect::<Vec<String>>();
         let docs = documents.filter(iri.eq_any(iris));
 
@@ -117,6 +230,14 @@ pub(crate) fn update_cache_control(db_conn: &PgConnection, docs: &Vec<crate::mod
     }
 }
 
+pub(crate) fn touch_document(db_conn: &PgConnection, doc_id: i64) -> QueryResult<usize> {
+    use schema::documents::dsl::*;
+
+    diesel::update(documents.find(doc_id))
+        .set(updated_at.eq(diesel::dsl::now))
+        .execute(db_conn)
+}
+
 pub(crate) fn delete_all_document_data(db_conn: &PgConnection) -> QueryResult<usize> {
     db_conn.execute("TRUNCATE TABLE documents CASCADE")
 }
@@ -165,17 +286,17 @@ fn get_document(
     let docs = if let Some(lang) = db_ctx.lang.clone() {
         documents
             .filter(iri.eq(doc_iri).and(language.eq(lang)))
-            .load::<Document>(&db_conn)
+            .load::<Document>(&*db_conn)
             .unwrap()
     } else {
         documents
             .filter(iri.eq(doc_iri))
-            .load::<Document>(&db_conn)
+            .load::<Document>(&*db_conn)
             .unwrap()
     };
 
     let doc_resources: Vec<Resource> = Resource::belonging_to(&docs)
-        .load::<Resource>(&db_conn)
+        .load::<Resource>(&*db_conn)
         .unwrap();
 
     let q = Property::belonging_to(&doc_resources);
@@ -183,7 +304,7 @@ fn get_document(
         let sql = debug_query::<Pg, _>(&q).to_string();
         debug!(target: "apex", "Executing bulk query: {}", sql);
     }
-    let doc_properties: Vec<Property> = match q.load::<Property>(&db_conn) {
+    let doc_properties: Vec<Property> = match q.load::<Property>(&*db_conn) {
         Ok(res) => res,
         Err(e) => {
             println!("{:?}", e);
@@ -194,13 +315,14 @@ fn get_document(
     let object_ids = doc_properties
         .iter()
         .map(|p| p.object_id.expect("Property without object_id"))
+        .chain(doc_properties.iter().filter_map(|p| p.graph_id))
         .collect::<Vec<_>>();
 
     let grouped_properties: Vec<Vec<Property>> = doc_properties.grouped_by(&doc_resources);
 
     let values = objects::objects
         .filter(objects::hash.eq_any(object_ids))
-        .load::<Object>(&db_conn)
+        .load::<Object>(&*db_conn)
         .unwrap();
 
     values.iter().for_each(|object| {
@@ -0,0 +1,826 @@
+//! Embedded on-disk storage using sled, for deployments without Postgres.
+//!
+//! Statements are stored as their hashes, with the values of the hashes in a separate tree. The
+//! hash seed is stored alongside, so the hashes stay valid across restarts.
+//!
+//! Trees:
+//! - `documents`: `iri \0 language` to the document metadata.
+//! - `statements`: `iri \0 language \0 statement`.
+//! - `subjects`, `predicates`, `objects`: the statement with the indexed terms first, followed by
+//!   the document key.
+//! - `values`: hash to value.
+//! - `revisions`: `iri \0 version` to the revision with its statements by value.
+//! - `outbox`: event id to the change event which hasn't been relayed yet.
+
+use crate::app_config::HistoryRetention;
+use crate::db::models::Document;
+use crate::db::storage::{
+    bound_term, expired_revisions, parse_object, restore, select_revision, snapshot, Operation,
+    OutboxEvent, Pattern, PatternPage, Revision, RevisionRecord, RevisionSelector, Snapshot,
+    Storage, StorageContext,
+};
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, LookupTable, Statement};
+use crate::models;
+use chrono::{NaiveDateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{IVec, Transactional, Tree};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+const SEED_KEY: &[u8] = b"seed";
+const STATEMENT_LEN: usize = 6 * 16;
+/// The amount of locks the documents are distributed over.
+const DOCUMENT_LOCKS: usize = 64;
+
+/// The order of the terms in each index.
+const SUBJECT_ORDER: [usize; 6] = [0, 1, 2, 3, 4, 5];
+const PREDICATE_ORDER: [usize; 6] = [1, 0, 2, 3, 4, 5];
+const OBJECT_ORDER: [usize; 6] = [2, 3, 4, 1, 0, 5];
+
+pub(crate) struct SledStorage {
+    db: sled::Db,
+    seed: u32,
+    retention: HistoryRetention,
+    documents: Tree,
+    statements: Tree,
+    subjects: Tree,
+    predicates: Tree,
+    objects: Tree,
+    values: Tree,
+    revisions: Tree,
+    outbox: Tree,
+    locks: Vec<Mutex<()>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredDocument {
+    id: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    cache_control: i16,
+}
+
+impl SledStorage {
+    pub fn new(path: &str, retention: HistoryRetention) -> Result<SledStorage, ErrorKind> {
+        SledStorage::from_db(sled::open(path)?, retention)
+    }
+
+    fn from_db(db: sled::Db, retention: HistoryRetention) -> Result<SledStorage, ErrorKind> {
+        let seed = match db.get(SEED_KEY)? {
+            Some(seed) => u32::from_be_bytes(
+                seed.as_ref()
+                    .try_into()
+                    .map_err(|_| ErrorKind::Unexpected("Invalid hash seed".into()))?,
+            ),
+            None => {
+                let seed = rand::random::<u32>();
+                db.insert(SEED_KEY, &seed.to_be_bytes())?;
+
+                seed
+            }
+        };
+
+        Ok(SledStorage {
+            db: db.clone(),
+            seed,
+            retention,
+            documents: db.open_tree("documents")?,
+            statements: db.open_tree("statements")?,
+            subjects: db.open_tree("subjects")?,
+            predicates: db.open_tree("predicates")?,
+            objects: db.open_tree("objects")?,
+            values: db.open_tree("values")?,
+            revisions: db.open_tree("revisions")?,
+            outbox: db.open_tree("outbox")?,
+            locks: (0..DOCUMENT_LOCKS).map(|_| Mutex::new(())).collect(),
+        })
+    }
+
+    /// Serializes the read-modify-write cycles on the documents with the given IRI.
+    fn lock(&self, iri: &str) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        iri.hash(&mut hasher);
+
+        self.locks[hasher.finish() as usize % DOCUMENT_LOCKS]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn load_revisions(&self, iri: &str) -> Result<Vec<RevisionRecord>, ErrorKind> {
+        let mut records = vec![];
+        for entry in self.revisions.scan_prefix(iri_prefix(iri)) {
+            let (_, value) = entry?;
+            records.push(
+                serde_json::from_slice(&value).map_err(|e| ErrorKind::Unexpected(e.to_string()))?,
+            );
+        }
+
+        Ok(records)
+    }
+
+    /// The revision recording the new state of the document, `None` for a deletion.
+    ///
+    /// Returns `None` when the history is disabled.
+    fn next_revision(
+        &self,
+        doc_key: &[u8],
+        statements: Option<Snapshot>,
+    ) -> Result<Option<RevisionRecord>, ErrorKind> {
+        if self.retention.max_versions == 0 {
+            return Ok(None);
+        }
+
+        let (iri, language) = split_doc_key(doc_key);
+        let version = match self
+            .revisions
+            .scan_prefix(iri_prefix(&String::from_utf8_lossy(iri)))
+            .next_back()
+        {
+            Some(entry) => {
+                let (key, _) = entry?;
+                i64::from_be_bytes(key[key.len() - 8..].try_into().unwrap()) + 1
+            }
+            None => 1,
+        };
+
+        Ok(Some(RevisionRecord {
+            revision: Revision {
+                version,
+                language: String::from_utf8_lossy(language).into(),
+                recorded_at: Utc::now().naive_utc(),
+                deleted: statements.is_none(),
+            },
+            statements: statements.unwrap_or_default(),
+        }))
+    }
+
+    fn prune_revisions(&self, iri: &str) -> Result<(), ErrorKind> {
+        let revisions: Vec<Revision> = self
+            .load_revisions(iri)?
+            .into_iter()
+            .map(|r| r.revision)
+            .collect();
+
+        for version in expired_revisions(&self.retention, &revisions, Utc::now().naive_utc()) {
+            self.revisions
+                .remove(revision_key(iri.as_bytes(), version))?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies the changes to a document atomically, `doc` of `None` removes the document.
+    fn write(
+        &self,
+        doc_key: &[u8],
+        doc: Option<&StoredDocument>,
+        removed: &[Statement],
+        added: &[Statement],
+        revision: Option<RevisionRecord>,
+    ) -> Result<(), ErrorKind> {
+        let doc = match doc {
+            Some(doc) => {
+                Some(serde_json::to_vec(doc).map_err(|e| ErrorKind::Unexpected(e.to_string()))?)
+            }
+            None => None,
+        };
+        let (iri, _) = split_doc_key(doc_key);
+        let revision = match &revision {
+            Some(record) => Some((
+                revision_key(iri, record.revision.version),
+                serde_json::to_vec(record).map_err(|e| ErrorKind::Unexpected(e.to_string()))?,
+            )),
+            None => None,
+        };
+
+        let trees = (
+            &self.documents,
+            &self.statements,
+            &self.subjects,
+            &self.predicates,
+            &self.objects,
+            &self.revisions,
+        );
+        trees
+            .transaction(
+                |(documents, statements, subjects, predicates, objects, revisions)| {
+                    match &doc {
+                        Some(doc) => documents.insert(doc_key, doc.as_slice())?,
+                        None => documents.remove(doc_key)?,
+                    };
+                    if let Some((key, record)) = &revision {
+                        revisions.insert(key.as_slice(), record.as_slice())?;
+                    }
+
+                    for s in removed {
+                        statements.remove(statement_key(doc_key, s))?;
+                        subjects.remove(index_key(&SUBJECT_ORDER, s, doc_key))?;
+                        predicates.remove(index_key(&PREDICATE_ORDER, s, doc_key))?;
+                        objects.remove(index_key(&OBJECT_ORDER, s, doc_key))?;
+                    }
+                    for s in added {
+                        statements.insert(statement_key(doc_key, s), &[])?;
+                        subjects.insert(index_key(&SUBJECT_ORDER, s, doc_key), &[])?;
+                        predicates.insert(index_key(&PREDICATE_ORDER, s, doc_key), &[])?;
+                        objects.insert(index_key(&OBJECT_ORDER, s, doc_key), &[])?;
+                    }
+
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                },
+            )
+            .map_err(|e| match e {
+                TransactionError::Storage(e) => ErrorKind::from(e),
+                TransactionError::Abort(_) => ErrorKind::Unexpected("Transaction aborted".into()),
+            })?;
+
+        if revision.is_some() {
+            self.prune_revisions(&String::from_utf8_lossy(iri))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for SledStorage {
+    fn open(&self, lang: Option<String>) -> Box<dyn StorageContext + '_> {
+        Box::new(SledContext {
+            storage: self,
+            lang,
+            lookup_table: LookupTable::new(self.seed),
+        })
+    }
+}
+
+pub(crate) struct SledContext<'a> {
+    storage: &'a SledStorage,
+    lang: Option<String>,
+    lookup_table: LookupTable,
+}
+
+impl<'a> SledContext<'a> {
+    /// The key of the document in the language of the context, or in any language without one.
+    fn find(&self, iri: &str) -> Result<Option<IVec>, ErrorKind> {
+        for entry in self.storage.documents.scan_prefix(iri_prefix(iri)) {
+            let (key, _) = entry?;
+            let (_, language) = split_doc_key(&key);
+            if self.lang.iter().all(|lang| lang.as_bytes() == language) {
+                return Ok(Some(key));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn load_document(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(Document, HashModel), ErrorKind> {
+        let stored: StoredDocument =
+            serde_json::from_slice(value).map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+        let (iri, language) = split_doc_key(key);
+        let doc = Document {
+            id: stored.id,
+            iri: String::from_utf8_lossy(iri).into(),
+            created_at: stored.created_at,
+            updated_at: stored.updated_at,
+            cache_control: stored.cache_control,
+            language: String::from_utf8_lossy(language).into(),
+        };
+
+        Ok((doc, self.load_statements(key)?))
+    }
+
+    fn load_statements(&mut self, doc_key: &[u8]) -> Result<HashModel, ErrorKind> {
+        let mut prefix = doc_key.to_vec();
+        prefix.push(0);
+
+        let mut model = vec![];
+        for entry in self.storage.statements.scan_prefix(prefix) {
+            let (key, _) = entry?;
+            let statement = decode_statement(&SUBJECT_ORDER, &key[key.len() - STATEMENT_LEN..]);
+            self.resolve(&statement)?;
+            model.push(statement);
+        }
+
+        Ok(model)
+    }
+
+    /// Adds the values of the statement to the lookup table.
+    fn resolve(&mut self, statement: &Statement) -> Result<(), ErrorKind> {
+        for hash in &terms(statement) {
+            if self.lookup_table.get_by_hash(*hash).is_some() {
+                continue;
+            }
+            let value = self
+                .storage
+                .values
+                .get(hash.to_be_bytes())?
+                .ok_or_else(|| ErrorKind::Unexpected(format!("Missing value for {}", hash)))?;
+            self.lookup_table
+                .ensure_value(&String::from_utf8_lossy(&value));
+        }
+
+        Ok(())
+    }
+
+    fn store_values(&self, model: &[Statement]) -> Result<(), ErrorKind> {
+        let mut batch = sled::Batch::default();
+        for hash in model.iter().flat_map(terms).collect::<HashSet<u128>>() {
+            let value = self
+                .lookup_table
+                .get_by_hash(hash)
+                .ok_or_else(|| ErrorKind::Unexpected(format!("Unknown value for {}", hash)))?;
+            batch.insert(&hash.to_be_bytes(), value.as_bytes());
+        }
+
+        Ok(self.storage.values.apply_batch(batch)?)
+    }
+}
+
+impl<'a> StorageContext for SledContext<'a> {
+    fn lookup_table(&mut self) -> &mut LookupTable {
+        &mut self.lookup_table
+    }
+
+    fn into_lookup_table(self: Box<Self>) -> LookupTable {
+        self.lookup_table
+    }
+
+    fn get_document(&mut self, iri: &str) -> Result<(Document, HashModel), ErrorKind> {
+        let key = self.find(iri)?.ok_or(ErrorKind::EmptyDocument)?;
+        let value = self
+            .storage
+            .documents
+            .get(&key)?
+            .ok_or(ErrorKind::EmptyDocument)?;
+
+        self.load_document(&key, &value)
+    }
+
+    fn random_document(&mut self) -> Result<(Document, HashModel), ErrorKind> {
+        let count = self.storage.documents.len();
+        if count == 0 {
+            return Err(ErrorKind::NoResources);
+        }
+        let n = rand::thread_rng().gen_range(0, count);
+        let (key, value) = self
+            .storage
+            .documents
+            .iter()
+            .nth(n)
+            .ok_or(ErrorKind::NoResources)??;
+
+        self.load_document(&key, &value)
+    }
+
+    fn update_document(
+        &mut self,
+        iri: &str,
+        update: &mut dyn FnMut(&LookupTable, &HashModel) -> HashModel,
+    ) -> Result<bool, ErrorKind> {
+        let _guard = self.storage.lock(iri);
+        let now = Utc::now().naive_utc();
+        let (key, mut stored, existing, created) = match self.find(iri)? {
+            Some(key) => {
+                let value = self
+                    .storage
+                    .documents
+                    .get(&key)?
+                    .ok_or(ErrorKind::EmptyDocument)?;
+                let stored: StoredDocument = serde_json::from_slice(&value)
+                    .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+                let existing = self.load_statements(&key)?;
+
+                (key.to_vec(), stored, existing, false)
+            }
+            None => {
+                let language = self.lang.clone().unwrap_or_default();
+                let stored = StoredDocument {
+                    id: self.storage.db.generate_id()? as i64,
+                    created_at: now,
+                    updated_at: now,
+                    cache_control: 0,
+                };
+
+                (doc_key(iri, &language), stored, vec![], true)
+            }
+        };
+
+        let mut next = update(&self.lookup_table, &existing);
+        let mut seen = HashSet::new();
+        next.retain(|s| seen.insert(*s));
+
+        let changed = next != existing;
+        if !changed && !created {
+            return Ok(false);
+        }
+
+        let statements = snapshot(&self.lookup_table, &next);
+        let existing_set: HashSet<Statement> = existing.iter().copied().collect();
+        let removed: Vec<Statement> = existing.into_iter().filter(|s| !seen.contains(s)).collect();
+        let added: Vec<Statement> = next
+            .into_iter()
+            .filter(|s| !existing_set.contains(s))
+            .collect();
+
+        self.store_values(&added)?;
+        let revision = if changed {
+            stored.updated_at = now;
+            self.storage.next_revision(&key, Some(statements))?
+        } else {
+            None
+        };
+        self.storage
+            .write(&key, Some(&stored), &removed, &added, revision)?;
+
+        Ok(changed)
+    }
+
+    fn delete_document(&mut self, iri: &str) -> Result<(), ErrorKind> {
+        let _guard = self.storage.lock(iri);
+        let keys = self
+            .storage
+            .documents
+            .scan_prefix(iri_prefix(iri))
+            .keys()
+            .collect::<Result<Vec<IVec>, sled::Error>>()?;
+        if keys.is_empty() {
+            return Err(ErrorKind::NotFound);
+        }
+
+        for key in keys {
+            let statements = self.load_statements(&key)?;
+            let revision = self.storage.next_revision(&key, None)?;
+            self.storage.write(&key, None, &statements, &[], revision)?;
+        }
+
+        Ok(())
+    }
+
+    fn delete_all(&mut self) -> Result<(), ErrorKind> {
+        let storage = self.storage;
+        for tree in &[
+            &storage.documents,
+            &storage.statements,
+            &storage.subjects,
+            &storage.predicates,
+            &storage.objects,
+            &storage.values,
+        ] {
+            tree.clear()?;
+        }
+
+        Ok(())
+    }
+
+    fn update_cache_control(&mut self, docs: &[models::Document]) -> Result<(), ErrorKind> {
+        for doc in docs {
+            let _guard = self.storage.lock(&doc.iri);
+            for entry in self.storage.documents.scan_prefix(iri_prefix(&doc.iri)) {
+                let (key, value) = entry?;
+                let mut stored: StoredDocument = serde_json::from_slice(&value)
+                    .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+                stored.cache_control = i16::from(doc.cache_control);
+                let value = serde_json::to_vec(&stored)
+                    .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+                self.storage.documents.insert(key, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn count_documents(&mut self) -> i64 {
+        self.storage.documents.len() as i64
+    }
+
+    fn match_pattern(&mut self, pattern: &Pattern) -> Result<PatternPage, ErrorKind> {
+        let table = &self.lookup_table;
+        let subject = bound_term(&pattern.subject).map(|s| table.calculate_hash(s));
+        let predicate = bound_term(&pattern.predicate).map(|p| table.calculate_hash(p));
+        let object = parse_object(&pattern.object)?.map(|o| {
+            [
+                table.calculate_hash(&o[0]),
+                table.calculate_hash(&o[1]),
+                table.calculate_hash(&o[2]),
+            ]
+        });
+        let document = bound_term(&pattern.document);
+
+        // Scan the most selective index for the bound terms.
+        let (order, scan) = if let Some(subject) = subject {
+            (
+                Some(SUBJECT_ORDER),
+                self.storage.subjects.scan_prefix(hash_bytes(&[subject])),
+            )
+        } else if let Some(object) = object {
+            (
+                Some(OBJECT_ORDER),
+                self.storage.objects.scan_prefix(hash_bytes(&object)),
+            )
+        } else if let Some(predicate) = predicate {
+            (
+                Some(PREDICATE_ORDER),
+                self.storage
+                    .predicates
+                    .scan_prefix(hash_bytes(&[predicate])),
+            )
+        } else if let Some(document) = document {
+            (
+                None,
+                self.storage.statements.scan_prefix(iri_prefix(document)),
+            )
+        } else {
+            (None, self.storage.statements.iter())
+        };
+
+        let mut matches = vec![];
+        for entry in scan {
+            let (key, _) = entry?;
+            let (statement, doc_key) = match order {
+                Some(order) => (
+                    decode_statement(&order, &key[..STATEMENT_LEN]),
+                    &key[STATEMENT_LEN..],
+                ),
+                None => (
+                    decode_statement(&SUBJECT_ORDER, &key[key.len() - STATEMENT_LEN..]),
+                    &key[..key.len() - STATEMENT_LEN - 1],
+                ),
+            };
+
+            let is_match = subject.iter().all(|s| *s == statement.subject)
+                && predicate.iter().all(|p| *p == statement.predicate)
+                && object
+                    .iter()
+                    .all(|o| *o == [statement.value, statement.datatype, statement.language])
+                && document
+                    .iter()
+                    .all(|d| split_doc_key(doc_key).0 == d.as_bytes());
+            if is_match {
+                matches.push(statement);
+            }
+        }
+
+        let (range, next, previous) = pattern.page_range(matches.len());
+        let statements = matches[range].to_vec();
+        for statement in &statements {
+            self.resolve(statement)?;
+        }
+
+        Ok(PatternPage {
+            statements,
+            next,
+            previous,
+            count: matches.len() as i64,
+        })
+    }
+
+    fn revisions(&mut self, iri: &str) -> Result<Vec<Revision>, ErrorKind> {
+        let records = self.storage.load_revisions(iri)?;
+
+        Ok(records.into_iter().map(|r| r.revision).collect())
+    }
+
+    fn get_revision(
+        &mut self,
+        iri: &str,
+        selector: &RevisionSelector,
+    ) -> Result<(Revision, HashModel), ErrorKind> {
+        let records = self.storage.load_revisions(iri)?;
+        let revisions: Vec<Revision> = records.iter().map(|r| r.revision.clone()).collect();
+        let revision =
+            select_revision(&revisions, &self.lang, selector).ok_or(ErrorKind::NotFound)?;
+        let record = records
+            .iter()
+            .find(|r| r.revision.version == revision.version)
+            .ok_or(ErrorKind::NotFound)?;
+
+        Ok((
+            revision,
+            restore(&mut self.lookup_table, &record.statements),
+        ))
+    }
+
+    fn enqueue_event(
+        &mut self,
+        iri: &str,
+        operation: Operation,
+        delta: Option<String>,
+    ) -> Result<(), ErrorKind> {
+        let mut updated_at = None;
+        for entry in self.storage.documents.scan_prefix(iri_prefix(iri)) {
+            let (_, value) = entry?;
+            let stored: StoredDocument =
+                serde_json::from_slice(&value).map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+            updated_at = updated_at.max(Some(stored.updated_at));
+        }
+        let event = OutboxEvent {
+            iri: iri.to_string(),
+            operation,
+            updated_at: updated_at.unwrap_or_else(|| Utc::now().naive_utc()),
+            delta,
+        };
+
+        let id = self.storage.db.generate_id()?;
+        let value = serde_json::to_vec(&event).map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+        self.storage.outbox.insert(id.to_be_bytes(), value)?;
+
+        Ok(())
+    }
+
+    fn pending_events(&mut self, limit: usize) -> Result<Vec<(i64, OutboxEvent)>, ErrorKind> {
+        let mut events = vec![];
+        for entry in self.storage.outbox.iter().take(limit) {
+            let (key, value) = entry?;
+            let id = u64::from_be_bytes(
+                key.as_ref()
+                    .try_into()
+                    .map_err(|_| ErrorKind::Unexpected("Invalid event id".into()))?,
+            );
+            let event =
+                serde_json::from_slice(&value).map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+            events.push((id as i64, event));
+        }
+
+        Ok(events)
+    }
+
+    fn remove_events(&mut self, ids: &[i64]) -> Result<(), ErrorKind> {
+        for id in ids {
+            self.storage.outbox.remove((*id as u64).to_be_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn transaction(
+        &mut self,
+        work: &mut dyn FnMut(&mut dyn StorageContext) -> Result<(), ErrorKind>,
+    ) -> Result<(), ErrorKind> {
+        // Not atomic, see the contract of `StorageContext::transaction`.
+        work(self)
+    }
+}
+
+impl From<sled::Error> for ErrorKind {
+    fn from(e: sled::Error) -> Self {
+        ErrorKind::Unexpected(e.to_string())
+    }
+}
+
+fn terms(s: &Statement) -> [u128; 6] {
+    [
+        s.subject,
+        s.predicate,
+        s.value,
+        s.datatype,
+        s.language,
+        s.graph,
+    ]
+}
+
+fn hash_bytes(hashes: &[u128]) -> Vec<u8> {
+    hashes
+        .iter()
+        .flat_map(|h| h.to_be_bytes().to_vec())
+        .collect()
+}
+
+fn doc_key(iri: &str, language: &str) -> Vec<u8> {
+    [iri.as_bytes(), &[0], language.as_bytes()].concat()
+}
+
+/// The prefix of the keys of the document in every language.
+fn iri_prefix(iri: &str) -> Vec<u8> {
+    [iri.as_bytes(), &[0]].concat()
+}
+
+fn revision_key(iri: &[u8], version: i64) -> Vec<u8> {
+    [iri, &[0], &version.to_be_bytes()].concat()
+}
+
+fn split_doc_key(key: &[u8]) -> (&[u8], &[u8]) {
+    match key.iter().position(|b| *b == 0) {
+        Some(i) => (&key[..i], &key[i + 1..]),
+        None => (key, &[]),
+    }
+}
+
+fn statement_key(doc_key: &[u8], s: &Statement) -> Vec<u8> {
+    [doc_key, &[0], &hash_bytes(&terms(s))].concat()
+}
+
+fn index_key(order: &[usize; 6], s: &Statement, doc_key: &[u8]) -> Vec<u8> {
+    let terms = terms(s);
+    let ordered: Vec<u128> = order.iter().map(|i| terms[*i]).collect();
+
+    [hash_bytes(&ordered).as_slice(), doc_key].concat()
+}
+
+fn decode_statement(order: &[usize; 6], bytes: &[u8]) -> Statement {
+    let mut terms = [0u128; 6];
+    for (n, i) in order.iter().enumerate() {
+        terms[*i] = u128::from_be_bytes(bytes[n * 16..(n + 1) * 16].try_into().unwrap());
+    }
+
+    Statement::new(terms[0], terms[1], terms[2], terms[3], terms[4], terms[5])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::storage::tests::{check_documents, check_outbox, check_revisions};
+    use crate::hashtuple::STRING_IRI;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    fn storage_with_history(max_versions: usize) -> SledStorage {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let retention = HistoryRetention {
+            max_versions,
+            max_age_days: None,
+        };
+
+        SledStorage::from_db(db, retention).unwrap()
+    }
+
+    fn storage() -> SledStorage {
+        storage_with_history(2)
+    }
+
+    #[test]
+    fn test_sled_documents() {
+        check_documents(&storage());
+    }
+
+    #[test]
+    fn test_sled_revisions() {
+        check_revisions(&storage());
+    }
+
+    #[test]
+    fn test_sled_outbox() {
+        check_outbox(&storage());
+    }
+
+    #[test]
+    fn test_sled_concurrent_updates() {
+        let storage = Arc::new(storage_with_history(10));
+        let start = Arc::new(Barrier::new(8));
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let storage = Arc::clone(&storage);
+                let start = Arc::clone(&start);
+                thread::spawn(move || {
+                    start.wait();
+                    let mut ctx = storage.open(Some(String::from("en")));
+                    let table = ctx.lookup_table();
+                    let statement = Statement::new(
+                        table.ensure_value("https://example.com/a"),
+                        table.ensure_value("http://schema.org/name"),
+                        table.ensure_value(&i.to_string()),
+                        table.ensure_value(STRING_IRI),
+                        table.ensure_value(""),
+                        table.ensure_value(""),
+                    );
+                    let mut add = |_: &LookupTable, existing: &HashModel| {
+                        let mut next = existing.clone();
+                        next.push(statement);
+                        next
+                    };
+                    ctx.update_document("https://example.com/a", &mut add)
+                        .unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let mut ctx = storage.open(None);
+        let (_, model) = ctx.get_document("https://example.com/a").unwrap();
+        assert_eq!(model.len(), 8);
+        assert_eq!(ctx.count_documents(), 1);
+
+        // Every update saw the result of the previous one.
+        let versions: Vec<i64> = ctx
+            .revisions("https://example.com/a")
+            .unwrap()
+            .iter()
+            .map(|r| r.version)
+            .collect();
+        assert_eq!(versions, (1..=8).collect::<Vec<i64>>());
+        let (_, model) = ctx
+            .get_revision("https://example.com/a", &RevisionSelector::Version(8))
+            .unwrap();
+        assert_eq!(model.len(), 8);
+    }
+}
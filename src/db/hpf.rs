@@ -14,18 +14,46 @@ use crate::db::schema::documents::dsl as documents;
 use crate::db::schema::objects::dsl as objects;
 use crate::db::schema::properties;
 use crate::db::schema::resources::dsl as resources;
+use crate::db::sql::{quote, NUMERIC_DATATYPES};
+use crate::db::storage::{Pattern, PatternPage};
 use crate::db::uu128::Uu128;
 use crate::errors::ErrorKind;
 use crate::hashtuple::{
-    HashModel, Statement, LANG_STRING_IRI, NAMED_NODE_IRI, OBJECT_IRI, PREDICATE_IRI, STRING_IRI,
-    SUBJECT_IRI,
+    HashModel, LookupTable, Statement, LANG_STRING_IRI, NAMED_NODE_IRI, OBJECT_IRI, PREDICATE_IRI,
+    STRING_IRI, SUBJECT_IRI,
 };
+use crate::rdf::sparql::XSD_INTEGER_IRI;
 use actix_web::Either;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use diesel::debug_query;
+use diesel::dsl::sql;
 use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::row::NamedRow;
+use diesel::sql_types::{Bool, Text};
 use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const DEFAULT_PAGE_SIZE: i64 = 500;
+const MAX_PAGE_SIZE: i64 = 100_000;
+/// The amount of properties loaded at once when executing a page.
+const BATCH_SIZE: i64 = 5_000;
+
+const HYDRA_FIRST_IRI: &str = "http://www.w3.org/ns/hydra/core#first";
+const HYDRA_NEXT_IRI: &str = "http://www.w3.org/ns/hydra/core#next";
+const HYDRA_PREVIOUS_IRI: &str = "http://www.w3.org/ns/hydra/core#previous";
+const HYDRA_TOTAL_ITEMS_IRI: &str = "http://www.w3.org/ns/hydra/core#totalItems";
+const HYDRA_ITEMS_PER_PAGE_IRI: &str = "http://www.w3.org/ns/hydra/core#itemsPerPage";
+const VOID_TRIPLES_IRI: &str = "http://rdfs.org/ns/void#triples";
+/// The amount of characters of values covered by the prefix index.
+const PREFIX_INDEX_LENGTH: usize = 256;
+const DATE_TIME_DATATYPES: [&str; 3] = [
+    "http://www.w3.org/2001/XMLSchema#dateTime",
+    "http://www.w3.org/2001/XMLSchema#dateTimeStamp",
+    "http://www.w3.org/2001/XMLSchema#date",
+];
+const SD_GRAPH_IRI: &str = "http://www.w3.org/ns/sparql-service-description#graph";
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct HPFQueryRequest {
@@ -40,11 +68,58 @@ pub(crate) struct HPFQueryRequest {
     #[serde(default)]
     language: Option<String>,
     #[serde(default)]
+    graph: Option<String>,
+    /// Matches values greater than the given one, numbers and dates are compared by their value.
+    #[serde(default)]
+    value_gt: Option<String>,
+    /// Matches values less than the given one, numbers and dates are compared by their value.
+    #[serde(default)]
+    value_lt: Option<String>,
+    #[serde(default)]
+    value_prefix: Option<String>,
+    #[serde(default)]
+    value_contains: Option<String>,
+    #[serde(default)]
     page: Option<i64>,
     #[serde(default)]
     page_size: Option<i64>,
 }
 
+impl HPFQueryRequest {
+    /// The parameters describing the fragment, to build the IRIs of its pages.
+    fn params(&self) -> BTreeMap<&'static str, String> {
+        let mut params = query_params(&[
+            ("subject", &self.subject),
+            ("predicate", &self.predicate),
+            ("value", &self.value),
+            ("datatype", &self.datatype),
+            ("language", &self.language),
+            ("value_gt", &self.value_gt),
+            ("value_lt", &self.value_lt),
+            ("value_prefix", &self.value_prefix),
+            ("value_contains", &self.value_contains),
+        ]);
+        // An empty graph selects the default graph.
+        if let Some(graph) = &self.graph {
+            params.insert("graph", graph.clone());
+        }
+
+        params
+    }
+
+    /// The terms of the pattern by their position.
+    pub fn terms(&self) -> [(Position, &Option<String>); 6] {
+        [
+            (Position::Subject, &self.subject),
+            (Position::Predicate, &self.predicate),
+            (Position::Value, &self.value),
+            (Position::Datatype, &self.datatype),
+            (Position::Language, &self.language),
+            (Position::Graph, &self.graph),
+        ]
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct TPFQueryRequest {
     #[serde(default)]
@@ -59,6 +134,89 @@ pub(crate) struct TPFQueryRequest {
     page_size: Option<i64>,
 }
 
+impl TPFQueryRequest {
+    fn params(&self) -> BTreeMap<&'static str, String> {
+        query_params(&[
+            ("subject", &self.subject),
+            ("predicate", &self.predicate),
+            ("object", &self.object),
+        ])
+    }
+
+    pub fn pattern(&self) -> Pattern {
+        Pattern {
+            subject: self.subject.clone(),
+            predicate: self.predicate.clone(),
+            object: self.object.clone(),
+            document: None,
+            page: self.page.unwrap_or(0).max(0),
+            page_size: self
+                .page_size
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .abs()
+                .min(MAX_PAGE_SIZE)
+                .max(1),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct QPFQueryRequest {
+    #[serde(default)]
+    subject: Option<String>,
+    #[serde(default)]
+    predicate: Option<String>,
+    #[serde(default)]
+    object: Option<String>,
+    /// The IRI of the document the statement belongs to.
+    #[serde(default)]
+    graph: Option<String>,
+    #[serde(default)]
+    page: Option<i64>,
+    #[serde(default)]
+    page_size: Option<i64>,
+}
+
+impl QPFQueryRequest {
+    fn params(&self) -> BTreeMap<&'static str, String> {
+        query_params(&[
+            ("subject", &self.subject),
+            ("predicate", &self.predicate),
+            ("object", &self.object),
+            ("graph", &self.graph),
+        ])
+    }
+}
+
+/// The endpoint a fragment is requested from, which the IRIs of its pages point to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Endpoint {
+    Hpf,
+    Tpf,
+    Qpf,
+}
+
+impl Endpoint {
+    fn path(self) -> &'static str {
+        match self {
+            Endpoint::Hpf => "/hpf",
+            Endpoint::Tpf => "/tpf",
+            Endpoint::Qpf => "/qpf",
+        }
+    }
+}
+
+/// The position of a term in a hex pattern.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Position {
+    Subject,
+    Predicate,
+    Value,
+    Datatype,
+    Language,
+    Graph,
+}
+
 pub(crate) struct Variable {
     /// Unclear if and how this should be conveyed in the response
     _name: String,
@@ -67,6 +225,37 @@ pub(crate) struct Variable {
 pub(crate) type VarOrIRI = Either<Variable, String>;
 pub(crate) type VarOrId = Either<Variable, i32>;
 pub(crate) type VarOrHash = Either<Variable, Uu128>;
+/// A named graph hash, or `None` for the default graph.
+pub(crate) type VarOrGraph = Either<Variable, Option<Uu128>>;
+
+/// The output of `EXPLAIN (FORMAT JSON)`.
+struct QueryPlan {
+    plan: String,
+}
+
+impl diesel::deserialize::QueryableByName<Pg> for QueryPlan {
+    fn build<R: NamedRow<Pg>>(row: &R) -> diesel::deserialize::Result<Self> {
+        Ok(QueryPlan {
+            plan: row.get::<Text, String>("QUERY PLAN")?,
+        })
+    }
+}
+
+/// How values are compared in range filters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ValueKind {
+    Numeric,
+    DateTime,
+    Text,
+}
+
+/// A filter on the value of the object which can't be expressed with its hash.
+pub(crate) struct ValueFilter {
+    /// The condition on the `value` column of `objects`.
+    condition: String,
+    /// The datatypes the filter is limited to, when not constrained by the pattern itself.
+    datatype_ids: Option<Vec<i32>>,
+}
 
 pub(crate) struct HPFQuery {
     page_size: i64,
@@ -78,6 +267,14 @@ pub(crate) struct HPFQuery {
     pub value: VarOrHash,
     pub datatype: VarOrId,
     pub language: VarOrId,
+    /// The named graph, the default graph is matched with an empty string.
+    pub graph: VarOrGraph,
+    /// The document owning the statements, which is used as their graph in quad pattern fragments.
+    pub document: VarOrIRI,
+    pub value_filters: Vec<ValueFilter>,
+    endpoint: Endpoint,
+    /// The non-paging parameters of the request, to build the IRIs of the pages.
+    params: BTreeMap<&'static str, String>,
 }
 
 impl HPFQuery {
@@ -90,9 +287,16 @@ impl HPFQuery {
         let value = parse_value(&mut db_ctx, &request.value)?;
         let datatype = parse_datatype(&mut db_ctx, &request.datatype)?;
         let language = parse_language(&mut db_ctx, &request.language)?;
+        let graph = parse_graph(db_ctx, &request.graph);
+        let value_filters = parse_value_filters(db_ctx, request, &datatype)?;
 
         Ok(HPFQuery {
-            page_size: request.page_size.unwrap_or(500).abs().min(100_000).max(1),
+            page_size: request
+                .page_size
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .abs()
+                .min(MAX_PAGE_SIZE)
+                .max(1),
             from: request.page.unwrap_or(0).max(0),
 
             subject,
@@ -100,6 +304,13 @@ impl HPFQuery {
             value,
             datatype,
             language,
+            graph,
+            document: Either::A(Variable {
+                _name: "anonymous".into(),
+            }),
+            value_filters,
+            endpoint: Endpoint::Hpf,
+            params: request.params(),
         })
     }
 
@@ -110,9 +321,15 @@ impl HPFQuery {
         let subject = parse_subject(&request.subject);
         let predicate = parse_predicate(&mut db_ctx, &request.predicate)?;
         let (value, datatype, language) = parse_object(&mut db_ctx, &request.object)?;
+        let graph = parse_graph(db_ctx, &None);
 
         Ok(HPFQuery {
-            page_size: request.page_size.unwrap_or(500).abs().min(100_000).max(1),
+            page_size: request
+                .page_size
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .abs()
+                .min(MAX_PAGE_SIZE)
+                .max(1),
             from: request.page.unwrap_or(0).max(0),
 
             subject,
@@ -120,115 +337,256 @@ impl HPFQuery {
             value,
             datatype,
             language,
+            graph,
+            document: Either::A(Variable {
+                _name: "anonymous".into(),
+            }),
+            value_filters: vec![],
+            endpoint: Endpoint::Tpf,
+            params: request.params(),
         })
     }
 
-    pub fn header(&self, mut db_ctx: &mut DbContext, origin: &str) -> Result<HashModel, ErrorKind> {
-        let dataset = db_ctx
-            .lookup_table
-            .ensure_value(&format!("{}#dataset", origin));
-        let template_iri = db_ctx
-            .lookup_table
-            .ensure_value(&format!("{}/tpf#template", origin));
-        let named_node = db_ctx.lookup_table.ensure_value(NAMED_NODE_IRI);
-        let empty = db_ctx.lookup_table.ensure_value("");
-
-        let dataset = vec![
-            Statement::new(
-                dataset,
-                db_ctx
-                    .lookup_table
-                    .ensure_value("http://rdfs.org/ns/void#subset"),
-                db_ctx
-                    .lookup_table
-                    .ensure_value(&self.collection_iri(&db_ctx, origin)),
-                named_node,
-                empty,
-                empty,
-            ),
-            Statement::new(
-                dataset,
-                db_ctx
-                    .lookup_table
-                    .ensure_value("http://www.w3.org/ns/hydra/core#search"),
-                template_iri,
-                named_node,
-                empty,
-                empty,
-            ),
-        ];
+    pub fn parse_qpf(
+        mut db_ctx: &mut DbContext,
+        request: &QPFQueryRequest,
+    ) -> Result<HPFQuery, ErrorKind> {
+        let subject = parse_subject(&request.subject);
+        let predicate = parse_predicate(&mut db_ctx, &request.predicate)?;
+        let (value, datatype, language) = parse_object(&mut db_ctx, &request.object)?;
+        let document = parse_subject(&request.graph);
 
-        let model = [
-            dataset.as_slice(),
-            template_statements(&mut db_ctx, origin).as_slice(),
-        ]
-        .concat();
+        Ok(HPFQuery {
+            page_size: request
+                .page_size
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .abs()
+                .min(MAX_PAGE_SIZE)
+                .max(1),
+            from: request.page.unwrap_or(0).max(0),
 
-        Ok(model)
+            subject,
+            predicate,
+            value,
+            datatype,
+            language,
+            graph: Either::A(Variable {
+                _name: "anonymous".into(),
+            }),
+            document,
+            value_filters: vec![],
+            endpoint: Endpoint::Qpf,
+            params: request.params(),
+        })
     }
 
-    pub fn collection_iri(&self, db_ctx: &DbContext, origin: &str) -> String {
-        let base = format!("{}/tpf", origin);
-        let mut map = HashMap::new();
+    /// Parses a triple pattern, where the pattern document is the document of the statements.
+    pub fn from_pattern(db_ctx: &mut DbContext, pattern: &Pattern) -> Result<HPFQuery, ErrorKind> {
+        let subject = parse_subject(&pattern.subject);
+        let predicate = parse_predicate(db_ctx, &pattern.predicate)?;
+        let (value, datatype, language) = parse_object(db_ctx, &pattern.object)?;
+        let document = parse_subject(&pattern.document);
 
-        if let Either::B(iri) = &self.subject {
-            map.insert("subject", iri.to_string());
-        }
-        if let Either::B(predicate_id) = self.predicate {
-            map.insert(
-                "predicate",
-                db_ctx
-                    .property_map
-                    .get_by_right(&predicate_id)
-                    .unwrap()
-                    .to_string(),
-            );
+        Ok(HPFQuery {
+            page_size: pattern.page_size,
+            from: pattern.page,
+
+            subject,
+            predicate,
+            value,
+            datatype,
+            language,
+            graph: Either::A(Variable {
+                _name: "anonymous".into(),
+            }),
+            document,
+            value_filters: vec![],
+            endpoint: Endpoint::Tpf,
+            params: pattern_params(pattern),
+        })
+    }
+
+    /// The dataset and fragment metadata, `matched` and `next` are the statement count and the
+    /// cursor of the next page returned by `execute`.
+    pub fn header(
+        &self,
+        db_ctx: &mut DbContext,
+        origin: &str,
+        matched: usize,
+        next: Option<i64>,
+    ) -> Result<HashModel, ErrorKind> {
+        let count = self.count(db_ctx, matched, next)?;
+        let previous = self.previous(db_ctx)?;
+
+        let mut links = vec![(HYDRA_FIRST_IRI, self.page_iri(origin, 0))];
+        if let Some(next) = next {
+            links.push((HYDRA_NEXT_IRI, self.page_iri(origin, next)));
         }
-        if let Either::B(object_id) = self.value {
-            let t = db_ctx.lookup_table.get_by_hash(u128::from(object_id));
-            if t.is_none() {
-                panic!("Value not in map")
-            }
-            map.insert("object", t.unwrap().to_string());
+        if let Some(previous) = previous {
+            links.push((HYDRA_PREVIOUS_IRI, self.page_iri(origin, previous)));
         }
+        let fragment_iri = self.page_iri(origin, self.from);
+
+        Ok(fragment_metadata(
+            &mut db_ctx.lookup_table,
+            origin,
+            self.endpoint,
+            &fragment_iri,
+            links,
+            (count, self.page_size),
+        ))
+    }
 
-        if map.len() > 0 {
-            format!("{}?{}", base, serde_qs::to_string(&map).unwrap())
-        } else {
-            base
-        }
+    /// The requested page with its paging controls.
+    pub fn page(&self, db_ctx: &mut DbContext) -> Result<PatternPage, ErrorKind> {
+        let (statements, next) = self.execute(db_ctx)?;
+
+        Ok(PatternPage {
+            count: self.count(db_ctx, statements.len(), next)?,
+            previous: self.previous(db_ctx)?,
+            statements,
+            next,
+        })
     }
 
-    pub fn execute(&self, mut db_ctx: &mut DbContext) -> Result<HashModel, ErrorKind> {
+    /// The IRI of the page of this fragment starting after property id `page`.
+    pub fn page_iri(&self, origin: &str, page: i64) -> String {
+        page_iri(origin, self.endpoint, &self.params, page, self.page_size)
+    }
+
+    fn quads(&self) -> bool {
+        self.endpoint == Endpoint::Qpf
+    }
+
+    /// The statements of the requested page, with the cursor of the next page if there may be one.
+    pub fn execute(&self, db_ctx: &mut DbContext) -> Result<(HashModel, Option<i64>), ErrorKind> {
+        let mut statements = vec![];
+        let (_, next) = self.execute_batched(db_ctx, |batch, _| {
+            statements.extend(batch);
+            Ok(())
+        })?;
+
+        Ok((statements, next))
+    }
+
+    /// Loads the requested page in batches, passing the statements of each batch to `sink` as
+    /// soon as they're read. Returns the amount of statements and the cursor of the next page.
+    pub fn execute_batched<F>(
+        &self,
+        db_ctx: &mut DbContext,
+        mut sink: F,
+    ) -> Result<(usize, Option<i64>), ErrorKind>
+    where
+        F: FnMut(HashModel, &mut DbContext) -> Result<(), ErrorKind>,
+    {
         use properties::dsl;
 
-        let conn = db_ctx.get_conn();
         debug!(target: "apex", "TPF: Retrieving max {} triples from id {}", self.page_size, self.from);
-        let mut q = dsl::properties
-            .filter(dsl::id.gt(self.from))
-            .limit(self.page_size)
-            .into_boxed();
 
-        match &self.subject {
-            Either::A(_var) => (),
-            Either::B(val) => {
-                let t = resources::resources
-                    .inner_join(documents::documents)
-                    .filter(documents::iri.eq(val))
-                    .load::<(Resource, Document)>(&conn)
-                    .unwrap();
-
-                let mut resource_ids = HashSet::new();
+        let mut cursor = self.from;
+        let mut matched = 0;
+        loop {
+            let limit = (self.page_size - matched as i64).min(BATCH_SIZE);
+            let q = self
+                .filtered(db_ctx)
+                .filter(dsl::id.gt(cursor))
+                .order(dsl::id)
+                .limit(limit);
+
+            if cfg!(debug_assertions) {
+                let sql = debug_query::<Pg, _>(&q).to_string();
+                debug!(target: "apex", "Executing H/TPF query: {}", sql);
+            }
+            // The connection is returned to the pool before `sink` waits for the client.
+            let matches = q.load::<Property>(&*db_ctx.get_conn()).unwrap();
+            matched += matches.len();
+            if let Some(last) = matches.last() {
+                cursor = last.id;
+            }
 
-                t.iter().for_each(|(resource, doc)| {
-                    db_ctx.lookup_table.ensure_value(&doc.iri);
-                    resource_ids.insert(resource.id);
-                });
+            let mut statements = properties_to_statements(db_ctx, &matches);
+            if self.quads() {
+                set_document_graphs(db_ctx, &matches, &mut statements)?;
+            }
+            sink(statements, db_ctx)?;
 
-                q = q.filter(dsl::resource_id.eq_any(resource_ids))
+            if (matches.len() as i64) < limit || matched as i64 >= self.page_size {
+                break;
             }
+        }
+
+        let next = if matched as i64 == self.page_size {
+            Some(cursor)
+        } else {
+            None
         };
 
+        Ok((matched, next))
+    }
+
+    /// The conditions on the properties table for the constants of the pattern, aliased as
+    /// `p{alias}`. The subject is not included since it needs a lookup.
+    pub(crate) fn constant_filters(&self, alias: usize) -> Vec<String> {
+        let mut filters = vec![];
+
+        if let Either::B(id) = self.predicate {
+            filters.push(format!("p{}.predicate_id = {}", alias, id));
+        }
+        if let Either::B(hash) = self.value {
+            filters.push(format!("p{}.object_id = '{}'", alias, hash));
+        }
+        if let Either::B(id) = self.datatype {
+            filters.push(format!("p{}.datatype_id = {}", alias, id));
+        }
+        if let Either::B(id) = self.language {
+            filters.push(format!("p{}.language_id = {}", alias, id));
+        }
+        match self.graph {
+            Either::B(Some(hash)) => filters.push(format!("p{}.graph_id = '{}'", alias, hash)),
+            Either::B(None) => filters.push(format!("p{}.graph_id IS NULL", alias)),
+            Either::A(_) => (),
+        }
+        filters.extend(self.value_conditions(&format!("p{}", alias)));
+
+        filters
+    }
+
+    /// The conditions for the value filters on the properties table named `table`.
+    fn value_conditions(&self, table: &str) -> Vec<String> {
+        let mut conditions = vec![];
+
+        for filter in &self.value_filters {
+            conditions.push(format!(
+                "{}.object_id IN (SELECT hash FROM objects WHERE {})",
+                table, filter.condition
+            ));
+            match &filter.datatype_ids {
+                Some(ids) if ids.is_empty() => conditions.push(String::from("FALSE")),
+                Some(ids) => conditions.push(format!(
+                    "{}.datatype_id IN ({})",
+                    table,
+                    ids.iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )),
+                None => (),
+            }
+        }
+
+        conditions
+    }
+
+    /// The properties matching the pattern, in any page.
+    fn filtered(&self, db_ctx: &mut DbContext) -> properties::BoxedQuery<'_, Pg> {
+        use properties::dsl;
+
+        let mut q = dsl::properties.into_boxed();
+
+        for resource_ids in self.resource_constraints(db_ctx) {
+            q = q.filter(dsl::resource_id.eq_any(resource_ids))
+        }
+
         match &self.predicate {
             Either::A(_var) => (),
             Either::B(val) => {
@@ -257,40 +615,192 @@ impl HPFQuery {
             }
         };
 
-        if cfg!(debug_assertions) {
-            let sql = debug_query::<Pg, _>(&q).to_string();
-            debug!(target: "apex", "Executing H/TPF query: {}", sql);
+        match &self.graph {
+            Either::A(_var) => (),
+            Either::B(Some(val)) => {
+                q = q.filter(dsl::graph_id.eq(val));
+            }
+            Either::B(None) => {
+                q = q.filter(dsl::graph_id.is_null());
+            }
+        };
+
+        for condition in self.value_conditions("properties") {
+            q = q.filter(sql::<Bool>(&condition));
         }
-        let matches = q.load::<Property>(&conn).unwrap();
 
-        ensure_subjects(&mut db_ctx, &matches);
-        ensure_objects(&mut db_ctx, &matches);
+        q
+    }
 
-        let statements = matches
+    /// The ids of the resources in the subject and graph documents, for those which are constant.
+    fn resource_constraints(&self, db_ctx: &mut DbContext) -> Vec<Vec<i64>> {
+        [&self.subject, &self.document]
             .iter()
-            .map(|p: &Property| Statement {
-                subject: db_ctx
-                    .lookup_table
-                    .calculate_hash(db_ctx.resource_map.get_by_right(&p.resource_id).unwrap()),
-                predicate: db_ctx
-                    .lookup_table
-                    .ensure_value(db_ctx.property_map.get_by_right(&p.predicate_id).unwrap()),
-                value: u128::from(p.object_id.unwrap()),
-                datatype: db_ctx.lookup_table.ensure_value(
-                    db_ctx
-                        .datatype_map
-                        .get_by_right(&p.datatype_id)
-                        .expect("Datatype id not in map"),
-                ),
-                language: db_ctx
-                    .lookup_table
-                    .ensure_value(db_ctx.property_map.get_by_right(&p.predicate_id).unwrap()),
-                graph: db_ctx.lookup_table.ensure_value(""),
+            .filter_map(|term| match term {
+                Either::A(_var) => None,
+                Either::B(iri) => Some(document_resource_ids(db_ctx, iri)),
             })
-            .collect();
+            .collect()
+    }
+
+    /// The exact count when the whole fragment fits the page, otherwise an estimate.
+    fn count(
+        &self,
+        db_ctx: &mut DbContext,
+        matched: usize,
+        next: Option<i64>,
+    ) -> Result<i64, ErrorKind> {
+        if self.from == 0 && next.is_none() {
+            Ok(matched as i64)
+        } else {
+            Ok(self.estimate_count(db_ctx)?.max(matched as i64))
+        }
+    }
+
+    /// The cursor of the previous page, `None` on the first page.
+    fn previous(&self, db_ctx: &mut DbContext) -> Result<Option<i64>, ErrorKind> {
+        use properties::dsl;
+
+        if self.from == 0 {
+            return Ok(None);
+        }
+
+        let conn = db_ctx.get_conn();
+        let before = self
+            .filtered(db_ctx)
+            .filter(dsl::id.le(self.from))
+            .order(dsl::id.desc())
+            .offset(self.page_size)
+            .first::<Property>(&*conn)
+            .optional()
+            .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+        Ok(Some(before.map_or(0, |p| p.id)))
+    }
+
+    /// The amount of matching properties as estimated by the query planner.
+    fn estimate_count(&self, db_ctx: &mut DbContext) -> Result<i64, ErrorKind> {
+        let mut filters = self.constant_filters(0);
+        for resource_ids in self.resource_constraints(db_ctx) {
+            if resource_ids.is_empty() {
+                return Ok(0);
+            }
+            let ids = resource_ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            filters.push(format!("p0.resource_id IN ({})", ids));
+        }
+        let filters = if filters.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", filters.join(" AND "))
+        };
+
+        let plan = diesel::sql_query(format!(
+            "EXPLAIN (FORMAT JSON) SELECT p0.id FROM properties p0{}",
+            filters
+        ))
+        .get_result::<QueryPlan>(&*db_ctx.get_conn())
+        .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+        let plan: Value =
+            serde_json::from_str(&plan.plan).map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+        Ok(plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or(0.0) as i64)
+    }
+}
+
+/// The ids of the resources in the document with `iri`.
+fn document_resource_ids(db_ctx: &mut DbContext, iri: &str) -> Vec<i64> {
+    let t = resources::resources
+        .inner_join(documents::documents)
+        .filter(documents::iri.eq(iri))
+        .load::<(Resource, Document)>(&*db_ctx.get_conn())
+        .unwrap();
+
+    let mut resource_ids = HashSet::new();
+    t.iter().for_each(|(resource, doc)| {
+        db_ctx.lookup_table.ensure_value(&doc.iri);
+        resource_ids.insert(resource.id);
+    });
 
-        Ok(statements)
+    resource_ids.into_iter().collect()
+}
+
+/// Sets the graph of the statements to the IRI of the document owning their property.
+fn set_document_graphs(
+    db_ctx: &mut DbContext,
+    matches: &[Property],
+    statements: &mut HashModel,
+) -> Result<(), ErrorKind> {
+    let resource_ids = matches
+        .iter()
+        .map(|p| p.resource_id)
+        .collect::<HashSet<i64>>()
+        .into_iter()
+        .collect::<Vec<i64>>();
+
+    let mut documents = HashMap::new();
+    for chunk in resource_ids.chunks(MAX_PROPERTY_INSERT_SIZE) {
+        let found = resources::resources
+            .inner_join(documents::documents)
+            .filter(resources::id.eq_any(chunk))
+            .select((resources::id, documents::iri))
+            .load::<(i64, String)>(&*db_ctx.get_conn())
+            .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+        for (resource_id, iri) in found {
+            documents.insert(resource_id, db_ctx.lookup_table.ensure_value(&iri));
+        }
     }
+
+    for (property, statement) in matches.iter().zip(statements.iter_mut()) {
+        if let Some(graph) = documents.get(&property.resource_id) {
+            statement.graph = *graph;
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts stored properties to statements, adding their values to the lookup table.
+pub(crate) fn properties_to_statements(
+    mut db_ctx: &mut DbContext,
+    matches: &Vec<Property>,
+) -> HashModel {
+    ensure_subjects(&mut db_ctx, matches);
+    ensure_objects(&mut db_ctx, matches);
+
+    let empty = db_ctx.lookup_table.ensure_value("");
+    matches
+        .iter()
+        .map(|p: &Property| Statement {
+            subject: db_ctx
+                .lookup_table
+                .calculate_hash(db_ctx.resource_map.get_by_right(&p.resource_id).unwrap()),
+            predicate: db_ctx
+                .lookup_table
+                .ensure_value(db_ctx.property_map.get_by_right(&p.predicate_id).unwrap()),
+            value: u128::from(p.object_id.unwrap()),
+            datatype: db_ctx.lookup_table.ensure_value(
+                db_ctx
+                    .datatype_map
+                    .get_by_right(&p.datatype_id)
+                    .expect("Datatype id not in map"),
+            ),
+            language: match p.language_id {
+                Some(id) => db_ctx.lookup_table.ensure_value(
+                    db_ctx
+                        .language_map
+                        .get_by_right(&id)
+                        .expect("Language id not in map"),
+                ),
+                None => empty,
+            },
+            graph: p.graph_id.map_or(empty, u128::from),
+        })
+        .collect()
 }
 
 fn ensure_subjects(db_ctx: &mut DbContext, matches: &Vec<Property>) {
@@ -308,7 +818,7 @@ fn ensure_subjects(db_ctx: &mut DbContext, matches: &Vec<Property>) {
         .for_each(|chunk| {
             let found_subjects = resources::resources
                 .filter(resources::id.eq_any(chunk))
-                .get_results::<Resource>(&db_ctx.get_conn())
+                .get_results::<Resource>(&*db_ctx.get_conn())
                 .unwrap();
 
             for o in found_subjects {
@@ -328,6 +838,9 @@ fn ensure_objects(db_ctx: &mut DbContext, matches: &Vec<Property>) {
 
     matches.iter().for_each(|p| {
         object_ids.insert(p.object_id.unwrap());
+        if let Some(graph_id) = p.graph_id {
+            object_ids.insert(graph_id);
+        }
     });
 
     debug!(target: "apex", "Ensuring {} objects", object_ids.len());
@@ -338,7 +851,7 @@ fn ensure_objects(db_ctx: &mut DbContext, matches: &Vec<Property>) {
         .for_each(|chunk| {
             let found_objects = objects::objects
                 .filter(objects::hash.eq_any(chunk))
-                .get_results::<Object>(&db_ctx.get_conn())
+                .get_results::<Object>(&*db_ctx.get_conn())
                 .unwrap();
 
             for o in found_objects {
@@ -390,25 +903,190 @@ fn parse_value(db_ctx: &mut DbContext, s: &Option<String>) -> Result<VarOrHash,
             _name: "anonymous".into(),
         }));
     }
+    if let Some(var) = parse_variable(s.as_ref().unwrap()) {
+        return Ok(Either::A(var));
+    }
 
     let value = Uu128::from(db_ctx.lookup_table.ensure_value(&s.as_ref().unwrap()));
 
     Ok(Either::B(value))
 }
 
+/// The variable named by a `?` prefixed term.
+fn parse_variable(s: &str) -> Option<Variable> {
+    s.strip_prefix('?').map(|name| Variable {
+        _name: String::from(name),
+    })
+}
+
+fn parse_value_filters(
+    db_ctx: &DbContext,
+    request: &HPFQueryRequest,
+    datatype: &VarOrId,
+) -> Result<Vec<ValueFilter>, ErrorKind> {
+    let mut filters = vec![];
+
+    let ranges = [
+        ("value_gt", ">", &request.value_gt),
+        ("value_lt", "<", &request.value_lt),
+    ];
+    for (param, operator, bound) in ranges.iter() {
+        let bound = match bound {
+            Some(bound) if !bound.is_empty() => bound,
+            _ => continue,
+        };
+        let (kind, datatype_ids) = match datatype {
+            Either::B(id) => {
+                let iri = db_ctx.datatype_map.get_by_right(id).map(String::as_str);
+                (datatype_value_kind(iri.unwrap_or("")), None)
+            }
+            Either::A(_) => {
+                let kind = infer_value_kind(bound);
+                (kind, kind_datatype_ids(db_ctx, kind))
+            }
+        };
+        let condition = match kind {
+            ValueKind::Numeric => {
+                if !is_number(bound) {
+                    return Err(ErrorKind::ParserError(format!("{} is not a number", param)));
+                }
+                format!(
+                    "apex_try_numeric(value) {} {}::numeric",
+                    operator,
+                    quote(bound.trim())
+                )
+            }
+            ValueKind::DateTime => {
+                if infer_value_kind(bound) != ValueKind::DateTime {
+                    return Err(ErrorKind::ParserError(format!("{} is not a date", param)));
+                }
+                format!(
+                    "apex_try_timestamptz(value) {} apex_try_timestamptz({})",
+                    operator,
+                    quote(bound)
+                )
+            }
+            ValueKind::Text => format!("value {} {}", operator, quote(bound)),
+        };
+
+        filters.push(ValueFilter {
+            condition,
+            datatype_ids,
+        });
+    }
+
+    if let Some(prefix) = request.value_prefix.as_ref().filter(|p| !p.is_empty()) {
+        let length = prefix.chars().count();
+        filters.push(ValueFilter {
+            // The first condition can use the index on the start of the value.
+            condition: format!(
+                "left(value, {}) LIKE {} AND left(value, {}) = {}",
+                PREFIX_INDEX_LENGTH,
+                quote(&format!(
+                    "{}%",
+                    escape_like(&prefix.chars().take(PREFIX_INDEX_LENGTH).collect::<String>())
+                )),
+                length,
+                quote(prefix)
+            ),
+            datatype_ids: None,
+        });
+    }
+
+    if let Some(substring) = request.value_contains.as_ref().filter(|p| !p.is_empty()) {
+        filters.push(ValueFilter {
+            condition: format!(
+                "value LIKE {}",
+                quote(&format!("%{}%", escape_like(substring)))
+            ),
+            datatype_ids: None,
+        });
+    }
+
+    Ok(filters)
+}
+
+fn datatype_value_kind(datatype: &str) -> ValueKind {
+    if NUMERIC_DATATYPES.contains(&datatype) {
+        ValueKind::Numeric
+    } else if DATE_TIME_DATATYPES.contains(&datatype) {
+        ValueKind::DateTime
+    } else {
+        ValueKind::Text
+    }
+}
+
+/// The kind of comparison for a bound when the datatype isn't given.
+fn infer_value_kind(bound: &str) -> ValueKind {
+    if is_number(bound) {
+        ValueKind::Numeric
+    } else if DateTime::parse_from_rfc3339(bound).is_ok()
+        || NaiveDateTime::parse_from_str(bound, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+        || NaiveDate::parse_from_str(bound, "%Y-%m-%d").is_ok()
+    {
+        ValueKind::DateTime
+    } else {
+        ValueKind::Text
+    }
+}
+
+/// The ids of the known datatypes of `kind`, `None` for text which isn't limited to a datatype.
+fn kind_datatype_ids(db_ctx: &DbContext, kind: ValueKind) -> Option<Vec<i32>> {
+    let datatypes: &[&str] = match kind {
+        ValueKind::Numeric => &NUMERIC_DATATYPES,
+        ValueKind::DateTime => &DATE_TIME_DATATYPES,
+        ValueKind::Text => return None,
+    };
+
+    Some(
+        datatypes
+            .iter()
+            .filter_map(|iri| db_ctx.datatype_map.get_by_left(&iri.to_string()))
+            .cloned()
+            .collect(),
+    )
+}
+
+fn is_number(value: &str) -> bool {
+    matches!(value.trim().parse::<f64>(), Ok(number) if number.is_finite())
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn parse_graph(db_ctx: &mut DbContext, s: &Option<String>) -> VarOrGraph {
+    match s {
+        None => Either::A(Variable {
+            _name: "anonymous".into(),
+        }),
+        Some(s) => match s.get(..1) {
+            None | Some("") => Either::B(None),
+            Some("?") => Either::A(Variable {
+                _name: String::from(&s[1..]),
+            }),
+            Some(_) => Either::B(Some(Uu128::from(db_ctx.lookup_table.ensure_value(s)))),
+        },
+    }
+}
+
 fn parse_datatype(db_ctx: &DbContext, s: &Option<String>) -> Result<VarOrId, ErrorKind> {
     if s.is_none() || s.as_ref().unwrap() == "" {
         return Ok(Either::A(Variable {
             _name: "anonymous".into(),
         }));
     }
+    if let Some(var) = parse_variable(s.as_ref().unwrap()) {
+        return Ok(Either::A(var));
+    }
 
-    let datatype_id = *db_ctx
-        .datatype_map
-        .get_by_left(s.as_ref().unwrap())
-        .unwrap();
-
-    Ok(Either::B(datatype_id))
+    match db_ctx.datatype_map.get_by_left(s.as_ref().unwrap()) {
+        Some(id) => Ok(Either::B(*id)),
+        None => Err(ErrorKind::NoResources),
+    }
 }
 
 fn parse_language(db_ctx: &DbContext, s: &Option<String>) -> Result<VarOrId, ErrorKind> {
@@ -417,13 +1095,14 @@ fn parse_language(db_ctx: &DbContext, s: &Option<String>) -> Result<VarOrId, Err
             _name: "anonymous".into(),
         }));
     }
+    if let Some(var) = parse_variable(s.as_ref().unwrap()) {
+        return Ok(Either::A(var));
+    }
 
-    let language_id = *db_ctx
-        .language_map
-        .get_by_left(s.as_ref().unwrap())
-        .unwrap();
-
-    Ok(Either::B(language_id))
+    match db_ctx.language_map.get_by_left(s.as_ref().unwrap()) {
+        Some(id) => Ok(Either::B(*id)),
+        None => Err(ErrorKind::NoResources),
+    }
 }
 
 fn parse_object(
@@ -497,14 +1176,14 @@ fn parse_object(
         let datatype = match datatype_id {
             Some(id) => Either::B(*id),
             None => Either::A(Variable {
-                _name: String::from("anonymous"),
+                _name: "anonymous".into(),
             }),
         };
 
         let lang = match lang_id {
             Some(id) => Either::B(*id),
             None => Either::A(Variable {
-                _name: String::from("anonymous"),
+                _name: "anonymous".into(),
             }),
         };
 
@@ -521,123 +1200,311 @@ fn parse_object(
                     .unwrap(),
             ),
             Either::A(Variable {
-                _name: String::from("anonymous"),
+                _name: "anonymous".into(),
             }),
         ))
     }
 }
 
-fn template_statements(db_ctx: &mut DbContext, origin: &str) -> Vec<Statement> {
-    let named_node = db_ctx.lookup_table.get_by_value(NAMED_NODE_IRI.into());
-    let string_type = db_ctx.lookup_table.ensure_value(STRING_IRI);
-    let hydra_mapping = db_ctx
-        .lookup_table
-        .ensure_value("http://www.w3.org/ns/hydra/core#mapping");
-    let hydra_property = db_ctx
-        .lookup_table
-        .ensure_value("http://www.w3.org/ns/hydra/core#property");
-    let hydra_template = db_ctx
-        .lookup_table
-        .ensure_value("http://www.w3.org/ns/hydra/core#template");
-    let hydra_variable = db_ctx
-        .lookup_table
-        .ensure_value("http://www.w3.org/ns/hydra/core#variable");
-
-    let empty = db_ctx.lookup_table.get_by_value("".into());
-    let tmpl_base_iri = format!("{}/tpf#template", origin);
-    let template_iri = db_ctx.lookup_table.ensure_value(&tmpl_base_iri);
-    let template_subject_iri = db_ctx
-        .lookup_table
-        .ensure_value(&format!("{}_subject", tmpl_base_iri));
-    let template_predicate_iri = db_ctx
-        .lookup_table
-        .ensure_value(&format!("{}_predicate", tmpl_base_iri));
-    let template_object_iri = db_ctx
-        .lookup_table
-        .ensure_value(&format!("{}_object", tmpl_base_iri));
-
-    vec![
-        Statement::new(
-            template_iri,
-            hydra_template,
-            db_ctx
-                .lookup_table
-                .ensure_value(&format!("{}/tpf{{?subject,?predicate,?object}}", origin)),
-            string_type,
-            empty,
-            empty,
-        ),
-        Statement::new(
-            template_iri,
-            hydra_mapping,
-            template_subject_iri,
-            named_node,
-            empty,
-            empty,
-        ),
+/// The non-empty parameters among `terms`.
+fn query_params(terms: &[(&'static str, &Option<String>)]) -> BTreeMap<&'static str, String> {
+    terms
+        .iter()
+        .filter_map(|(name, term)| {
+            term.as_ref()
+                .filter(|t| !t.is_empty())
+                .map(|t| (*name, t.clone()))
+        })
+        .collect()
+}
+
+fn pattern_params(pattern: &Pattern) -> BTreeMap<&'static str, String> {
+    query_params(&[
+        ("subject", &pattern.subject),
+        ("predicate", &pattern.predicate),
+        ("object", &pattern.object),
+    ])
+}
+
+/// The IRI of the page starting after property id `page` of the fragment described by `params`.
+fn page_iri(
+    origin: &str,
+    endpoint: Endpoint,
+    params: &BTreeMap<&'static str, String>,
+    page: i64,
+    page_size: i64,
+) -> String {
+    let mut map = params.clone();
+    if page > 0 {
+        map.insert("page", page.to_string());
+    }
+    if page_size != DEFAULT_PAGE_SIZE {
+        map.insert("page_size", page_size.to_string());
+    }
+
+    let base = format!("{}{}", origin, endpoint.path());
+    if map.is_empty() {
+        base
+    } else {
+        format!("{}?{}", base, serde_qs::to_string(&map).unwrap())
+    }
+}
+
+/// The statements describing the dataset and a fragment, `counts` are the (estimated) amount of
+/// matching statements and the page size.
+fn fragment_metadata(
+    table: &mut LookupTable,
+    origin: &str,
+    endpoint: Endpoint,
+    fragment_iri: &str,
+    links: Vec<(&str, String)>,
+    counts: (i64, i64),
+) -> HashModel {
+    // Hydra can't map the decomposed object of hex patterns, so those are searched through TPF.
+    let (path, quads) = match endpoint {
+        Endpoint::Qpf => ("/qpf", true),
+        Endpoint::Hpf | Endpoint::Tpf => ("/tpf", false),
+    };
+    let dataset = table.ensure_value(&format!("{}#dataset", origin));
+    let template_iri = table.ensure_value(&format!("{}{}#template", origin, path));
+    let fragment = table.ensure_value(fragment_iri);
+    let named_node = table.ensure_value(NAMED_NODE_IRI);
+    let integer = table.ensure_value(XSD_INTEGER_IRI);
+    let empty = table.ensure_value("");
+
+    let mut model = vec![
         Statement::new(
-            template_iri,
-            hydra_mapping,
-            template_predicate_iri,
+            dataset,
+            table.ensure_value("http://rdfs.org/ns/void#subset"),
+            fragment,
             named_node,
             empty,
             empty,
         ),
         Statement::new(
+            dataset,
+            table.ensure_value("http://www.w3.org/ns/hydra/core#search"),
             template_iri,
-            hydra_mapping,
-            template_object_iri,
             named_node,
             empty,
             empty,
         ),
-        Statement::new(
-            template_subject_iri,
-            hydra_variable,
-            db_ctx.lookup_table.ensure_value("subject".into()),
-            string_type,
-            empty,
-            empty,
-        ),
-        Statement::new(
-            template_subject_iri,
-            hydra_property,
-            db_ctx.lookup_table.ensure_value(&SUBJECT_IRI),
+    ];
+
+    for (predicate, page_iri) in links {
+        model.push(Statement::new(
+            fragment,
+            table.ensure_value(predicate),
+            table.ensure_value(&page_iri),
             named_node,
             empty,
             empty,
-        ),
-        Statement::new(
-            template_predicate_iri,
-            hydra_variable,
-            db_ctx.lookup_table.ensure_value("predicate".into()),
-            string_type,
+        ));
+    }
+
+    let (count, page_size) = counts;
+    let counts = [
+        (VOID_TRIPLES_IRI, count),
+        (HYDRA_TOTAL_ITEMS_IRI, count),
+        (HYDRA_ITEMS_PER_PAGE_IRI, page_size),
+    ];
+    for (predicate, value) in counts.iter() {
+        model.push(Statement::new(
+            fragment,
+            table.ensure_value(predicate),
+            table.ensure_value(&value.to_string()),
+            integer,
             empty,
             empty,
-        ),
-        Statement::new(
-            template_predicate_iri,
-            hydra_property,
-            db_ctx.lookup_table.ensure_value(&PREDICATE_IRI),
+        ));
+    }
+
+    model.extend(template_statements(table, origin, path, quads));
+
+    model
+}
+
+/// The metadata of a triple pattern fragment served from a page of `match_pattern`.
+pub(crate) fn pattern_header(
+    table: &mut LookupTable,
+    origin: &str,
+    pattern: &Pattern,
+    page: &PatternPage,
+) -> HashModel {
+    let params = pattern_params(pattern);
+    let link = |cursor: i64| page_iri(origin, Endpoint::Tpf, &params, cursor, pattern.page_size);
+
+    let mut links = vec![(HYDRA_FIRST_IRI, link(0))];
+    if let Some(next) = page.next {
+        links.push((HYDRA_NEXT_IRI, link(next)));
+    }
+    if let Some(previous) = page.previous {
+        links.push((HYDRA_PREVIOUS_IRI, link(previous)));
+    }
+
+    fragment_metadata(
+        table,
+        origin,
+        Endpoint::Tpf,
+        &link(pattern.page),
+        links,
+        (page.count, pattern.page_size),
+    )
+}
+
+fn template_statements(
+    table: &mut LookupTable,
+    origin: &str,
+    path: &str,
+    quads: bool,
+) -> Vec<Statement> {
+    let named_node = table.get_by_value(NAMED_NODE_IRI.into());
+    let string_type = table.ensure_value(STRING_IRI);
+    let hydra_mapping = table.ensure_value("http://www.w3.org/ns/hydra/core#mapping");
+    let hydra_property = table.ensure_value("http://www.w3.org/ns/hydra/core#property");
+    let hydra_template = table.ensure_value("http://www.w3.org/ns/hydra/core#template");
+    let hydra_variable = table.ensure_value("http://www.w3.org/ns/hydra/core#variable");
+
+    let empty = table.get_by_value("".into());
+    let tmpl_base_iri = format!("{}{}#template", origin, path);
+    let template_iri = table.ensure_value(&tmpl_base_iri);
+
+    let mut mappings = vec![
+        ("subject", SUBJECT_IRI),
+        ("predicate", PREDICATE_IRI),
+        ("object", OBJECT_IRI),
+    ];
+    if quads {
+        mappings.push(("graph", SD_GRAPH_IRI));
+    }
+    let variables = mappings
+        .iter()
+        .map(|(variable, _)| *variable)
+        .collect::<Vec<&str>>()
+        .join(",");
+
+    let mut statements = vec![Statement::new(
+        template_iri,
+        hydra_template,
+        table.ensure_value(&format!("{}{}{{?{}}}", origin, path, variables)),
+        string_type,
+        empty,
+        empty,
+    )];
+
+    for (variable, property) in mappings {
+        let mapping_iri = table.ensure_value(&format!("{}_{}", tmpl_base_iri, variable));
+
+        statements.push(Statement::new(
+            template_iri,
+            hydra_mapping,
+            mapping_iri,
             named_node,
             empty,
             empty,
-        ),
-        Statement::new(
-            template_object_iri,
+        ));
+        statements.push(Statement::new(
+            mapping_iri,
             hydra_variable,
-            db_ctx.lookup_table.ensure_value("object".into()),
+            table.ensure_value(variable),
             string_type,
             empty,
             empty,
-        ),
-        Statement::new(
-            template_object_iri,
+        ));
+        statements.push(Statement::new(
+            mapping_iri,
             hydra_property,
-            db_ctx.lookup_table.ensure_value(&OBJECT_IRI),
+            table.ensure_value(property),
             named_node,
             empty,
             empty,
-        ),
-    ]
+        ));
+    }
+
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Query;
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("50%_\\"), "50\\%\\_\\\\");
+    }
+
+    fn anonymous<T>() -> Either<Variable, T> {
+        Either::A(Variable {
+            _name: "anonymous".into(),
+        })
+    }
+
+    fn query(endpoint: Endpoint, params: BTreeMap<&'static str, String>) -> HPFQuery {
+        HPFQuery {
+            page_size: 10,
+            from: 0,
+            subject: anonymous(),
+            predicate: anonymous(),
+            value: anonymous(),
+            datatype: anonymous(),
+            language: anonymous(),
+            graph: anonymous(),
+            document: anonymous(),
+            value_filters: vec![],
+            endpoint,
+            params,
+        }
+    }
+
+    #[test]
+    fn test_page_iri() {
+        let request = Query::<HPFQueryRequest>::from_query(
+            "subject=https://example.com/a&value=5&datatype=http://www.w3.org/2001/XMLSchema%23integer\
+             &language=&graph=&value_gt=1&value_contains=a%20b&page=7&page_size=10",
+        )
+        .unwrap();
+        let hpf = query(Endpoint::Hpf, request.params());
+        assert_eq!(
+            hpf.page_iri("https://example.com", 0),
+            "https://example.com/hpf?datatype=http%3A%2F%2Fwww.w3.org%2F2001%2FXMLSchema%23integer\
+             &graph=&page_size=10&subject=https%3A%2F%2Fexample.com%2Fa&value=5&value_contains=a+b\
+             &value_gt=1"
+        );
+        assert_eq!(
+            hpf.page_iri("https://example.com", 42),
+            "https://example.com/hpf?datatype=http%3A%2F%2Fwww.w3.org%2F2001%2FXMLSchema%23integer\
+             &graph=&page=42&page_size=10&subject=https%3A%2F%2Fexample.com%2Fa&value=5\
+             &value_contains=a+b&value_gt=1"
+        );
+
+        let request =
+            Query::<TPFQueryRequest>::from_query("object=%22A%22@en&page_size=500").unwrap();
+        let tpf = query(Endpoint::Tpf, request.params());
+        assert_eq!(
+            tpf.page_iri("https://example.com", 0),
+            "https://example.com/tpf?object=%22A%22%40en&page_size=10"
+        );
+
+        let request =
+            Query::<QPFQueryRequest>::from_query("subject=?s&graph=https://example.com/a").unwrap();
+        let qpf = query(Endpoint::Qpf, request.params());
+        assert_eq!(
+            qpf.page_iri("https://example.com", 3),
+            "https://example.com/qpf?graph=https%3A%2F%2Fexample.com%2Fa&page=3&page_size=10\
+             &subject=%3Fs"
+        );
+    }
+
+    #[test]
+    fn test_infer_value_kind() {
+        assert_eq!(infer_value_kind("-1.5e3"), ValueKind::Numeric);
+        assert_eq!(infer_value_kind("2020-10-21"), ValueKind::DateTime);
+        assert_eq!(
+            infer_value_kind("2020-10-21T09:00:00Z"),
+            ValueKind::DateTime
+        );
+        assert_eq!(infer_value_kind("NaN"), ValueKind::Text);
+        assert_eq!(infer_value_kind("Foo"), ValueKind::Text);
+    }
 }
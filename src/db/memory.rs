@@ -0,0 +1,405 @@
+//! In-memory storage, for tests and deployments without a database.
+//!
+//! Statements are kept as strings, so values which are no longer used aren't retained.
+
+use crate::app_config::HistoryRetention;
+use crate::db::models::Document;
+use crate::db::storage::{
+    bound_term, expired_revisions, parse_object, restore, select_revision, snapshot, Operation,
+    OutboxEvent, Pattern, PatternPage, Revision, RevisionRecord, RevisionSelector, Snapshot,
+    Storage, StorageContext,
+};
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, LookupTable};
+use crate::models;
+use chrono::Utc;
+use rand::seq::IteratorRandom;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::RwLock;
+
+type StoredStatement = [String; 6];
+
+pub(crate) struct MemoryStorage {
+    seed: u32,
+    retention: HistoryRetention,
+    state: RwLock<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The documents by IRI and language.
+    documents: BTreeMap<(String, String), MemoryDocument>,
+    last_id: i64,
+    /// The revisions by IRI, oldest first.
+    revisions: BTreeMap<String, Vec<RevisionRecord>>,
+    /// The events which haven't been relayed yet, by id.
+    outbox: BTreeMap<i64, OutboxEvent>,
+    last_event_id: i64,
+}
+
+impl State {
+    /// Records a revision of the document, `None` for a deletion.
+    fn record(
+        &mut self,
+        retention: &HistoryRetention,
+        iri: &str,
+        language: &str,
+        statements: Option<Snapshot>,
+    ) {
+        if retention.max_versions == 0 {
+            return;
+        }
+
+        let now = Utc::now().naive_utc();
+        let records = self.revisions.entry(iri.to_string()).or_default();
+        let version = records.last().map_or(1, |r| r.revision.version + 1);
+        records.push(RevisionRecord {
+            revision: Revision {
+                version,
+                language: language.to_string(),
+                recorded_at: now,
+                deleted: statements.is_none(),
+            },
+            statements: statements.unwrap_or_default(),
+        });
+
+        let revisions: Vec<Revision> = records.iter().map(|r| r.revision.clone()).collect();
+        let expired = expired_revisions(retention, &revisions, now);
+        records.retain(|r| !expired.contains(&r.revision.version));
+    }
+}
+
+struct MemoryDocument {
+    doc: Document,
+    statements: Vec<StoredStatement>,
+}
+
+impl MemoryStorage {
+    pub fn new(retention: HistoryRetention) -> MemoryStorage {
+        MemoryStorage {
+            seed: rand::random::<u32>(),
+            retention,
+            state: RwLock::new(State::default()),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn open(&self, lang: Option<String>) -> Box<dyn StorageContext + '_> {
+        Box::new(MemoryContext {
+            storage: self,
+            lang,
+            lookup_table: LookupTable::new(self.seed),
+        })
+    }
+}
+
+pub(crate) struct MemoryContext<'a> {
+    storage: &'a MemoryStorage,
+    lang: Option<String>,
+    lookup_table: LookupTable,
+}
+
+impl<'a> MemoryContext<'a> {
+    /// The key of the document in the language of the context, or in any language without one.
+    fn find(&self, state: &State, iri: &str) -> Option<(String, String)> {
+        state
+            .documents
+            .keys()
+            .find(|(doc_iri, language)| {
+                doc_iri == iri && self.lang.iter().all(|lang| lang == language)
+            })
+            .cloned()
+    }
+}
+
+impl<'a> StorageContext for MemoryContext<'a> {
+    fn lookup_table(&mut self) -> &mut LookupTable {
+        &mut self.lookup_table
+    }
+
+    fn into_lookup_table(self: Box<Self>) -> LookupTable {
+        self.lookup_table
+    }
+
+    fn get_document(&mut self, iri: &str) -> Result<(Document, HashModel), ErrorKind> {
+        let storage = self.storage;
+        let state = storage.state.read().unwrap();
+        let stored = self
+            .find(&state, iri)
+            .and_then(|key| state.documents.get(&key))
+            .ok_or(ErrorKind::EmptyDocument)?;
+
+        Ok((
+            stored.doc.clone(),
+            restore(&mut self.lookup_table, &stored.statements),
+        ))
+    }
+
+    fn random_document(&mut self) -> Result<(Document, HashModel), ErrorKind> {
+        let storage = self.storage;
+        let state = storage.state.read().unwrap();
+        let stored = state
+            .documents
+            .values()
+            .choose(&mut rand::thread_rng())
+            .ok_or(ErrorKind::NoResources)?;
+
+        Ok((
+            stored.doc.clone(),
+            restore(&mut self.lookup_table, &stored.statements),
+        ))
+    }
+
+    fn update_document(
+        &mut self,
+        iri: &str,
+        update: &mut dyn FnMut(&LookupTable, &HashModel) -> HashModel,
+    ) -> Result<bool, ErrorKind> {
+        let storage = self.storage;
+        let mut state = storage.state.write().unwrap();
+        let key = match self.find(&state, iri) {
+            Some(key) => key,
+            None => {
+                state.last_id += 1;
+                let now = Utc::now().naive_utc();
+                let language = self.lang.clone().unwrap_or_default();
+                let doc = Document {
+                    id: state.last_id,
+                    iri: iri.to_string(),
+                    created_at: now,
+                    updated_at: now,
+                    cache_control: 0,
+                    language: language.clone(),
+                };
+                let key = (iri.to_string(), language);
+                state.documents.insert(
+                    key.clone(),
+                    MemoryDocument {
+                        doc,
+                        statements: vec![],
+                    },
+                );
+
+                key
+            }
+        };
+
+        let stored = state.documents.get_mut(&key).unwrap();
+        let existing = restore(&mut self.lookup_table, &stored.statements);
+        let mut next = update(&self.lookup_table, &existing);
+        let mut seen = HashSet::new();
+        next.retain(|s| seen.insert(*s));
+
+        if next == existing {
+            return Ok(false);
+        }
+        let statements = snapshot(&self.lookup_table, &next);
+        stored.statements = statements.clone();
+        stored.doc.updated_at = Utc::now().naive_utc();
+        state.record(&storage.retention, iri, &key.1, Some(statements));
+
+        Ok(true)
+    }
+
+    fn delete_document(&mut self, iri: &str) -> Result<(), ErrorKind> {
+        let mut state = self.storage.state.write().unwrap();
+        let keys: Vec<(String, String)> = state
+            .documents
+            .keys()
+            .filter(|(doc_iri, _)| doc_iri == iri)
+            .cloned()
+            .collect();
+        if keys.is_empty() {
+            return Err(ErrorKind::NotFound);
+        }
+        for key in keys {
+            state.documents.remove(&key);
+            state.record(&self.storage.retention, iri, &key.1, None);
+        }
+
+        Ok(())
+    }
+
+    fn delete_all(&mut self) -> Result<(), ErrorKind> {
+        self.storage.state.write().unwrap().documents.clear();
+
+        Ok(())
+    }
+
+    fn update_cache_control(&mut self, docs: &[models::Document]) -> Result<(), ErrorKind> {
+        let mut state = self.storage.state.write().unwrap();
+        for stored in state.documents.values_mut() {
+            if let Some(doc) = docs.iter().find(|d| d.iri == stored.doc.iri) {
+                stored.doc.cache_control = i16::from(doc.cache_control);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn count_documents(&mut self) -> i64 {
+        self.storage.state.read().unwrap().documents.len() as i64
+    }
+
+    fn match_pattern(&mut self, pattern: &Pattern) -> Result<PatternPage, ErrorKind> {
+        let object = parse_object(&pattern.object)?;
+        let storage = self.storage;
+        let state = storage.state.read().unwrap();
+
+        let matches: Vec<&StoredStatement> = state
+            .documents
+            .iter()
+            .filter(|((iri, _), _)| is_match(&pattern.document, iri))
+            .flat_map(|(_, stored)| stored.statements.iter())
+            .filter(|s| {
+                is_match(&pattern.subject, &s[0])
+                    && is_match(&pattern.predicate, &s[1])
+                    && object.iter().all(|object| {
+                        object
+                            .iter()
+                            .zip(&s[2..5])
+                            .all(|(term, value)| term == value)
+                    })
+            })
+            .collect();
+
+        let (range, next, previous) = pattern.page_range(matches.len());
+        let page: Vec<StoredStatement> = matches[range].iter().map(|s| (*s).clone()).collect();
+
+        Ok(PatternPage {
+            statements: restore(&mut self.lookup_table, &page),
+            next,
+            previous,
+            count: matches.len() as i64,
+        })
+    }
+
+    fn revisions(&mut self, iri: &str) -> Result<Vec<Revision>, ErrorKind> {
+        let state = self.storage.state.read().unwrap();
+        let revisions = state
+            .revisions
+            .get(iri)
+            .map(|records| records.iter().map(|r| r.revision.clone()).collect())
+            .unwrap_or_default();
+
+        Ok(revisions)
+    }
+
+    fn get_revision(
+        &mut self,
+        iri: &str,
+        selector: &RevisionSelector,
+    ) -> Result<(Revision, HashModel), ErrorKind> {
+        let storage = self.storage;
+        let state = storage.state.read().unwrap();
+        let records = state.revisions.get(iri).ok_or(ErrorKind::NotFound)?;
+        let revisions: Vec<Revision> = records.iter().map(|r| r.revision.clone()).collect();
+        let revision =
+            select_revision(&revisions, &self.lang, selector).ok_or(ErrorKind::NotFound)?;
+        let record = records
+            .iter()
+            .find(|r| r.revision.version == revision.version)
+            .ok_or(ErrorKind::NotFound)?;
+
+        Ok((
+            revision,
+            restore(&mut self.lookup_table, &record.statements),
+        ))
+    }
+
+    fn enqueue_event(
+        &mut self,
+        iri: &str,
+        operation: Operation,
+        delta: Option<String>,
+    ) -> Result<(), ErrorKind> {
+        let mut state = self.storage.state.write().unwrap();
+        let updated_at = state
+            .documents
+            .iter()
+            .filter(|((doc_iri, _), _)| doc_iri == iri)
+            .map(|(_, stored)| stored.doc.updated_at)
+            .max()
+            .unwrap_or_else(|| Utc::now().naive_utc());
+
+        state.last_event_id += 1;
+        let id = state.last_event_id;
+        state.outbox.insert(
+            id,
+            OutboxEvent {
+                iri: iri.to_string(),
+                operation,
+                updated_at,
+                delta,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn pending_events(&mut self, limit: usize) -> Result<Vec<(i64, OutboxEvent)>, ErrorKind> {
+        let state = self.storage.state.read().unwrap();
+        let events = state
+            .outbox
+            .iter()
+            .take(limit)
+            .map(|(id, event)| (*id, event.clone()))
+            .collect();
+
+        Ok(events)
+    }
+
+    fn remove_events(&mut self, ids: &[i64]) -> Result<(), ErrorKind> {
+        let mut state = self.storage.state.write().unwrap();
+        for id in ids {
+            state.outbox.remove(id);
+        }
+
+        Ok(())
+    }
+
+    fn transaction(
+        &mut self,
+        work: &mut dyn FnMut(&mut dyn StorageContext) -> Result<(), ErrorKind>,
+    ) -> Result<(), ErrorKind> {
+        // Not atomic, see the contract of `StorageContext::transaction`.
+        work(self)
+    }
+}
+
+/// Whether the term of the pattern is unbound or equal to `value`.
+fn is_match(term: &Option<String>, value: &str) -> bool {
+    match bound_term(term) {
+        Some(term) => term == value,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::storage::tests::{check_documents, check_outbox, check_revisions};
+
+    fn storage() -> MemoryStorage {
+        MemoryStorage::new(HistoryRetention {
+            max_versions: 2,
+            max_age_days: None,
+        })
+    }
+
+    #[test]
+    fn test_memory_documents() {
+        check_documents(&storage());
+    }
+
+    #[test]
+    fn test_memory_revisions() {
+        check_revisions(&storage());
+    }
+
+    #[test]
+    fn test_memory_outbox() {
+        check_outbox(&storage());
+    }
+}
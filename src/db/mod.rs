@@ -1,9 +1,19 @@
+pub mod bgp;
 pub mod cache_control;
 pub mod db_context;
 pub mod document;
+pub mod embedded;
 pub mod hpf;
+pub mod memory;
 pub mod models;
+pub mod outbox;
+pub mod postgres;
 pub mod properties;
 pub mod resources;
+pub mod revisions;
 pub mod schema;
+pub mod search;
+pub mod sparql;
+mod sql;
+pub mod storage;
 pub mod uu128;
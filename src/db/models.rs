@@ -37,6 +37,37 @@ pub struct NewDocument {
     pub language: String,
 }
 
+#[derive(Eq, PartialEq, Debug, Queryable, Insertable)]
+#[table_name = "document_revisions"]
+pub struct DocumentRevision {
+    pub iri: String,
+    pub version: i64,
+    pub language: String,
+    pub recorded_at: NaiveDateTime,
+    pub deleted: bool,
+    /// The statements by value as JSON.
+    pub statements: String,
+}
+
+#[derive(Eq, PartialEq, Debug, Queryable)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub iri: String,
+    pub operation: String,
+    pub updated_at: NaiveDateTime,
+    pub delta: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Eq, PartialEq, Debug, Insertable)]
+#[table_name = "outbox_events"]
+pub struct NewOutboxEntry {
+    pub iri: String,
+    pub operation: String,
+    pub updated_at: NaiveDateTime,
+    pub delta: Option<String>,
+}
+
 #[derive(Eq, PartialEq, Debug, Queryable, Associations, Identifiable)]
 #[belongs_to(Document)]
 pub struct Resource {
@@ -92,6 +123,8 @@ pub struct Property {
     pub language_id: Option<i32>,
     pub value: String,
     pub object_id: Option<Uu128>,
+    /// The hash of the named graph, `None` for the default graph.
+    pub graph_id: Option<Uu128>,
 }
 
 #[derive(Eq, PartialEq, Debug, Associations, Insertable)]
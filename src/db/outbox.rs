@@ -0,0 +1,66 @@
+use crate::db::models::{NewOutboxEntry, OutboxEntry};
+use crate::db::schema::documents::dsl as documents;
+use crate::db::schema::outbox_events::dsl;
+use crate::db::storage::{Operation, OutboxEvent};
+use crate::errors::ErrorKind;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+/// Adds an event to the outbox, stamped with the latest `updated_at` of the document.
+pub(crate) fn enqueue_event(
+    db_conn: &PgConnection,
+    doc_iri: &str,
+    operation: Operation,
+    delta: Option<String>,
+) -> Result<(), ErrorKind> {
+    let updated_at = documents::documents
+        .filter(documents::iri.eq(doc_iri))
+        .select(diesel::dsl::max(documents::updated_at))
+        .first::<Option<NaiveDateTime>>(db_conn)
+        .map_err(|e| ErrorKind::Unexpected(e.to_string()))?
+        .unwrap_or_else(|| Utc::now().naive_utc());
+
+    diesel::insert_into(dsl::outbox_events)
+        .values(&NewOutboxEntry {
+            iri: doc_iri.to_string(),
+            operation: operation.as_str().to_string(),
+            updated_at,
+            delta,
+        })
+        .execute(db_conn)
+        .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+    Ok(())
+}
+
+/// The oldest events in the outbox by their id.
+pub(crate) fn pending_events(
+    db_conn: &PgConnection,
+    limit: usize,
+) -> Result<Vec<(i64, OutboxEvent)>, ErrorKind> {
+    dsl::outbox_events
+        .order(dsl::id.asc())
+        .limit(limit as i64)
+        .load::<OutboxEntry>(db_conn)
+        .map_err(|e| ErrorKind::Unexpected(e.to_string()))?
+        .into_iter()
+        .map(|entry| {
+            let event = OutboxEvent {
+                iri: entry.iri,
+                operation: entry.operation.parse()?,
+                updated_at: entry.updated_at,
+                delta: entry.delta,
+            };
+
+            Ok((entry.id, event))
+        })
+        .collect()
+}
+
+pub(crate) fn remove_events(db_conn: &PgConnection, ids: &[i64]) -> Result<(), ErrorKind> {
+    diesel::delete(dsl::outbox_events.filter(dsl::id.eq_any(ids)))
+        .execute(db_conn)
+        .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+    Ok(())
+}
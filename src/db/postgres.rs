@@ -0,0 +1,307 @@
+use crate::app_config::HistoryRetention;
+use crate::db::db_context::{DbContext, DbPool};
+use crate::db::document::{
+    delete_all_document_data, delete_document_data, doc_by_iri, find_or_create_document,
+    random_doc, update_cache_control, update_document_data,
+};
+use crate::db::hpf::HPFQuery;
+use crate::db::models::Document;
+use crate::db::outbox::{enqueue_event, pending_events, remove_events};
+use crate::db::revisions::{record_revision, revision_statements, revisions_by_iri};
+use crate::db::schema::documents::dsl as documents;
+use crate::db::storage::{
+    restore, select_revision, snapshot, Operation, OutboxEvent, Pattern, PatternPage, Revision,
+    RevisionSelector, Storage, StorageContext,
+};
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, LookupTable};
+use crate::models;
+use diesel::prelude::*;
+use diesel::result::Error::RollbackTransaction;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+pub(crate) struct PgStorage {
+    pool: DbPool,
+    retention: HistoryRetention,
+}
+
+impl PgStorage {
+    pub fn new(pool: DbPool, retention: HistoryRetention) -> PgStorage {
+        PgStorage { pool, retention }
+    }
+}
+
+impl Storage for PgStorage {
+    fn open(&self, lang: Option<String>) -> Box<dyn StorageContext + '_> {
+        let mut ctx = DbContext::new_with_lang(&self.pool, lang);
+        ctx.retention = self.retention.clone();
+
+        Box::new(ctx)
+    }
+
+    fn pool(&self) -> Option<&DbPool> {
+        Some(&self.pool)
+    }
+}
+
+impl<'a> StorageContext for DbContext<'a> {
+    fn lookup_table(&mut self) -> &mut LookupTable {
+        &mut self.lookup_table
+    }
+
+    fn into_lookup_table(self: Box<Self>) -> LookupTable {
+        self.lookup_table
+    }
+
+    fn get_document(&mut self, iri: &str) -> Result<(Document, HashModel), ErrorKind> {
+        doc_by_iri(self, iri)
+    }
+
+    fn random_document(&mut self) -> Result<(Document, HashModel), ErrorKind> {
+        random_doc(self)
+    }
+
+    fn update_document(
+        &mut self,
+        iri: &str,
+        update: &mut dyn FnMut(&LookupTable, &HashModel) -> HashModel,
+    ) -> Result<bool, ErrorKind> {
+        let stored = find_or_create_document(self, iri);
+        let language = stored.doc.language.clone();
+        let existing: HashModel = stored.properties.iter().map(|(_, s)| *s).collect();
+        let mut next = update(&self.lookup_table, &existing);
+        let mut seen = HashSet::new();
+        next.retain(|s| seen.insert(*s));
+
+        let changed = update_document_data(self, stored, &next);
+        if changed {
+            let statements = snapshot(&self.lookup_table, &next);
+            record_revision(
+                &self.get_conn(),
+                &self.retention,
+                iri,
+                &language,
+                Some(statements),
+            )?;
+        }
+
+        Ok(changed)
+    }
+
+    fn delete_document(&mut self, iri: &str) -> Result<(), ErrorKind> {
+        let db_conn = self.get_conn();
+        let doc_id = delete_document_data(&db_conn, iri)?;
+        let language = documents::documents
+            .find(doc_id)
+            .select(documents::language)
+            .first::<String>(&*db_conn)
+            .unwrap_or_default();
+
+        record_revision(&db_conn, &self.retention, iri, &language, None)
+    }
+
+    fn delete_all(&mut self) -> Result<(), ErrorKind> {
+        delete_all_document_data(&self.get_conn())
+            .map(|_| ())
+            .map_err(|e| ErrorKind::Unexpected(e.to_string()))
+    }
+
+    fn update_cache_control(&mut self, docs: &[models::Document]) -> Result<(), ErrorKind> {
+        update_cache_control(&self.get_conn(), docs);
+
+        Ok(())
+    }
+
+    fn count_documents(&mut self) -> i64 {
+        self.est_counts().documents
+    }
+
+    fn match_pattern(&mut self, pattern: &Pattern) -> Result<PatternPage, ErrorKind> {
+        match HPFQuery::from_pattern(self, pattern) {
+            Ok(query) => query.page(self),
+            Err(ErrorKind::NoResources) => Ok(PatternPage {
+                statements: vec![],
+                next: None,
+                previous: None,
+                count: 0,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn revisions(&mut self, iri: &str) -> Result<Vec<Revision>, ErrorKind> {
+        revisions_by_iri(&self.get_conn(), iri)
+    }
+
+    fn get_revision(
+        &mut self,
+        iri: &str,
+        selector: &RevisionSelector,
+    ) -> Result<(Revision, HashModel), ErrorKind> {
+        let db_conn = self.get_conn();
+        let revisions = revisions_by_iri(&db_conn, iri)?;
+        let lang = self.lang.as_ref().map(|lang| lang.trim_end().to_string());
+        let revision = select_revision(&revisions, &lang, selector).ok_or(ErrorKind::NotFound)?;
+        let statements = revision_statements(&db_conn, iri, revision.version)?;
+
+        Ok((revision, restore(&mut self.lookup_table, &statements)))
+    }
+
+    fn enqueue_event(
+        &mut self,
+        iri: &str,
+        operation: Operation,
+        delta: Option<String>,
+    ) -> Result<(), ErrorKind> {
+        enqueue_event(&self.get_conn(), iri, operation, delta)
+    }
+
+    fn pending_events(&mut self, limit: usize) -> Result<Vec<(i64, OutboxEvent)>, ErrorKind> {
+        pending_events(&self.get_conn(), limit)
+    }
+
+    fn remove_events(&mut self, ids: &[i64]) -> Result<(), ErrorKind> {
+        remove_events(&self.get_conn(), ids)
+    }
+
+    fn transaction(
+        &mut self,
+        work: &mut dyn FnMut(&mut dyn StorageContext) -> Result<(), ErrorKind>,
+    ) -> Result<(), ErrorKind> {
+        let db_conn = self.get_conn();
+        let outer = self.transaction_conn.replace(Rc::clone(&db_conn));
+        let mut result: Result<(), ErrorKind> =
+            Err(ErrorKind::Unexpected("No result from processing".into()));
+
+        let outcome = db_conn.transaction::<(), diesel::result::Error, _>(|| match work(self) {
+            Ok(_) => {
+                result = Ok(());
+
+                Ok(())
+            }
+            Err(e) => {
+                result = Err(e);
+                Err(RollbackTransaction)
+            }
+        });
+        self.transaction_conn = outer;
+
+        match outcome {
+            Ok(_) => result,
+            Err(e) => {
+                self.reload_mappings();
+
+                match e {
+                    RollbackTransaction => result,
+                    e => Err(ErrorKind::Unexpected(e.to_string())),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db_context::{test_lock, test_pool};
+    use crate::db::schema::{document_revisions, outbox_events};
+    use crate::db::storage::tests::{check_documents, check_outbox, check_revisions};
+    use crate::hashtuple::{Statement, STRING_IRI};
+
+    /// A storage on `DATABASE_URL` without the leftovers of earlier runs.
+    fn storage() -> PgStorage {
+        let storage = PgStorage::new(
+            test_pool(),
+            HistoryRetention {
+                max_versions: 2,
+                max_age_days: None,
+            },
+        );
+        let mut ctx = storage.open(None);
+        for iri in &["https://example.com/a", "https://example.com/b"] {
+            let _ = ctx.delete_document(iri);
+        }
+        drop(ctx);
+
+        let db_conn = storage.pool.get().unwrap();
+        diesel::delete(documents::documents.filter(documents::iri.like("https://example.com/_")))
+            .execute(&db_conn)
+            .unwrap();
+        diesel::delete(
+            document_revisions::table.filter(document_revisions::iri.like("https://example.com/%")),
+        )
+        .execute(&db_conn)
+        .unwrap();
+        diesel::delete(outbox_events::table)
+            .execute(&db_conn)
+            .unwrap();
+
+        storage
+    }
+
+    #[test]
+    #[ignore]
+    fn test_postgres_documents() {
+        let _lock = test_lock();
+        check_documents(&storage());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_postgres_revisions() {
+        let _lock = test_lock();
+        check_revisions(&storage());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_postgres_outbox() {
+        let _lock = test_lock();
+        check_outbox(&storage());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_postgres_transaction_rolls_back() {
+        let _lock = test_lock();
+        let storage = storage();
+        let mut ctx = storage.open(Some(String::from("en")));
+        // A new predicate, so it's inserted in the transaction as well.
+        let predicate = format!("https://example.com/predicate/{}", rand::random::<u32>());
+        let table = ctx.lookup_table();
+        let statement = Statement::new(
+            table.ensure_value("https://example.com/a"),
+            table.ensure_value(&predicate),
+            table.ensure_value("A"),
+            table.ensure_value(STRING_IRI),
+            table.ensure_value(""),
+            table.ensure_value(""),
+        );
+
+        let result = ctx.transaction(&mut |ctx| {
+            ctx.update_document("https://example.com/a", &mut |_, _| vec![statement])?;
+            // Postgres doesn't accept NUL in text, so inserting the event fails.
+            ctx.enqueue_event(
+                "https://example.com/a",
+                Operation::Update,
+                Some(String::from("\0")),
+            )
+        });
+        assert!(result.is_err());
+
+        let mut other = storage.open(None);
+        assert!(other.get_document("https://example.com/a").is_err());
+        assert!(other.revisions("https://example.com/a").unwrap().is_empty());
+        assert!(other.pending_events(10).unwrap().is_empty());
+
+        // The context can still write after the rollback.
+        ctx.update_document("https://example.com/a", &mut |_, _| vec![statement])
+            .unwrap();
+        let (_, model) = storage
+            .open(None)
+            .get_document("https://example.com/a")
+            .unwrap();
+        assert_eq!(model, vec![statement]);
+    }
+}
@@ -77,6 +77,21 @@ pub(crate) fn insert_properties(
 
         let pred_id: i32 = *ctx.property_map.get_by_left(predicate).unwrap();
 
+        let graph = ctx
+            .lookup_table
+            .get_by_hash(h.graph)
+            .expect("Graph to insert not in map");
+        let graph_id = if graph.is_empty() {
+            None
+        } else {
+            values.insert(Object {
+                hash: Uu128::from(h.graph),
+                value: String::from(graph),
+            });
+
+            Some(Uu128::from(h.graph))
+        };
+
         properties.push((
             dsl::resource_id.eq(resource_id),
             dsl::predicate_id.eq(pred_id),
@@ -85,6 +100,7 @@ pub(crate) fn insert_properties(
             dsl::object_id.eq(Uu128::from(h.value)),
             dsl::datatype_id.eq(datatype_id),
             dsl::language_id.eq(language_id),
+            dsl::graph_id.eq(graph_id),
             //            dsl::prop_resource.eq(None),
         ));
     }
@@ -97,7 +113,7 @@ pub(crate) fn insert_properties(
             insert_into(schema::objects::table)
                 .values(chunk)
                 .on_conflict_do_nothing()
-                .execute(&ctx.get_conn())
+                .execute(&*ctx.get_conn())
                 .expect("Error while inserting into objects");
         });
 
@@ -106,7 +122,7 @@ pub(crate) fn insert_properties(
         .for_each(|chunk| {
             insert_into(schema::properties::table)
                 .values(chunk)
-                .execute(&ctx.get_conn())
+                .execute(&*ctx.get_conn())
                 .expect("Error while inserting into resources");
         });
 }
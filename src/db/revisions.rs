@@ -0,0 +1,149 @@
+use crate::app_config::HistoryRetention;
+use crate::db::models::DocumentRevision;
+use crate::db::schema::document_revisions::dsl;
+use crate::db::storage::{expired_revisions, Revision, Snapshot};
+use crate::errors::ErrorKind;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+
+/// Records the new state of the document, `None` for a deletion, and removes the revisions which
+/// fall outside the retention.
+pub(crate) fn record_revision(
+    db_conn: &PgConnection,
+    retention: &HistoryRetention,
+    doc_iri: &str,
+    doc_language: &str,
+    statements: Option<Snapshot>,
+) -> Result<(), ErrorKind> {
+    if retention.max_versions == 0 {
+        return Ok(());
+    }
+    let deleted = statements.is_none();
+    let statements = serde_json::to_string(&statements.unwrap_or_default())
+        .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+    db_conn
+        .transaction(|| {
+            // Serializes the writers of the document, the next version is derived from the latest.
+            sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+                .bind::<Text, _>(doc_iri)
+                .execute(db_conn)?;
+
+            let now = Utc::now().naive_utc();
+            let latest = dsl::document_revisions
+                .filter(dsl::iri.eq(doc_iri))
+                .select(diesel::dsl::max(dsl::version))
+                .first::<Option<i64>>(db_conn)?;
+            let revision = DocumentRevision {
+                iri: doc_iri.to_string(),
+                version: latest.unwrap_or(0) + 1,
+                language: doc_language.trim_end().to_string(),
+                recorded_at: now,
+                deleted,
+                statements,
+            };
+            diesel::insert_into(dsl::document_revisions)
+                .values(&revision)
+                .execute(db_conn)?;
+
+            let expired = expired_revisions(retention, &load_revisions(db_conn, doc_iri)?, now);
+            if !expired.is_empty() {
+                diesel::delete(
+                    dsl::document_revisions
+                        .filter(dsl::iri.eq(doc_iri).and(dsl::version.eq_any(expired))),
+                )
+                .execute(db_conn)?;
+            }
+
+            Ok(())
+        })
+        .map_err(|e: diesel::result::Error| ErrorKind::Unexpected(e.to_string()))
+}
+
+/// The revisions of the document without their statements, oldest first.
+pub(crate) fn revisions_by_iri(
+    db_conn: &PgConnection,
+    doc_iri: &str,
+) -> Result<Vec<Revision>, ErrorKind> {
+    load_revisions(db_conn, doc_iri).map_err(|e| ErrorKind::Unexpected(e.to_string()))
+}
+
+fn load_revisions(db_conn: &PgConnection, doc_iri: &str) -> QueryResult<Vec<Revision>> {
+    let rows = dsl::document_revisions
+        .filter(dsl::iri.eq(doc_iri))
+        .select((dsl::version, dsl::language, dsl::recorded_at, dsl::deleted))
+        .order(dsl::version.asc())
+        .load::<(i64, String, NaiveDateTime, bool)>(db_conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(version, language, recorded_at, deleted)| Revision {
+            version,
+            language,
+            recorded_at,
+            deleted,
+        })
+        .collect())
+}
+
+pub(crate) fn revision_statements(
+    db_conn: &PgConnection,
+    doc_iri: &str,
+    doc_version: i64,
+) -> Result<Snapshot, ErrorKind> {
+    let statements = dsl::document_revisions
+        .find((doc_iri, doc_version))
+        .select(dsl::statements)
+        .first::<String>(db_conn)
+        .map_err(|_| ErrorKind::NotFound)?;
+
+    serde_json::from_str(&statements).map_err(|e| ErrorKind::Unexpected(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db_context::{test_lock, test_pool};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    #[ignore]
+    fn test_record_revision_concurrently() {
+        let _lock = test_lock();
+        let pool = test_pool();
+        let iri = "https://example.com/revisions/a";
+        diesel::delete(dsl::document_revisions.filter(dsl::iri.eq(iri)))
+            .execute(&pool.get().unwrap())
+            .unwrap();
+
+        let retention = HistoryRetention {
+            max_versions: 10,
+            max_age_days: None,
+        };
+        let start = Arc::new(Barrier::new(4));
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let (pool, retention, start) = (pool.clone(), retention.clone(), start.clone());
+
+                thread::spawn(move || {
+                    let db_conn = pool.get().unwrap();
+                    start.wait();
+                    record_revision(&db_conn, &retention, iri, "en", Some(vec![])).unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let versions: Vec<i64> = revisions_by_iri(&pool.get().unwrap(), iri)
+            .unwrap()
+            .iter()
+            .map(|r| r.version)
+            .collect();
+        assert_eq!(versions, vec![1, 2, 3, 4]);
+    }
+}
@@ -12,6 +12,17 @@ table! {
     }
 }
 
+table! {
+    document_revisions (iri, version) {
+        iri -> Varchar,
+        version -> Int8,
+        language -> Varchar,
+        recorded_at -> Timestamp,
+        deleted -> Bool,
+        statements -> Text,
+    }
+}
+
 table! {
     documents (id) {
         id -> Int8,
@@ -30,6 +41,17 @@ table! {
     }
 }
 
+table! {
+    outbox_events (id) {
+        id -> Int8,
+        iri -> Varchar,
+        operation -> Varchar,
+        updated_at -> Timestamp,
+        delta -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     predicates (id) {
         id -> Int4,
@@ -55,6 +77,7 @@ table! {
         language_id -> Nullable<Int4>,
         value -> Varchar,
         object_id -> Nullable<Uuid>,
+        graph_id -> Nullable<Uuid>,
     }
 }
 
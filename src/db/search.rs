@@ -0,0 +1,344 @@
+//! Full-text search over literal values
+//!
+//! Literals are indexed in `properties.search_vector` by a trigger, both stemmed with the
+//! dictionary of their language and as plain words. Matching properties are grouped by their
+//! subject, which is ranked by its best matching property.
+
+use crate::db::bgp::statements_by_id;
+use crate::db::db_context::DbContext;
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, Statement, BLANK_NODE_IRI, NAMED_NODE_IRI};
+use crate::rdf::sparql::{XSD_DOUBLE_IRI, XSD_INTEGER_IRI};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Float4, Text};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+/// The amount of matching statements included per subject.
+const MATCHES_PER_SUBJECT: usize = 3;
+
+const HYDRA_MEMBER_IRI: &str = "http://www.w3.org/ns/hydra/core#member";
+const HYDRA_FIRST_IRI: &str = "http://www.w3.org/ns/hydra/core#first";
+const HYDRA_NEXT_IRI: &str = "http://www.w3.org/ns/hydra/core#next";
+const HYDRA_PREVIOUS_IRI: &str = "http://www.w3.org/ns/hydra/core#previous";
+const HYDRA_ITEMS_PER_PAGE_IRI: &str = "http://www.w3.org/ns/hydra/core#itemsPerPage";
+const RDFS_IS_DEFINED_BY_IRI: &str = "http://www.w3.org/2000/01/rdf-schema#isDefinedBy";
+const SEARCH_RANK_IRI: &str = "https://ns.ontola.io/core#searchRank";
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SearchRequest {
+    #[serde(default)]
+    q: Option<String>,
+    /// The language of the query, used to stem its words.
+    #[serde(default)]
+    lang: Option<String>,
+    #[serde(default)]
+    page: Option<i64>,
+    #[serde(default)]
+    page_size: Option<i64>,
+}
+
+#[derive(QueryableByName)]
+struct Hit {
+    #[sql_type = "Text"]
+    subject: String,
+    #[sql_type = "Text"]
+    document: String,
+    #[sql_type = "Float4"]
+    rank: f32,
+    #[sql_type = "Array<BigInt>"]
+    property_ids: Vec<i64>,
+}
+
+pub(crate) struct SearchQuery {
+    query: String,
+    language: Option<String>,
+    page: i64,
+    page_size: i64,
+}
+
+impl SearchQuery {
+    pub fn parse(request: &SearchRequest) -> Result<SearchQuery, ErrorKind> {
+        let query = match &request.q {
+            Some(q) if !q.trim().is_empty() => q.trim().to_string(),
+            _ => return Err(ErrorKind::ParserError(String::from("Missing query"))),
+        };
+
+        let page = request.page.unwrap_or(0).max(0);
+        let page_size = request
+            .page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .max(1)
+            .min(MAX_PAGE_SIZE);
+        // The end of the page bounds both its offset and the number of the next page.
+        if page
+            .checked_add(1)
+            .and_then(|next| next.checked_mul(page_size))
+            .is_none()
+        {
+            return Err(ErrorKind::ParserError(String::from("Page out of range")));
+        }
+
+        Ok(SearchQuery {
+            query,
+            language: request.lang.clone().filter(|l| !l.is_empty()),
+            page,
+            page_size,
+        })
+    }
+
+    /// The ranked subjects with their document, best matching statements and paging controls.
+    pub fn execute(&self, db_ctx: &mut DbContext, origin: &str) -> Result<HashModel, ErrorKind> {
+        let mut hits = diesel::sql_query(self.to_sql())
+            .bind::<Text, _>(&self.query)
+            .bind::<Text, _>(self.language.as_deref().unwrap_or(""))
+            .load::<Hit>(&*db_ctx.get_conn())
+            .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+
+        let has_next = hits.len() as i64 > self.page_size;
+        hits.truncate(self.page_size as usize);
+
+        let property_ids: HashSet<i64> = hits
+            .iter()
+            .flat_map(|hit| hit.property_ids.iter().cloned())
+            .collect();
+        let matched = statements_by_id(db_ctx, property_ids)?;
+
+        let table = &mut db_ctx.lookup_table;
+        let named_node = table.ensure_value(NAMED_NODE_IRI);
+        let blank_node = table.ensure_value(BLANK_NODE_IRI);
+        let double = table.ensure_value(XSD_DOUBLE_IRI);
+        let integer = table.ensure_value(XSD_INTEGER_IRI);
+        let empty = table.ensure_value("");
+
+        let collection = table.ensure_value(&self.page_iri(origin, self.page));
+        let member = table.ensure_value(HYDRA_MEMBER_IRI);
+        let is_defined_by = table.ensure_value(RDFS_IS_DEFINED_BY_IRI);
+        let search_rank = table.ensure_value(SEARCH_RANK_IRI);
+
+        let mut model = vec![];
+        for hit in &hits {
+            let subject = table.ensure_value(&hit.subject);
+            let subject_type = if hit.subject.contains(':') {
+                named_node
+            } else {
+                blank_node
+            };
+
+            model.push(Statement::new(
+                collection,
+                member,
+                subject,
+                subject_type,
+                empty,
+                empty,
+            ));
+            model.push(Statement::new(
+                subject,
+                is_defined_by,
+                table.ensure_value(&hit.document),
+                named_node,
+                empty,
+                empty,
+            ));
+            model.push(Statement::new(
+                subject,
+                search_rank,
+                table.ensure_value(&hit.rank.to_string()),
+                double,
+                empty,
+                empty,
+            ));
+            model.extend(
+                hit.property_ids
+                    .iter()
+                    .filter_map(|id| matched.get(id).cloned()),
+            );
+        }
+
+        let mut links = vec![(HYDRA_FIRST_IRI, 0)];
+        if has_next {
+            links.push((HYDRA_NEXT_IRI, self.page + 1));
+        }
+        if self.page > 0 {
+            links.push((HYDRA_PREVIOUS_IRI, self.page - 1));
+        }
+        for (predicate, page) in links {
+            let page_iri = self.page_iri(origin, page);
+            model.push(Statement::new(
+                collection,
+                table.ensure_value(predicate),
+                table.ensure_value(&page_iri),
+                named_node,
+                empty,
+                empty,
+            ));
+        }
+        model.push(Statement::new(
+            collection,
+            table.ensure_value(HYDRA_ITEMS_PER_PAGE_IRI),
+            table.ensure_value(&self.page_size.to_string()),
+            integer,
+            empty,
+            empty,
+        ));
+
+        Ok(model)
+    }
+
+    /// The query for the hits of the page, binding the query as `$1` and its language as `$2`.
+    fn to_sql(&self) -> String {
+        format!(
+            "SELECT r.iri AS subject, d.iri AS document, max(ts_rank(p.search_vector, query)) AS rank, \
+             (array_agg(p.id ORDER BY ts_rank(p.search_vector, query) DESC, p.id))[1:{matches}] AS property_ids \
+             FROM websearch_to_tsquery(apex_search_config($2), $1) AS query, \
+             properties p JOIN resources r ON r.id = p.resource_id JOIN documents d ON d.id = r.document_id \
+             WHERE p.search_vector @@ query \
+             GROUP BY r.iri, d.iri \
+             ORDER BY rank DESC, subject \
+             LIMIT {limit} OFFSET {offset}",
+            matches = MATCHES_PER_SUBJECT,
+            // One extra to know whether there is a next page.
+            limit = self.page_size + 1,
+            offset = self.page * self.page_size,
+        )
+    }
+
+    fn page_iri(&self, origin: &str, page: i64) -> String {
+        let mut map = BTreeMap::new();
+        map.insert("q", self.query.clone());
+        if let Some(language) = &self.language {
+            map.insert("lang", language.clone());
+        }
+        if page > 0 {
+            map.insert("page", page.to_string());
+        }
+        if self.page_size != DEFAULT_PAGE_SIZE {
+            map.insert("page_size", self.page_size.to_string());
+        }
+
+        format!("{}/search?{}", origin, serde_qs::to_string(&map).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db_context::{test_lock, test_pool};
+    use crate::db::storage::StorageContext;
+    use crate::hashtuple::{LookupTable, STRING_IRI};
+    use actix_web::web::Query;
+
+    fn parse(query: &str) -> Result<SearchQuery, ErrorKind> {
+        SearchQuery::parse(&Query::<SearchRequest>::from_query(query).unwrap())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(parse("q=%20"), Err(ErrorKind::ParserError(_))));
+        assert!(matches!(parse("lang=en"), Err(ErrorKind::ParserError(_))));
+
+        let query = parse("q=%20apple%20pie%20&lang=&page=-1&page_size=1000").unwrap();
+        assert_eq!(query.query, "apple pie");
+        assert_eq!(query.language, None);
+        assert_eq!(query.page, 0);
+        assert_eq!(query.page_size, MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_parse_page_out_of_range() {
+        let max = format!("q=apple&page={}&page_size=1", i64::MAX);
+        assert!(matches!(parse(&max), Err(ErrorKind::ParserError(_))));
+
+        let overflow = format!("q=apple&page={}", i64::MAX / DEFAULT_PAGE_SIZE);
+        assert!(matches!(parse(&overflow), Err(ErrorKind::ParserError(_))));
+
+        let last = format!("q=apple&page={}&page_size=1", i64::MAX - 1);
+        assert_eq!(parse(&last).unwrap().page, i64::MAX - 1);
+    }
+
+    #[test]
+    fn test_to_sql() {
+        let sql = parse("q=apple&page=2&page_size=10").unwrap().to_sql();
+
+        assert!(sql.contains("websearch_to_tsquery(apex_search_config($2), $1)"));
+        assert!(sql.contains("[1:3] AS property_ids"));
+        assert!(sql.contains("ORDER BY rank DESC, subject"));
+        assert!(sql.ends_with("LIMIT 11 OFFSET 20"));
+    }
+
+    #[test]
+    fn test_page_iri() {
+        let query = parse("q=apple%20pie&lang=en&page=1").unwrap();
+
+        assert_eq!(
+            query.page_iri("https://example.com", 0),
+            "https://example.com/search?lang=en&q=apple+pie"
+        );
+        assert_eq!(
+            query.page_iri("https://example.com", 2),
+            "https://example.com/search?lang=en&page=2&q=apple+pie"
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_execute_ranks_and_pages() {
+        let _lock = test_lock();
+        let pool = test_pool();
+        let mut ctx = DbContext::new_with_lang(&pool, Some(String::from("en")));
+        let name = "https://example.com/search/name";
+        let documents = [
+            ("https://example.com/search/a", "Quince"),
+            ("https://example.com/search/b", "Quince quince quince"),
+            ("https://example.com/search/c", "Pear"),
+        ];
+        for (iri, value) in documents.iter() {
+            let table = &mut ctx.lookup_table;
+            let statement = Statement::new(
+                table.ensure_value(iri),
+                table.ensure_value(name),
+                table.ensure_value(value),
+                table.ensure_value(STRING_IRI),
+                table.ensure_value(""),
+                table.ensure_value(""),
+            );
+            ctx.update_document(iri, &mut |_: &LookupTable, _: &HashModel| vec![statement])
+                .unwrap();
+        }
+
+        let search = |query: &str| {
+            let mut ctx = DbContext::new(&pool);
+            let model = parse(query)
+                .unwrap()
+                .execute(&mut ctx, "https://example.com")
+                .unwrap();
+            let table = &ctx.lookup_table;
+            let value = |hash| table.get_by_hash(hash).unwrap().clone();
+            let members: Vec<String> = model
+                .iter()
+                .filter(|s| value(s.predicate) == HYDRA_MEMBER_IRI)
+                .map(|s| value(s.value))
+                .collect();
+            let next = model
+                .iter()
+                .find(|s| value(s.predicate) == HYDRA_NEXT_IRI)
+                .map(|s| value(s.value));
+
+            (members, next)
+        };
+
+        let (members, next) = search("q=quince&page_size=1");
+        assert_eq!(members, vec!["https://example.com/search/b"]);
+        assert_eq!(
+            next.as_deref(),
+            Some("https://example.com/search?page=1&page_size=1&q=quince")
+        );
+
+        let (members, next) = search("q=quince&page_size=1&page=1");
+        assert_eq!(members, vec!["https://example.com/search/a"]);
+        assert_eq!(next, None);
+    }
+}
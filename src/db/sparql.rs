@@ -0,0 +1,806 @@
+//! SPARQL database implementation
+//!
+//! Queries are compiled to a single SQL query over the properties tables. Every triple pattern
+//! joins `properties` with `resources`, the patterns of OPTIONAL groups are left joined onto the
+//! mandatory ones. Like basic graph patterns, the query selects the ids of the properties matched
+//! by each pattern, from which the variable bindings are read.
+//!
+//! Values are compared through the `objects`, `predicates`, `datatypes` and `languages` tables
+//! where needed, all strings from the query are interpolated as literals escaped by `quote`.
+
+use crate::db::bgp::statements_by_id;
+use crate::db::db_context::DbContext;
+use crate::db::hpf::Position;
+use crate::db::sql::{quote, NUMERIC_DATATYPES};
+use crate::db::uu128::Uu128;
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, Statement, BLANK_NODE_IRI, NAMED_NODE_IRI, STRING_IRI};
+use crate::rdf::sparql::{
+    parse_query, Comparison, Expression, Query, QueryForm, Term, TriplePattern, XSD_BOOLEAN_IRI,
+};
+use crate::serving::serialization::Binding;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Nullable};
+use std::collections::{HashMap, HashSet};
+
+/// The maximum amount of solutions, also when the query doesn't have a LIMIT.
+const MAX_LIMIT: u64 = 10_000;
+const STATEMENT_TIMEOUT_MS: u32 = 30_000;
+/// Visible from every group, used for the WHERE clause and ORDER BY.
+const ALL_GROUPS: usize = usize::MAX;
+
+const NUMERIC_PATTERN: &str = "^[+-]?([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][+-]?[0-9]+)?$";
+
+/// Where a variable occurs, `group` 0 holds the mandatory patterns.
+#[derive(Clone, Copy)]
+struct Site {
+    alias: usize,
+    group: usize,
+    position: Position,
+}
+
+/// SQL expressions for the components of an RDF term, which are NULL when unbound.
+struct TermSql {
+    value: String,
+    datatype: String,
+    language: String,
+}
+
+enum Sql {
+    Term(TermSql),
+    Condition(String),
+}
+
+/// The ids of the properties matched by each pattern, `None` for unmatched OPTIONAL patterns.
+type Row = Vec<Option<i64>>;
+
+#[derive(QueryableByName)]
+struct SolutionRow {
+    #[sql_type = "Array<Nullable<BigInt>>"]
+    property_ids: Vec<Option<i64>>,
+}
+
+pub(crate) struct SPARQLQuery {
+    query: Query,
+    /// The triple patterns with their group, in alias order.
+    patterns: Vec<(usize, TriplePattern)>,
+    sites: HashMap<String, Vec<Site>>,
+}
+
+impl SPARQLQuery {
+    pub fn parse(query: &str) -> Result<SPARQLQuery, ErrorKind> {
+        let query = parse_query(query)?;
+
+        let mut patterns = vec![];
+        let groups = std::iter::once(&query.pattern).chain(query.pattern.optionals.iter());
+        for (group, pattern) in groups.enumerate() {
+            if group > 0 && pattern.triples.is_empty() {
+                bail!(ErrorKind::ParserError(
+                    "OPTIONAL groups need at least one triple pattern".into()
+                ));
+            }
+            patterns.extend(pattern.triples.iter().map(|t| (group, t.clone())));
+        }
+
+        let mut sites: HashMap<String, Vec<Site>> = HashMap::new();
+        for (alias, (group, triple)) in patterns.iter().enumerate() {
+            let terms = [
+                (Position::Subject, &triple.subject),
+                (Position::Predicate, &triple.predicate),
+                (Position::Value, &triple.object),
+            ];
+            for (position, term) in terms.iter() {
+                if let Term::Variable(name) = term {
+                    sites.entry(name.clone()).or_default().push(Site {
+                        alias,
+                        group: *group,
+                        position: *position,
+                    });
+                }
+            }
+        }
+
+        Ok(SPARQLQuery {
+            query,
+            patterns,
+            sites,
+        })
+    }
+
+    pub fn is_construct(&self) -> bool {
+        matches!(self.query.form, QueryForm::Construct { .. })
+    }
+
+    /// The projected variables with their bindings for every solution.
+    pub fn select(&self, db_ctx: &mut DbContext) -> Result<(Vec<String>, Vec<Binding>), ErrorKind> {
+        let (rows, statements) = self.solutions(db_ctx)?;
+        let variables = self.query.projection();
+
+        let bindings = rows
+            .iter()
+            .map(|row| {
+                variables
+                    .iter()
+                    .map(|name| self.binding(name, row, &statements))
+                    .collect()
+            })
+            .collect();
+
+        Ok((variables, bindings))
+    }
+
+    /// The statements of the CONSTRUCT template for every solution.
+    pub fn construct(&self, db_ctx: &mut DbContext) -> Result<HashModel, ErrorKind> {
+        let template = match &self.query.form {
+            QueryForm::Construct { template } => template,
+            QueryForm::Select { .. } => {
+                bail!(ErrorKind::Unexpected("Not a CONSTRUCT query".into()))
+            }
+        };
+        let (rows, statements) = self.solutions(db_ctx)?;
+
+        let lookup_table = &mut db_ctx.lookup_table;
+        let named_node = lookup_table.ensure_value(NAMED_NODE_IRI);
+        let blank_node = lookup_table.ensure_value(BLANK_NODE_IRI);
+        let empty = lookup_table.ensure_value("");
+
+        let mut seen = HashSet::new();
+        let mut model = vec![];
+        for row in &rows {
+            for triple in template {
+                let mut resolve = |term: &Term| -> Option<(u128, u128, u128)> {
+                    match term {
+                        Term::Variable(name) => {
+                            let (statement, position) = self.binding(name, row, &statements)?;
+                            Some(match position {
+                                Position::Subject => {
+                                    let subject = lookup_table.get_by_hash(statement.subject)?;
+                                    let kind = if subject.contains(':') {
+                                        named_node
+                                    } else {
+                                        blank_node
+                                    };
+                                    (statement.subject, kind, empty)
+                                }
+                                Position::Predicate => (statement.predicate, named_node, empty),
+                                _ => (statement.value, statement.datatype, statement.language),
+                            })
+                        }
+                        Term::Iri(iri) => Some((lookup_table.ensure_value(iri), named_node, empty)),
+                        Term::Literal {
+                            value,
+                            datatype,
+                            language,
+                        } => Some((
+                            lookup_table.ensure_value(value),
+                            lookup_table.ensure_value(datatype),
+                            lookup_table.ensure_value(language),
+                        )),
+                    }
+                };
+
+                let subject = resolve(&triple.subject);
+                let predicate = resolve(&triple.predicate);
+                let object = resolve(&triple.object);
+                if let (Some(subject), Some(predicate), Some(object)) = (subject, predicate, object)
+                {
+                    let valid_subject = subject.1 == named_node || subject.1 == blank_node;
+                    if !valid_subject || predicate.1 != named_node {
+                        continue;
+                    }
+                    let statement =
+                        Statement::new(subject.0, predicate.0, object.0, object.1, object.2, empty);
+                    if seen.insert(statement) {
+                        model.push(statement);
+                    }
+                }
+            }
+        }
+
+        Ok(model)
+    }
+
+    /// The first site of `name` which is bound in `row`.
+    fn binding(
+        &self,
+        name: &str,
+        row: &[Option<i64>],
+        statements: &HashMap<i64, Statement>,
+    ) -> Option<(Statement, Position)> {
+        self.sites.get(name)?.iter().find_map(|site| {
+            row[site.alias]
+                .and_then(|id| statements.get(&id))
+                .map(|statement| (*statement, site.position))
+        })
+    }
+
+    fn solutions(
+        &self,
+        db_ctx: &mut DbContext,
+    ) -> Result<(Vec<Row>, HashMap<i64, Statement>), ErrorKind> {
+        let sql = self.to_sql(db_ctx)?;
+        debug!(target: "apex", "Executing SPARQL query: {}", sql);
+
+        let conn = db_ctx.get_conn();
+        let rows = conn
+            .transaction::<_, diesel::result::Error, _>(|| {
+                diesel::sql_query(format!(
+                    "SET LOCAL statement_timeout = {}",
+                    STATEMENT_TIMEOUT_MS
+                ))
+                .execute(&*conn)?;
+
+                diesel::sql_query(sql).load::<SolutionRow>(&*conn)
+            })
+            .map_err(|e| ErrorKind::Unexpected(e.to_string()))?;
+        drop(conn);
+
+        let property_ids = rows
+            .iter()
+            .flat_map(|row| row.property_ids.iter().filter_map(|id| *id))
+            .collect();
+        let statements = statements_by_id(db_ctx, property_ids)?;
+
+        Ok((
+            rows.into_iter().map(|r| r.property_ids).collect(),
+            statements,
+        ))
+    }
+
+    fn to_sql(&self, db_ctx: &DbContext) -> Result<String, ErrorKind> {
+        let group_count = self.query.pattern.optionals.len() + 1;
+        let mut conditions: Vec<Vec<String>> = vec![vec![]; group_count];
+
+        for (alias, (group, triple)) in self.patterns.iter().enumerate() {
+            conditions[*group].extend(constant_conditions(db_ctx, alias, triple));
+        }
+        for sites in self.sites.values() {
+            for (i, site) in sites.iter().enumerate() {
+                conditions[site.group].extend(join_conditions(&sites[..i], site));
+            }
+        }
+        let groups =
+            std::iter::once(&self.query.pattern).chain(self.query.pattern.optionals.iter());
+        for (group, pattern) in groups.enumerate() {
+            let scope = if group == 0 { ALL_GROUPS } else { group };
+            for filter in &pattern.filters {
+                let condition = self.compile(filter, scope)?;
+                conditions[group].push(to_condition(condition));
+            }
+        }
+
+        let mut from = vec![];
+        for (group, group_conditions) in conditions.iter().enumerate() {
+            let joins = self
+                .patterns
+                .iter()
+                .enumerate()
+                .filter(|(_, (g, _))| *g == group)
+                .map(|(alias, _)| {
+                    format!(
+                        "properties p{a} JOIN resources r{a} ON r{a}.id = p{a}.resource_id",
+                        a = alias
+                    )
+                })
+                .collect::<Vec<String>>();
+
+            if group == 0 {
+                if joins.is_empty() {
+                    from.push(String::from("(SELECT 1) AS root"));
+                } else {
+                    from.push(joins.join(" CROSS JOIN "));
+                }
+            } else {
+                from.push(format!(
+                    "LEFT JOIN ({}) ON {}",
+                    joins.join(" CROSS JOIN "),
+                    and(group_conditions)
+                ));
+            }
+        }
+
+        let ids = (0..self.patterns.len())
+            .map(|alias| format!("p{}.id", alias))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut columns = vec![format!("ARRAY[{}]::bigint[] AS property_ids", ids)];
+        let mut order = vec![];
+        for (i, condition) in self.query.order.iter().enumerate() {
+            let term = to_term(self.compile(&condition.expression, ALL_GROUPS)?);
+            let (direction, nulls) = if condition.descending {
+                ("DESC", "NULLS LAST")
+            } else {
+                ("ASC", "NULLS FIRST")
+            };
+            columns.push(format!("{} AS n{}", numeric(&term), i));
+            columns.push(format!("{} AS k{}", term.value, i));
+            order.push(format!(
+                "n{} {} {}, k{} {} {}",
+                i, direction, nulls, i, direction, nulls
+            ));
+        }
+        order.push(String::from("property_ids"));
+
+        let distinct = matches!(self.query.form, QueryForm::Select { distinct: true, .. });
+        if distinct {
+            let partition = self
+                .query
+                .projection()
+                .iter()
+                .map(|name| {
+                    let term = self.variable(name, ALL_GROUPS);
+                    format!("{}, {}, {}", term.value, term.datatype, term.language)
+                })
+                .collect::<Vec<String>>();
+            let partition = if partition.is_empty() {
+                String::new()
+            } else {
+                format!("PARTITION BY {}", partition.join(", "))
+            };
+            columns.push(format!("ROW_NUMBER() OVER ({}) AS rn", partition));
+        }
+
+        let limit = self.query.limit.unwrap_or(MAX_LIMIT).min(MAX_LIMIT);
+        let offset = self.query.offset.unwrap_or(0);
+
+        Ok(format!(
+            "SELECT property_ids FROM (SELECT {columns} FROM {from} WHERE {filters}) AS solutions{distinct} ORDER BY {order} LIMIT {limit} OFFSET {offset}",
+            columns = columns.join(", "),
+            from = from.join(" "),
+            filters = and(&conditions[0]),
+            distinct = if distinct { " WHERE rn = 1" } else { "" },
+            order = order.join(", "),
+            limit = limit,
+            offset = offset,
+        ))
+    }
+
+    /// The term bound to variable `name`, as visible from the OPTIONAL group `scope`.
+    fn variable(&self, name: &str, scope: usize) -> TermSql {
+        let visible: Vec<&Site> = self
+            .sites
+            .get(name)
+            .map(|sites| sites.iter().filter(|s| s.group <= scope).collect())
+            .unwrap_or_default();
+
+        match visible.first() {
+            None => TermSql {
+                value: "NULL".into(),
+                datatype: "NULL".into(),
+                language: "NULL".into(),
+            },
+            Some(site) if site.group == 0 || visible.len() == 1 => site_term(site),
+            Some(_) => {
+                let case = |f: &dyn Fn(TermSql) -> String| {
+                    let whens = visible
+                        .iter()
+                        .map(|site| {
+                            format!(
+                                "WHEN p{}.id IS NOT NULL THEN {}",
+                                site.alias,
+                                f(site_term(site))
+                            )
+                        })
+                        .collect::<Vec<String>>();
+                    format!("CASE {} END", whens.join(" "))
+                };
+
+                TermSql {
+                    value: case(&|t| t.value),
+                    datatype: case(&|t| t.datatype),
+                    language: case(&|t| t.language),
+                }
+            }
+        }
+    }
+
+    fn compile(&self, expression: &Expression, scope: usize) -> Result<Sql, ErrorKind> {
+        let sql = match expression {
+            Expression::Term(Term::Variable(name)) => Sql::Term(self.variable(name, scope)),
+            Expression::Term(term) => Sql::Term(constant_term(term)),
+            Expression::And(a, b) => Sql::Condition(format!(
+                "({} AND {})",
+                to_condition(self.compile(a, scope)?),
+                to_condition(self.compile(b, scope)?)
+            )),
+            Expression::Or(a, b) => Sql::Condition(format!(
+                "({} OR {})",
+                to_condition(self.compile(a, scope)?),
+                to_condition(self.compile(b, scope)?)
+            )),
+            Expression::Not(a) => {
+                Sql::Condition(format!("(NOT {})", to_condition(self.compile(a, scope)?)))
+            }
+            Expression::Compare(a, comparison, b) => {
+                let is_numeric =
+                    |e: &Expression| matches!(e, Expression::Term(t) if t.is_numeric());
+                let numeric_comparison = is_numeric(a) || is_numeric(b);
+                let left = to_term(self.compile(a, scope)?);
+                let right = to_term(self.compile(b, scope)?);
+                let operator = match comparison {
+                    Comparison::Eq => "=",
+                    Comparison::Ne => "<>",
+                    Comparison::Lt => "<",
+                    Comparison::Gt => ">",
+                    Comparison::Le => "<=",
+                    Comparison::Ge => ">=",
+                };
+
+                let condition = if numeric_comparison {
+                    format!("({} {} {})", numeric(&left), operator, numeric(&right))
+                } else {
+                    match comparison {
+                        Comparison::Eq => term_equals(&left, &right),
+                        Comparison::Ne => format!("(NOT {})", term_equals(&left, &right)),
+                        _ => format!("({} {} {})", left.value, operator, right.value),
+                    }
+                };
+
+                Sql::Condition(condition)
+            }
+            Expression::Function(name, arguments) => self.function(name, arguments, scope)?,
+        };
+
+        Ok(sql)
+    }
+
+    fn function(
+        &self,
+        name: &str,
+        arguments: &[Expression],
+        scope: usize,
+    ) -> Result<Sql, ErrorKind> {
+        let arity = match name {
+            "BOUND" | "ISIRI" | "ISURI" | "ISBLANK" | "ISLITERAL" | "STR" | "LANG" | "DATATYPE"
+            | "LCASE" | "UCASE" => 1,
+            "CONTAINS" | "STRSTARTS" | "STRENDS" | "LANGMATCHES" | "SAMETERM" => 2,
+            "REGEX" if arguments.len() == 3 => 3,
+            "REGEX" => 2,
+            _ => bail!(ErrorKind::ParserError(format!(
+                "Unsupported function {}",
+                name
+            ))),
+        };
+        if arguments.len() != arity {
+            bail!(ErrorKind::ParserError(format!(
+                "{} expects {} arguments",
+                name, arity
+            )));
+        }
+
+        if name == "BOUND" {
+            let variable = match &arguments[0] {
+                Expression::Term(Term::Variable(variable)) => variable,
+                _ => bail!(ErrorKind::ParserError("BOUND expects a variable".into())),
+            };
+            let visible: Vec<&Site> = self
+                .sites
+                .get(variable)
+                .map(|sites| sites.iter().filter(|s| s.group <= scope).collect())
+                .unwrap_or_default();
+            let condition = if visible.iter().any(|s| s.group == 0) {
+                String::from("TRUE")
+            } else if visible.is_empty() {
+                String::from("FALSE")
+            } else {
+                let bound = visible
+                    .iter()
+                    .map(|s| format!("p{}.id IS NOT NULL", s.alias))
+                    .collect::<Vec<String>>();
+                format!("({})", bound.join(" OR "))
+            };
+
+            return Ok(Sql::Condition(condition));
+        }
+
+        let mut terms = vec![];
+        for argument in arguments {
+            terms.push(to_term(self.compile(argument, scope)?));
+        }
+        let literal = |i: usize| match &arguments[i] {
+            Expression::Term(Term::Literal { value, .. }) => Ok(value.clone()),
+            _ => Err(ErrorKind::ParserError(format!(
+                "{} expects a literal as argument {}",
+                name,
+                i + 1
+            ))),
+        };
+        let string = |value: String| TermSql {
+            value,
+            datatype: quote(STRING_IRI),
+            language: "''".into(),
+        };
+        let a = &terms[0];
+
+        let sql = match name {
+            "ISIRI" | "ISURI" => {
+                Sql::Condition(format!("({} = {})", a.datatype, quote(NAMED_NODE_IRI)))
+            }
+            "ISBLANK" => Sql::Condition(format!("({} = {})", a.datatype, quote(BLANK_NODE_IRI))),
+            "ISLITERAL" => Sql::Condition(format!(
+                "({} NOT IN ({}, {}))",
+                a.datatype,
+                quote(NAMED_NODE_IRI),
+                quote(BLANK_NODE_IRI)
+            )),
+            "STR" => Sql::Term(string(a.value.clone())),
+            "LANG" => Sql::Term(string(a.language.clone())),
+            "DATATYPE" => Sql::Term(TermSql {
+                value: a.datatype.clone(),
+                datatype: quote(NAMED_NODE_IRI),
+                language: "''".into(),
+            }),
+            "LCASE" | "UCASE" => Sql::Term(TermSql {
+                value: format!(
+                    "{}({})",
+                    if name == "LCASE" { "lower" } else { "upper" },
+                    a.value
+                ),
+                datatype: a.datatype.clone(),
+                language: a.language.clone(),
+            }),
+            "CONTAINS" => Sql::Condition(format!("(strpos({}, {}) > 0)", a.value, terms[1].value)),
+            "STRSTARTS" => Sql::Condition(format!(
+                "(left({a}, length({b})) = {b})",
+                a = a.value,
+                b = terms[1].value
+            )),
+            "STRENDS" => Sql::Condition(format!(
+                "(right({a}, length({b})) = {b})",
+                a = a.value,
+                b = terms[1].value
+            )),
+            "SAMETERM" => Sql::Condition(term_equals(a, &terms[1])),
+            "LANGMATCHES" => {
+                let range = literal(1)?.to_lowercase();
+                if range == "*" {
+                    Sql::Condition(format!("({} <> '')", a.value))
+                } else {
+                    Sql::Condition(format!(
+                        "(lower({a}) = {range} OR left(lower({a}), {length}) = {prefix})",
+                        a = a.value,
+                        range = quote(&range),
+                        length = range.chars().count() + 1,
+                        prefix = quote(&format!("{}-", range))
+                    ))
+                }
+            }
+            "REGEX" => {
+                let pattern = literal(1)?;
+                let operator = match arguments.get(2).map(|_| literal(2)).transpose()? {
+                    None => "~",
+                    Some(flags) if flags.is_empty() => "~",
+                    Some(flags) if flags == "i" => "~*",
+                    Some(flags) => bail!(ErrorKind::ParserError(format!(
+                        "Unsupported REGEX flags '{}'",
+                        flags
+                    ))),
+                };
+                Sql::Condition(format!("({} {} {})", a.value, operator, quote(&pattern)))
+            }
+            _ => unreachable!("Arity is checked above"),
+        };
+
+        Ok(sql)
+    }
+}
+
+/// The conditions for the constants in the triple pattern at `alias`.
+fn constant_conditions(db_ctx: &DbContext, alias: usize, triple: &TriplePattern) -> Vec<String> {
+    let mut conditions = vec![];
+    let never = String::from("FALSE");
+
+    match &triple.subject {
+        Term::Variable(_) => (),
+        Term::Iri(iri) => conditions.push(format!("r{}.iri = {}", alias, quote(iri))),
+        Term::Literal { .. } => conditions.push(never.clone()),
+    }
+
+    match &triple.predicate {
+        Term::Variable(_) => (),
+        Term::Iri(iri) => match db_ctx.property_map.get_by_left(iri) {
+            Some(id) => conditions.push(format!("p{}.predicate_id = {}", alias, id)),
+            None => conditions.push(never.clone()),
+        },
+        Term::Literal { .. } => conditions.push(never.clone()),
+    }
+
+    let (value, datatype, language) = match &triple.object {
+        Term::Variable(_) => return conditions,
+        Term::Iri(iri) => (iri.as_str(), NAMED_NODE_IRI, ""),
+        Term::Literal {
+            value,
+            datatype,
+            language,
+        } => (value.as_str(), datatype.as_str(), language.as_str()),
+    };
+    let hash = Uu128::from(db_ctx.lookup_table.calculate_hash(value));
+    conditions.push(format!("p{}.object_id = '{}'", alias, hash));
+    match db_ctx.datatype_map.get_by_left(&datatype.to_string()) {
+        Some(id) => conditions.push(format!("p{}.datatype_id = {}", alias, id)),
+        None => conditions.push(never.clone()),
+    }
+    if !language.is_empty() {
+        match db_ctx.language_map.get_by_left(&language.to_string()) {
+            Some(id) => conditions.push(format!("p{}.language_id = {}", alias, id)),
+            None => conditions.push(never),
+        }
+    }
+
+    conditions
+}
+
+/// The conditions for `site` to bind the same term as the `previous` sites of its variable.
+fn join_conditions(previous: &[Site], site: &Site) -> Vec<String> {
+    let mut conditions = vec![];
+    let mut compared_groups = HashSet::new();
+
+    for other in previous {
+        if !compared_groups.insert(other.group) {
+            continue;
+        }
+        if site.group == 0 && other.group != 0 {
+            continue;
+        }
+
+        let condition = site_equals(other, site);
+        if other.group == 0 || other.group == site.group {
+            conditions.push(condition);
+        } else {
+            // Variables bound in an unmatched OPTIONAL group don't constrain other groups.
+            conditions.push(format!("(p{}.id IS NULL OR {})", other.alias, condition));
+        }
+    }
+
+    conditions
+}
+
+fn site_equals(a: &Site, b: &Site) -> String {
+    if a.position != b.position {
+        return term_equals(&site_term(a), &site_term(b));
+    }
+
+    match a.position {
+        Position::Subject => format!("r{}.iri = r{}.iri", a.alias, b.alias),
+        Position::Predicate => format!("p{}.predicate_id = p{}.predicate_id", a.alias, b.alias),
+        _ => format!(
+            "p{a}.object_id = p{b}.object_id AND p{a}.datatype_id = p{b}.datatype_id AND p{a}.language_id IS NOT DISTINCT FROM p{b}.language_id",
+            a = a.alias,
+            b = b.alias
+        ),
+    }
+}
+
+fn site_term(site: &Site) -> TermSql {
+    let a = site.alias;
+
+    match site.position {
+        Position::Subject => TermSql {
+            value: format!("r{}.iri", a),
+            datatype: format!(
+                "CASE WHEN strpos(r{a}.iri, ':') > 0 THEN {} WHEN strpos(r{a}.iri, ':') = 0 THEN {} END",
+                quote(NAMED_NODE_IRI),
+                quote(BLANK_NODE_IRI),
+                a = a
+            ),
+            language: format!("CASE WHEN p{}.id IS NOT NULL THEN '' END", a),
+        },
+        Position::Predicate => TermSql {
+            value: format!("(SELECT value FROM predicates WHERE id = p{}.predicate_id)", a),
+            datatype: format!("CASE WHEN p{}.id IS NOT NULL THEN {} END", a, quote(NAMED_NODE_IRI)),
+            language: format!("CASE WHEN p{}.id IS NOT NULL THEN '' END", a),
+        },
+        _ => TermSql {
+            value: format!("(SELECT value FROM objects WHERE hash = p{}.object_id)", a),
+            datatype: format!("(SELECT value FROM datatypes WHERE id = p{}.datatype_id)", a),
+            language: format!(
+                "CASE WHEN p{a}.id IS NOT NULL THEN COALESCE((SELECT value FROM languages WHERE id = p{a}.language_id), '') END",
+                a = a
+            ),
+        },
+    }
+}
+
+fn constant_term(term: &Term) -> TermSql {
+    match term {
+        Term::Iri(iri) => TermSql {
+            value: quote(iri),
+            datatype: quote(NAMED_NODE_IRI),
+            language: "''".into(),
+        },
+        Term::Literal {
+            value,
+            datatype,
+            language,
+        } => TermSql {
+            value: quote(value),
+            datatype: quote(datatype),
+            language: quote(language),
+        },
+        Term::Variable(_) => unreachable!("Variables are resolved by the query"),
+    }
+}
+
+fn term_equals(a: &TermSql, b: &TermSql) -> String {
+    format!(
+        "({} = {} AND {} = {} AND lower({}) = lower({}))",
+        a.value, b.value, a.datatype, b.datatype, a.language, b.language
+    )
+}
+
+/// The numeric value of `term`, NULL if it isn't numeric.
+fn numeric(term: &TermSql) -> String {
+    let datatypes = NUMERIC_DATATYPES
+        .iter()
+        .map(|datatype| quote(datatype))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    format!(
+        "CASE WHEN {datatype} IN ({datatypes}) AND {value} ~ '{pattern}' THEN ({value})::numeric END",
+        datatype = term.datatype,
+        datatypes = datatypes,
+        value = term.value,
+        pattern = NUMERIC_PATTERN
+    )
+}
+
+fn to_condition(sql: Sql) -> String {
+    match sql {
+        Sql::Condition(condition) => condition,
+        // The effective boolean value, only booleans are supported.
+        Sql::Term(term) => format!(
+            "({} = {} AND {} IN ('true', '1'))",
+            term.datatype,
+            quote(XSD_BOOLEAN_IRI),
+            term.value
+        ),
+    }
+}
+
+fn to_term(sql: Sql) -> TermSql {
+    match sql {
+        Sql::Term(term) => term,
+        Sql::Condition(condition) => TermSql {
+            value: format!(
+                "CASE WHEN {c} THEN 'true' WHEN NOT {c} THEN 'false' END",
+                c = condition
+            ),
+            datatype: quote(XSD_BOOLEAN_IRI),
+            language: "''".into(),
+        },
+    }
+}
+
+fn and(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::from("TRUE")
+    } else {
+        conditions.join(" AND ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_conditions_with_optionals() {
+        let site = |alias, group, position| Site {
+            alias,
+            group,
+            position,
+        };
+        let mandatory = site(0, 0, Position::Subject);
+        let first_optional = site(1, 1, Position::Subject);
+        let second_optional = site(2, 2, Position::Subject);
+
+        assert_eq!(
+            join_conditions(&[mandatory, first_optional], &second_optional),
+            vec![
+                String::from("r0.iri = r2.iri"),
+                String::from("(p1.id IS NULL OR r1.iri = r2.iri)"),
+            ]
+        );
+    }
+}
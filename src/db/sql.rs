@@ -0,0 +1,43 @@
+//! Helpers shared by the queries which are built as SQL text
+
+use crate::rdf::sparql::{XSD_DECIMAL_IRI, XSD_DOUBLE_IRI, XSD_INTEGER_IRI};
+
+pub(crate) const NUMERIC_DATATYPES: [&str; 13] = [
+    XSD_INTEGER_IRI,
+    XSD_DECIMAL_IRI,
+    XSD_DOUBLE_IRI,
+    "http://www.w3.org/2001/XMLSchema#float",
+    "http://www.w3.org/2001/XMLSchema#long",
+    "http://www.w3.org/2001/XMLSchema#int",
+    "http://www.w3.org/2001/XMLSchema#short",
+    "http://www.w3.org/2001/XMLSchema#byte",
+    "http://www.w3.org/2001/XMLSchema#nonNegativeInteger",
+    "http://www.w3.org/2001/XMLSchema#positiveInteger",
+    "http://www.w3.org/2001/XMLSchema#nonPositiveInteger",
+    "http://www.w3.org/2001/XMLSchema#negativeInteger",
+    "http://www.w3.org/2001/XMLSchema#unsignedInt",
+];
+
+/// A SQL string literal for `value`, quoted like postgres' `quote_literal`.
+///
+/// All values interpolated into queries are quoted here. The result is a constant to the planner,
+/// which it needs to use the prefix index for `LIKE`.
+pub(crate) fn quote(value: &str) -> String {
+    let quoted = value.replace('\'', "''");
+    if quoted.contains('\\') {
+        format!("E'{}'", quoted.replace('\\', "\\\\"))
+    } else {
+        format!("'{}'", quoted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(quote("a\\b'"), "E'a\\\\b'''");
+    }
+}
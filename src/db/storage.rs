@@ -0,0 +1,508 @@
+//! Storage backends
+//!
+//! A backend opens a context per unit of work, which holds the lookup table the statements it
+//! returns and accepts are hashed with.
+
+use crate::app_config::{AppConfig, HistoryRetention, StorageBackend};
+use crate::db::db_context::{DbContext, DbPool};
+use crate::db::embedded::SledStorage;
+use crate::db::memory::MemoryStorage;
+use crate::db::models::Document;
+use crate::db::postgres::PgStorage;
+use crate::errors::ErrorKind;
+use crate::hashtuple::{
+    HashModel, LookupTable, Statement, LANG_STRING_IRI, NAMED_NODE_IRI, STRING_IRI,
+};
+use crate::models;
+use chrono::{Duration, NaiveDateTime, SubsecRound};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub(crate) trait Storage: Send + Sync {
+    fn open(&self, lang: Option<String>) -> Box<dyn StorageContext + '_>;
+
+    /// The connection pool, for the endpoints which query Postgres directly.
+    ///
+    /// Without a pool only documents and triple pattern fragments are served.
+    fn pool(&self) -> Option<&DbPool> {
+        None
+    }
+}
+
+pub(crate) trait StorageContext {
+    fn lookup_table(&mut self) -> &mut LookupTable;
+
+    fn into_lookup_table(self: Box<Self>) -> LookupTable;
+
+    /// The document with its statements, `EmptyDocument` if it isn't stored.
+    fn get_document(&mut self, iri: &str) -> Result<(Document, HashModel), ErrorKind>;
+
+    fn random_document(&mut self) -> Result<(Document, HashModel), ErrorKind>;
+
+    /// Replaces the statements of the document with the result of `update`, creating the
+    /// document if it doesn't exist yet.
+    ///
+    /// Returns whether anything changed.
+    fn update_document(
+        &mut self,
+        iri: &str,
+        update: &mut dyn FnMut(&LookupTable, &HashModel) -> HashModel,
+    ) -> Result<bool, ErrorKind>;
+
+    fn delete_document(&mut self, iri: &str) -> Result<(), ErrorKind>;
+
+    /// Removes all documents, their revisions are kept.
+    fn delete_all(&mut self) -> Result<(), ErrorKind>;
+
+    fn update_cache_control(&mut self, docs: &[models::Document]) -> Result<(), ErrorKind>;
+
+    /// The (estimated) amount of stored documents.
+    fn count_documents(&mut self) -> i64;
+
+    /// A page of the statements matching the pattern.
+    fn match_pattern(&mut self, pattern: &Pattern) -> Result<PatternPage, ErrorKind>;
+
+    /// The recorded revisions of the document, oldest first.
+    fn revisions(&mut self, iri: &str) -> Result<Vec<Revision>, ErrorKind>;
+
+    /// The statements of the document as of the selected revision, `NotFound` if none matches.
+    fn get_revision(
+        &mut self,
+        iri: &str,
+        selector: &RevisionSelector,
+    ) -> Result<(Revision, HashModel), ErrorKind>;
+
+    /// Adds an event for the document to the outbox, stamped with its latest `updated_at`.
+    fn enqueue_event(
+        &mut self,
+        iri: &str,
+        operation: Operation,
+        delta: Option<String>,
+    ) -> Result<(), ErrorKind>;
+
+    /// The oldest events in the outbox by their id.
+    fn pending_events(&mut self, limit: usize) -> Result<Vec<(i64, OutboxEvent)>, ErrorKind>;
+
+    /// Removes relayed events from the outbox.
+    fn remove_events(&mut self, ids: &[i64]) -> Result<(), ErrorKind>;
+
+    /// Runs `work` atomically if the backend supports it.
+    ///
+    /// Postgres runs `work` in a transaction which is rolled back when it fails. The memory and
+    /// sled backends run it as is, so the writes made before a failure are kept.
+    fn transaction(
+        &mut self,
+        work: &mut dyn FnMut(&mut dyn StorageContext) -> Result<(), ErrorKind>,
+    ) -> Result<(), ErrorKind>;
+}
+
+/// A triple pattern in the syntax of triple pattern fragments, unbound terms match anything.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Pattern {
+    pub subject: Option<String>,
+    pub predicate: Option<String>,
+    /// An IRI or a quoted literal, optionally with a datatype or language.
+    pub object: Option<String>,
+    /// The IRI of the document the statements should belong to.
+    pub document: Option<String>,
+    /// The cursor of the page, 0 for the first page.
+    pub page: i64,
+    pub page_size: i64,
+}
+
+impl Pattern {
+    /// The indices of the matches on the page, with the cursors of the adjacent pages.
+    pub fn page_range(&self, count: usize) -> (Range<usize>, Option<i64>, Option<i64>) {
+        let from = (self.page.max(0) as usize).min(count);
+        let page_size = self.page_size.max(1) as usize;
+        let to = (from + page_size).min(count);
+
+        let next = if to < count { Some(to as i64) } else { None };
+        let previous = if from > 0 {
+            Some(from.saturating_sub(page_size) as i64)
+        } else {
+            None
+        };
+
+        (from..to, next, previous)
+    }
+}
+
+pub(crate) struct PatternPage {
+    pub statements: HashModel,
+    pub next: Option<i64>,
+    pub previous: Option<i64>,
+    /// The (estimated) amount of statements matching the pattern.
+    pub count: i64,
+}
+
+/// A recorded state of a document, versions are numbered per IRI.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Revision {
+    pub version: i64,
+    pub language: String,
+    pub recorded_at: NaiveDateTime,
+    /// Whether the document was deleted in this revision.
+    pub deleted: bool,
+}
+
+pub(crate) enum RevisionSelector {
+    Version(i64),
+    /// The latest revision recorded at or before the moment.
+    At(NaiveDateTime),
+}
+
+/// A committed change to a document, kept in the outbox until it's relayed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OutboxEvent {
+    pub iri: String,
+    pub operation: Operation,
+    pub updated_at: NaiveDateTime,
+    /// The applied delta as hextuples, if configured.
+    pub delta: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Operation {
+    Update,
+    /// The delta left the document empty.
+    Delete,
+}
+
+impl Operation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "update" => Ok(Operation::Update),
+            "delete" => Ok(Operation::Delete),
+            _ => Err(ErrorKind::Unexpected(format!("Unknown operation {}", s))),
+        }
+    }
+}
+
+/// The statements of a document by value, independent of the hash seed.
+pub(crate) type Snapshot = Vec<[String; 6]>;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RevisionRecord {
+    pub revision: Revision,
+    pub statements: Snapshot,
+}
+
+pub(crate) fn snapshot(table: &LookupTable, model: &[Statement]) -> Snapshot {
+    let value = |id| table.get_by_hash(id).cloned().unwrap_or_default();
+
+    model
+        .iter()
+        .map(|s| {
+            [
+                value(s.subject),
+                value(s.predicate),
+                value(s.value),
+                value(s.datatype),
+                value(s.language),
+                value(s.graph),
+            ]
+        })
+        .collect()
+}
+
+pub(crate) fn restore(table: &mut LookupTable, snapshot: &[[String; 6]]) -> HashModel {
+    snapshot
+        .iter()
+        .map(|s| {
+            Statement::new(
+                table.ensure_value(&s[0]),
+                table.ensure_value(&s[1]),
+                table.ensure_value(&s[2]),
+                table.ensure_value(&s[3]),
+                table.ensure_value(&s[4]),
+                table.ensure_value(&s[5]),
+            )
+        })
+        .collect()
+}
+
+/// The revision in the language, if any, matching the selector. Moments are compared in whole
+/// seconds, the precision of the Accept-Datetime and Memento-Datetime headers.
+pub(crate) fn select_revision(
+    revisions: &[Revision],
+    lang: &Option<String>,
+    selector: &RevisionSelector,
+) -> Option<Revision> {
+    let mut candidates = revisions
+        .iter()
+        .filter(|r| lang.iter().all(|lang| *lang == r.language));
+
+    match selector {
+        RevisionSelector::Version(version) => candidates.find(|r| r.version == *version),
+        RevisionSelector::At(moment) => candidates
+            .filter(|r| r.recorded_at.trunc_subsecs(0) <= moment.trunc_subsecs(0))
+            .max_by_key(|r| r.version),
+    }
+    .cloned()
+}
+
+/// The versions of the revisions which fall outside the retention, the latest is always kept.
+pub(crate) fn expired_revisions(
+    retention: &HistoryRetention,
+    revisions: &[Revision],
+    now: NaiveDateTime,
+) -> Vec<i64> {
+    let latest = revisions.iter().map(|r| r.version).max();
+    let excess = revisions.len().saturating_sub(retention.max_versions);
+    let cutoff = retention
+        .max_age_days
+        .map(|days| now - Duration::days(days));
+
+    revisions
+        .iter()
+        .enumerate()
+        .filter(|(i, r)| *i < excess || cutoff.iter().any(|cutoff| r.recorded_at < *cutoff))
+        .map(|(_, r)| r.version)
+        .filter(|version| Some(*version) != latest)
+        .collect()
+}
+
+/// The value of the term if it's bound, variables and empty terms match anything.
+pub(crate) fn bound_term(term: &Option<String>) -> Option<&str> {
+    match term.as_deref() {
+        None | Some("") => None,
+        Some(term) if term.starts_with('?') => None,
+        Some(term) => Some(term),
+    }
+}
+
+/// The value, datatype and language of the object term, `None` if it's unbound.
+pub(crate) fn parse_object(object: &Option<String>) -> Result<Option<[String; 3]>, ErrorKind> {
+    let object = match bound_term(object) {
+        Some(object) => object,
+        None => return Ok(None),
+    };
+
+    if !object.starts_with('"') {
+        return Ok(Some([object.into(), NAMED_NODE_IRI.into(), String::new()]));
+    }
+
+    let end = match object[1..].find('"') {
+        Some(end) => end + 1,
+        None => {
+            return Err(ErrorKind::ParserError(String::from(
+                "Invalid object parameter format",
+            )))
+        }
+    };
+    let value = object[1..end].to_string();
+    let suffix = &object[end + 1..];
+
+    let term = if let Some(datatype) = suffix.strip_prefix("^^") {
+        [value, datatype.into(), String::new()]
+    } else if let Some(language) = suffix.strip_prefix('@') {
+        [value, LANG_STRING_IRI.into(), language.into()]
+    } else {
+        [value, STRING_IRI.into(), String::new()]
+    };
+
+    Ok(Some(term))
+}
+
+/// Creates the storage backend selected by `STORAGE_BACKEND`.
+pub(crate) fn storage_from_config(config: &AppConfig) -> Result<Arc<dyn Storage>, String> {
+    let retention = config.history_retention.clone();
+
+    match &config.storage_backend {
+        StorageBackend::Postgres => {
+            let pool =
+                DbContext::default_pool(config.database_url.clone(), config.database_pool_size)?;
+
+            Ok(Arc::new(PgStorage::new(pool, retention)))
+        }
+        StorageBackend::Memory => Ok(Arc::new(MemoryStorage::new(retention))),
+        StorageBackend::Sled(path) => Ok(Arc::new(
+            SledStorage::new(path, retention).map_err(|e| e.to_string())?,
+        )),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn name(ctx: &mut dyn StorageContext, value: &str) -> Statement {
+        let table = ctx.lookup_table();
+
+        Statement::new(
+            table.ensure_value("https://example.com/a"),
+            table.ensure_value("http://schema.org/name"),
+            table.ensure_value(value),
+            table.ensure_value(STRING_IRI),
+            table.ensure_value(""),
+            table.ensure_value(""),
+        )
+    }
+
+    #[test]
+    fn test_select_revision() {
+        let at = |second, micro| {
+            chrono::NaiveDate::from_ymd_opt(2020, 11, 4)
+                .and_then(|date| date.and_hms_micro_opt(9, 30, second, micro))
+                .unwrap()
+        };
+        let revision = |version, language: &str, recorded_at| Revision {
+            version,
+            language: String::from(language),
+            recorded_at,
+            deleted: false,
+        };
+        let revisions = vec![
+            revision(1, "en", at(10, 0)),
+            revision(2, "nl", at(15, 500_000)),
+            revision(3, "en", at(15, 700_000)),
+            revision(4, "en", at(16, 0)),
+        ];
+        let select = |lang: Option<&str>, selector| {
+            select_revision(&revisions, &lang.map(String::from), &selector).map(|r| r.version)
+        };
+
+        // The header has no fraction, so revisions within the requested second match.
+        assert_eq!(select(None, RevisionSelector::At(at(15, 0))), Some(3));
+        assert_eq!(select(Some("nl"), RevisionSelector::At(at(15, 0))), Some(2));
+        assert_eq!(select(None, RevisionSelector::At(at(14, 0))), Some(1));
+        assert_eq!(select(None, RevisionSelector::At(at(9, 0))), None);
+        assert_eq!(select(Some("en"), RevisionSelector::Version(2)), None);
+        assert_eq!(select(None, RevisionSelector::Version(2)), Some(2));
+    }
+
+    /// Exercises storing, reading and matching documents.
+    pub(crate) fn check_documents(storage: &dyn Storage) {
+        let mut ctx = storage.open(Some(String::from("en")));
+        let count = ctx.count_documents();
+        let statement = name(ctx.as_mut(), "A");
+
+        let mut put = |_: &LookupTable, _: &HashModel| vec![statement, statement];
+        assert!(ctx
+            .update_document("https://example.com/a", &mut put)
+            .unwrap());
+        assert!(!ctx
+            .update_document("https://example.com/a", &mut put)
+            .unwrap());
+
+        let mut other = storage.open(None);
+        let (doc, model) = other.get_document("https://example.com/a").unwrap();
+        // Postgres pads the language to the width of its column.
+        assert_eq!(doc.language.trim_end(), "en");
+        assert_eq!(model, vec![statement]);
+        assert_eq!(other.count_documents(), count + 1);
+
+        let page = other
+            .match_pattern(&Pattern {
+                object: Some(String::from("\"A\"")),
+                page_size: 10,
+                ..Pattern::default()
+            })
+            .unwrap();
+        assert_eq!(page.count, 1);
+        assert_eq!(page.statements, vec![statement]);
+        assert_eq!(
+            other.lookup_table().get_by_hash(statement.value).unwrap(),
+            "A"
+        );
+
+        let page = other
+            .match_pattern(&Pattern {
+                subject: Some(String::from("https://example.com/b")),
+                page_size: 10,
+                ..Pattern::default()
+            })
+            .unwrap();
+        assert_eq!(page.count, 0);
+
+        // Postgres keeps the emptied document, so only its statements are checked.
+        other.delete_document("https://example.com/a").unwrap();
+        match other.get_document("https://example.com/a") {
+            Ok((_, model)) => assert!(model.is_empty()),
+            Err(e) => assert!(matches!(e, ErrorKind::EmptyDocument)),
+        }
+    }
+
+    /// Exercises the history of a document, the retention of the tests is 2 versions.
+    pub(crate) fn check_revisions(storage: &dyn Storage) {
+        let mut ctx = storage.open(Some(String::from("en")));
+        let statement = name(ctx.as_mut(), "A");
+        let renamed = name(ctx.as_mut(), "B");
+        for model in &[statement, renamed] {
+            let mut put = |_: &LookupTable, _: &HashModel| vec![*model];
+            assert!(ctx
+                .update_document("https://example.com/a", &mut put)
+                .unwrap());
+        }
+
+        let mut other = storage.open(None);
+        let (revision, model) = other
+            .get_revision("https://example.com/a", &RevisionSelector::Version(1))
+            .unwrap();
+        assert_eq!(revision.language.trim_end(), "en");
+        assert_eq!(model, vec![statement]);
+
+        other.delete_document("https://example.com/a").unwrap();
+
+        // The oldest revision is pruned.
+        let revisions = other.revisions("https://example.com/a").unwrap();
+        let versions: Vec<(i64, bool)> = revisions.iter().map(|r| (r.version, r.deleted)).collect();
+        assert_eq!(versions, vec![(2, false), (3, true)]);
+
+        let (revision, model) = other
+            .get_revision(
+                "https://example.com/a",
+                &RevisionSelector::At(revisions[1].recorded_at),
+            )
+            .unwrap();
+        assert_eq!(revision.version, 3);
+        assert!(model.is_empty());
+
+        let (_, model) = other
+            .get_revision("https://example.com/a", &RevisionSelector::Version(2))
+            .unwrap();
+        assert_eq!(model, vec![renamed]);
+        assert_eq!(
+            other.lookup_table().get_by_hash(renamed.value).unwrap(),
+            "B"
+        );
+    }
+
+    /// Exercises queueing and relaying change events.
+    pub(crate) fn check_outbox(storage: &dyn Storage) {
+        let mut ctx = storage.open(None);
+        ctx.enqueue_event("https://example.com/a", Operation::Update, None)
+            .unwrap();
+        ctx.enqueue_event(
+            "https://example.com/b",
+            Operation::Delete,
+            Some(String::from("[]\n")),
+        )
+        .unwrap();
+        let pending = ctx.pending_events(10).unwrap();
+        let iris: Vec<&str> = pending.iter().map(|(_, e)| e.iri.as_str()).collect();
+        assert_eq!(iris, vec!["https://example.com/a", "https://example.com/b"]);
+        assert_eq!(pending[1].1.operation, Operation::Delete);
+        assert_eq!(pending[1].1.delta.as_deref(), Some("[]\n"));
+
+        ctx.remove_events(&[pending[0].0]).unwrap();
+        let pending = ctx.pending_events(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.iri, "https://example.com/b");
+    }
+}
@@ -1,3 +1,4 @@
 pub mod delta_processor;
 pub mod processor;
 pub mod processors;
+pub mod registry;
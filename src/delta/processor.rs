@@ -1,38 +1,28 @@
 use crate::delta::delta_processor::DeltaProcessor;
-use crate::delta::processors::add_processor::AddProcessor;
-use crate::delta::processors::invalidate_processor::InvalidateProcessor;
-use crate::delta::processors::replace_processor::ReplaceProcessor;
-use crate::delta::processors::supplant_processor::SupplantProcessor;
+use crate::delta::registry::{registry, ProcessorRegistry};
 use crate::hashtuple::{HashModel, LookupTable, Statement};
 use crate::importing::events::DeltaProcessingTiming;
 use crate::serving::serialization::{
     hash_model_to_hextuples, hashtuple_to_hextuple, hextuple_to_utf8,
 };
+use std::collections::HashMap;
 use std::time::Instant;
 
+pub const SP_VARIABLE: &str = "http://spinrdf.org/sp#Variable";
+
 pub(crate) trait ProcessorInitializer {
     fn initialize(lookup_table: &mut LookupTable);
 }
 
+/// The processors from the registry, see `registry::register_processor`.
 pub fn default_processors<'a>(
     lookup_table: &'a LookupTable,
 ) -> Vec<Box<dyn DeltaProcessor<'a> + 'a>> {
-    vec![
-        Box::new(SupplantProcessor::<'a> { lookup_table }),
-        Box::new(AddProcessor::<'a> { lookup_table }),
-        Box::new(ReplaceProcessor::<'a> { lookup_table }),
-        Box::new(InvalidateProcessor::<'a> { lookup_table }),
-        //         RemoveProcessor {},
-        //         PurgeProcessor {},
-        //         SliceProcessor {},
-    ]
+    registry().processors(lookup_table)
 }
 
 pub fn add_processor_methods_to_table(lookup_table: &mut LookupTable) {
-    AddProcessor::initialize(lookup_table);
-    ReplaceProcessor::initialize(lookup_table);
-    SupplantProcessor::initialize(lookup_table);
-    InvalidateProcessor::initialize(lookup_table);
+    registry().initialize(lookup_table);
 }
 
 /// FIXME: Be sure to call `add_processor_methods_to_table` on the `lookup_table` beforehand.
@@ -40,10 +30,20 @@ pub fn apply_delta(
     lookup_table: &LookupTable,
     current: &HashModel,
     delta: &HashModel,
+) -> (HashModel, DeltaProcessingTiming) {
+    apply_delta_with(&registry(), lookup_table, current, delta)
+}
+
+/// Applies the delta with the processors of `registry`, its operators must be in `lookup_table`.
+pub(crate) fn apply_delta_with(
+    registry: &ProcessorRegistry,
+    lookup_table: &LookupTable,
+    current: &HashModel,
+    delta: &HashModel,
 ) -> (HashModel, DeltaProcessingTiming) {
     let mut timing = DeltaProcessingTiming::new();
     let setup_start = Instant::now();
-    let processors = default_processors(lookup_table);
+    let processors = registry.processors(lookup_table);
 
     let mut result = current.clone();
     let mut addable: HashModel = vec![];
@@ -53,13 +53,41 @@ pub fn apply_delta(
     let setup_end = Instant::now();
     timing.setup_time = setup_end.duration_since(setup_start);
 
+    let default_graph = lookup_table.calculate_hash("");
+    let mut in_named_graph: HashMap<u128, HashModel> = HashMap::new();
+
     for statement in delta {
+        let (operator, named_graph) = operator_and_graph(lookup_table, statement.graph);
+        let graph = named_graph.unwrap_or(default_graph);
+        let statement = &Statement {
+            graph: operator,
+            ..*statement
+        };
+        let in_graph = |s: Statement| {
+            if s.graph == operator {
+                Statement { graph, ..s }
+            } else {
+                s
+            }
+        };
+        // Operators on a named graph only see the statements in that graph.
+        let scope = match named_graph {
+            Some(named_graph) => in_named_graph.entry(named_graph).or_insert_with(|| {
+                current
+                    .iter()
+                    .filter(|s| s.graph == named_graph)
+                    .cloned()
+                    .collect()
+            }),
+            None => current,
+        };
+
         match processors.iter().find(|p| p.matches(*statement)) {
             Some(processor) => {
-                let (adds, replaces, removes) = processor.process(current, &delta, *statement);
+                let (adds, replaces, removes) = processor.process(scope, &delta, *statement);
 
-                addable.extend(adds);
-                replaceable.extend(replaces);
+                addable.extend(adds.into_iter().map(in_graph));
+                replaceable.extend(replaces.into_iter().map(in_graph));
                 removable.extend(removes);
             }
             None => {
@@ -82,7 +110,7 @@ pub fn apply_delta(
     timing.remove_time = remove_end.duration_since(sort_end);
 
     if !replaceable.is_empty() {
-        result = replace_matches(&mut result, &replaceable);
+        result = replace_matches(&result, &replaceable);
     }
     let replace_end = Instant::now();
     timing.replace_time = replace_end.duration_since(remove_end);
@@ -97,6 +125,21 @@ pub fn apply_delta(
     (result, timing)
 }
 
+/// Splits the graph of a delta statement (`<operator>?graph=<named graph>`) into the hashes of the
+/// operator and the named graph the statement belongs to, `None` without a named graph.
+fn operator_and_graph(lookup_table: &LookupTable, graph: u128) -> (u128, Option<u128>) {
+    match lookup_table.get_by_hash(graph) {
+        Some(value) => match value.find("?graph=") {
+            Some(i) => (
+                lookup_table.calculate_hash(&value[..i]),
+                Some(lookup_table.calculate_hash(&value[i + "?graph=".len()..])),
+            ),
+            None => (graph, None),
+        },
+        None => (graph, None),
+    }
+}
+
 fn remove_all(cur: &HashModel, patch: &HashModel) -> HashModel {
     let mut next = vec![];
     for h in cur {
@@ -108,19 +151,21 @@ fn remove_all(cur: &HashModel, patch: &HashModel) -> HashModel {
     next
 }
 
-fn replace_matches(cur: &mut HashModel, patch: &HashModel) -> HashModel {
-    let mut cleaned: HashModel = Vec::with_capacity(patch.len());
-    for st in patch {
-        match cur
-            .iter()
-            .find(|x| x.subject == st.subject && x.predicate == st.predicate)
-        {
-            Some(patch_value) => cleaned.push(*patch_value),
-            None => cleaned.push(*st),
-        }
-    }
+/// Replaces all current values of the (subject, predicate, graph) triples in `patch` with the patch
+/// values.
+fn replace_matches(cur: &HashModel, patch: &HashModel) -> HashModel {
+    let mut next: HashModel = cur
+        .iter()
+        .filter(|x| {
+            !patch.iter().any(|st| {
+                x.subject == st.subject && x.predicate == st.predicate && x.graph == st.graph
+            })
+        })
+        .cloned()
+        .collect();
+    next.extend(patch);
 
-    cleaned
+    next
 }
 
 fn add_all(cur: &mut HashModel, patch: &HashModel) {
@@ -140,7 +185,7 @@ fn contains(model: &HashModel, h: &Statement) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hashtuple::STRING_IRI;
+    use crate::hashtuple::{LANG_STRING_IRI, STRING_IRI};
 
     #[test]
     fn test_apply_delta_with_replace() {
@@ -178,6 +223,245 @@ mod tests {
         assert_eq!(patch.len(), 4);
     }
 
+    #[test]
+    fn test_apply_delta_with_remove() {
+        let mut lookup_table = LookupTable::new(1);
+        add_processor_methods_to_table(&mut lookup_table);
+        let named_node = lookup_table.ensure_value(&String::from("rdf:namedNode"));
+        let string = lookup_table.ensure_value(&String::from(STRING_IRI));
+        let var = lookup_table.ensure_value(&String::from("http://spinrdf.org/sp#Variable"));
+        let remove =
+            lookup_table.ensure_value(&String::from("http://purl.org/linked-delta/remove"));
+
+        let name = lookup_table.ensure_value(&String::from("https://schema.org/name"));
+        let homepage = lookup_table.ensure_value(&String::from("https://schema.org/homepage"));
+
+        let id = lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234"));
+        let other =
+            lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/5678"));
+        let bob = lookup_table.ensure_value(&String::from("Bob"));
+        let alice = lookup_table.ensure_value(&String::from("Alice"));
+        let empty = lookup_table.ensure_value("");
+        let bobs_homepage = lookup_table.ensure_value(&String::from("https://bob.com"));
+
+        let cur: HashModel = vec![
+            Statement::new(id, name, bob, string, empty, empty),
+            Statement::new(id, homepage, bobs_homepage, named_node, empty, empty),
+            Statement::new(other, name, alice, string, empty, empty),
+        ];
+
+        let exact_patch: HashModel = vec![Statement::new(id, name, bob, string, empty, remove)];
+        let (out, _) = apply_delta(&lookup_table, &cur, &exact_patch);
+        assert_eq!(out, vec![cur[1], cur[2]]);
+
+        let unmatched_patch: HashModel =
+            vec![Statement::new(id, name, alice, string, empty, remove)];
+        let (out, _) = apply_delta(&lookup_table, &cur, &unmatched_patch);
+        assert_eq!(out, cur);
+
+        let subject_patch: HashModel = vec![Statement::new(id, var, var, string, empty, remove)];
+        let (out, _) = apply_delta(&lookup_table, &cur, &subject_patch);
+        assert_eq!(out, vec![cur[2]]);
+
+        let predicate_patch: HashModel =
+            vec![Statement::new(var, name, var, string, empty, remove)];
+        let (out, _) = apply_delta(&lookup_table, &cur, &predicate_patch);
+        assert_eq!(out, vec![cur[1]]);
+
+        let object_patch: HashModel = vec![Statement::new(var, var, alice, string, empty, remove)];
+        let (out, _) = apply_delta(&lookup_table, &cur, &object_patch);
+        assert_eq!(out, vec![cur[0], cur[1]]);
+    }
+
+    #[test]
+    fn test_apply_delta_with_purge() {
+        let mut lookup_table = LookupTable::new(1);
+        add_processor_methods_to_table(&mut lookup_table);
+        let named_node = lookup_table.ensure_value(&String::from("rdf:namedNode"));
+        let string = lookup_table.ensure_value(&String::from(STRING_IRI));
+        let var = lookup_table.ensure_value(&String::from("http://spinrdf.org/sp#Variable"));
+        let purge = lookup_table.ensure_value(&String::from("http://purl.org/linked-delta/purge"));
+
+        let name = lookup_table.ensure_value(&String::from("https://schema.org/name"));
+        let homepage = lookup_table.ensure_value(&String::from("https://schema.org/homepage"));
+
+        let id = lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234"));
+        let other =
+            lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/5678"));
+        let bob = lookup_table.ensure_value(&String::from("Bob"));
+        let alice = lookup_table.ensure_value(&String::from("Alice"));
+        let empty = lookup_table.ensure_value("");
+        let bobs_homepage = lookup_table.ensure_value(&String::from("https://bob.com"));
+
+        let cur: HashModel = vec![
+            Statement::new(id, name, bob, string, empty, empty),
+            Statement::new(id, homepage, bobs_homepage, named_node, empty, empty),
+            Statement::new(other, name, alice, string, empty, empty),
+        ];
+
+        let subject_patch: HashModel = vec![Statement::new(id, var, var, named_node, empty, purge)];
+        let (out, _) = apply_delta(&lookup_table, &cur, &subject_patch);
+        assert_eq!(out, vec![cur[2]]);
+
+        let predicate_patch: HashModel =
+            vec![Statement::new(id, name, var, named_node, empty, purge)];
+        let (out, _) = apply_delta(&lookup_table, &cur, &predicate_patch);
+        assert_eq!(out, vec![cur[1], cur[2]]);
+
+        let object_patch: HashModel = vec![
+            Statement::new(id, name, bob, named_node, empty, purge),
+            Statement::new(var, name, alice, string, empty, purge),
+        ];
+        let (out, _) = apply_delta(&lookup_table, &cur, &object_patch);
+        assert_eq!(out, vec![cur[0], cur[1]]);
+    }
+
+    #[test]
+    fn test_apply_delta_with_slice() {
+        let mut lookup_table = LookupTable::new(1);
+        add_processor_methods_to_table(&mut lookup_table);
+        let string = lookup_table.ensure_value(&String::from(STRING_IRI));
+        let lang_string = lookup_table.ensure_value(&String::from(LANG_STRING_IRI));
+        let slice = lookup_table.ensure_value(&String::from("http://purl.org/linked-delta/slice"));
+
+        let name = lookup_table.ensure_value(&String::from("https://schema.org/name"));
+
+        let id = lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234"));
+        let bob = lookup_table.ensure_value(&String::from("Bob"));
+        let en = lookup_table.ensure_value(&String::from("en"));
+        let nl = lookup_table.ensure_value(&String::from("nl"));
+        let empty = lookup_table.ensure_value("");
+
+        let cur: HashModel = vec![
+            Statement::new(id, name, bob, string, empty, empty),
+            Statement::new(id, name, bob, lang_string, en, empty),
+            Statement::new(id, name, bob, lang_string, nl, empty),
+        ];
+        let patch: HashModel = vec![
+            Statement::new(id, name, bob, lang_string, en, slice),
+            Statement::new(id, name, bob, lang_string, empty, slice),
+        ];
+
+        let (out, _) = apply_delta(&lookup_table, &cur, &patch);
+
+        assert_eq!(out, vec![cur[0], cur[2]]);
+    }
+
+    #[test]
+    fn test_apply_delta_with_slice_add_and_replace() {
+        let mut lookup_table = LookupTable::new(1);
+        add_processor_methods_to_table(&mut lookup_table);
+        let string = lookup_table.ensure_value(&String::from(STRING_IRI));
+        let add = lookup_table.ensure_value(&String::from("http://purl.org/linked-delta/add"));
+        let replace =
+            lookup_table.ensure_value(&String::from("http://purl.org/linked-delta/replace"));
+        let slice = lookup_table.ensure_value(&String::from("http://purl.org/linked-delta/slice"));
+
+        let name = lookup_table.ensure_value(&String::from("https://schema.org/name"));
+        let comment = lookup_table.ensure_value(&String::from("https://schema.org/comment"));
+
+        let id = lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234"));
+        let bob = lookup_table.ensure_value(&String::from("bob"));
+        let bob_corrected = lookup_table.ensure_value(&String::from("Bob"));
+        let comment0 = lookup_table.ensure_value(&String::from("Comment 0"));
+        let comment1 = lookup_table.ensure_value(&String::from("Comment 1"));
+        let comment2 = lookup_table.ensure_value(&String::from("Comment 2"));
+        let empty = lookup_table.ensure_value("");
+
+        let cur: HashModel = vec![
+            Statement::new(id, name, bob, string, empty, empty),
+            Statement::new(id, comment, comment0, string, empty, empty),
+            Statement::new(id, comment, comment1, string, empty, empty),
+        ];
+        let patch: HashModel = vec![
+            Statement::new(id, comment, comment0, string, empty, slice),
+            Statement::new(id, comment, comment2, string, empty, add),
+            Statement::new(id, name, bob_corrected, string, empty, replace),
+        ];
+
+        let (out, _) = apply_delta(&lookup_table, &cur, &patch);
+
+        let values: Vec<u128> = out.iter().map(|s| s.value).collect();
+        assert_eq!(values, vec![comment1, bob_corrected, comment2]);
+    }
+
+    #[test]
+    fn test_apply_delta_with_named_graph() {
+        let mut lookup_table = LookupTable::new(1);
+        add_processor_methods_to_table(&mut lookup_table);
+        let string = lookup_table.ensure_value(&String::from(STRING_IRI));
+        let graph =
+            lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234#meta"));
+        let replace = lookup_table.ensure_value(&String::from(
+            "http://purl.org/linked-delta/replace?graph=https://id.openraadsinformatie.nl/1234#meta",
+        ));
+
+        let name = lookup_table.ensure_value(&String::from("https://schema.org/name"));
+
+        let id = lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234"));
+        let bob = lookup_table.ensure_value(&String::from("Bob"));
+        let alice = lookup_table.ensure_value(&String::from("Alice"));
+        let empty = lookup_table.ensure_value("");
+
+        let cur: HashModel = vec![
+            Statement::new(id, name, bob, string, empty, empty),
+            Statement::new(id, name, bob, string, empty, graph),
+        ];
+        let patch: HashModel = vec![Statement::new(id, name, alice, string, empty, replace)];
+
+        let (out, _) = apply_delta(&lookup_table, &cur, &patch);
+
+        assert_eq!(
+            out,
+            vec![
+                cur[0],
+                Statement::new(id, name, alice, string, empty, graph)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_delta_with_named_graph_removals() {
+        let mut lookup_table = LookupTable::new(1);
+        add_processor_methods_to_table(&mut lookup_table);
+        let string = lookup_table.ensure_value(&String::from(STRING_IRI));
+        let var = lookup_table.ensure_value(&String::from("http://spinrdf.org/sp#Variable"));
+        let graph =
+            lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234#meta"));
+        let mut in_graph = |operator: &str| {
+            lookup_table.ensure_value(&format!(
+                "http://purl.org/linked-delta/{}?graph=https://id.openraadsinformatie.nl/1234#meta",
+                operator
+            ))
+        };
+        let remove = in_graph("remove");
+        let purge = in_graph("purge");
+        let slice = in_graph("slice");
+
+        let name = lookup_table.ensure_value(&String::from("https://schema.org/name"));
+
+        let id = lookup_table.ensure_value(&String::from("https://id.openraadsinformatie.nl/1234"));
+        let bob = lookup_table.ensure_value(&String::from("Bob"));
+        let empty = lookup_table.ensure_value("");
+
+        let cur: HashModel = vec![
+            Statement::new(id, name, bob, string, empty, empty),
+            Statement::new(id, name, bob, string, empty, graph),
+        ];
+
+        for patch in vec![
+            vec![Statement::new(id, name, bob, string, empty, remove)],
+            vec![Statement::new(id, var, var, string, empty, remove)],
+            vec![Statement::new(id, var, var, string, empty, purge)],
+            vec![Statement::new(id, name, bob, string, empty, purge)],
+            vec![Statement::new(id, name, bob, string, empty, slice)],
+        ] {
+            let (out, _) = apply_delta(&lookup_table, &cur, &patch);
+
+            assert_eq!(out, vec![cur[0]]);
+        }
+    }
+
     #[test]
     fn test_add_all() {
         let mut cur: HashModel = vec![Statement::new(2u128, 0u128, 0u128, 0u128, 0u128, 0u128)];
@@ -2,7 +2,7 @@ use crate::delta::delta_processor::DeltaProcessor;
 use crate::delta::processor::ProcessorInitializer;
 use crate::hashtuple::{HashModel, LookupTable, Statement};
 
-const LD_ADD: &str = "http://purl.org/linked-delta/add";
+pub const LD_ADD: &str = "http://purl.org/linked-delta/add";
 
 pub struct AddProcessor<'a> {
     pub(crate) lookup_table: &'a LookupTable,
@@ -1,8 +1,8 @@
 use crate::delta::delta_processor::DeltaProcessor;
-use crate::delta::processor::ProcessorInitializer;
+use crate::delta::processor::{ProcessorInitializer, SP_VARIABLE};
 use crate::hashtuple::{HashModel, LookupTable, Statement};
 
-const ONT_INVALIDATE: &str = "https://ns.ontola.io/core#invalidate";
+pub const ONT_INVALIDATE: &str = "https://ns.ontola.io/core#invalidate";
 
 pub struct InvalidateProcessor<'a> {
     pub(crate) lookup_table: &'a LookupTable,
@@ -11,6 +11,7 @@ pub struct InvalidateProcessor<'a> {
 impl<'a> ProcessorInitializer for InvalidateProcessor<'a> {
     fn initialize(lookup_table: &mut LookupTable) {
         lookup_table.ensure_value(&String::from(ONT_INVALIDATE));
+        lookup_table.ensure_value(&String::from(SP_VARIABLE));
     }
 }
 
@@ -29,9 +30,7 @@ impl<'a> DeltaProcessor<'a> for InvalidateProcessor<'a> {
         _: &HashModel,
         st: Statement,
     ) -> (HashModel, HashModel, HashModel) {
-        let var = self
-            .lookup_table
-            .get_by_value(String::from("http://spinrdf.org/sp#Variable"));
+        let var = self.lookup_table.get_by_value(String::from(SP_VARIABLE));
         if st.subject == var || st.predicate != var || st.value != var {
             error!(
                 "Processor only supports <subj> <sp:Variable> <sp:Variable> <ont:inv> statements"
@@ -1,4 +1,7 @@
 pub mod add_processor;
 pub mod invalidate_processor;
+pub mod purge_processor;
+pub mod remove_processor;
 pub mod replace_processor;
+pub mod slice_processor;
 pub mod supplant_processor;
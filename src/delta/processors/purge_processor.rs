@@ -0,0 +1,50 @@
+use crate::delta::delta_processor::DeltaProcessor;
+use crate::delta::processor::{ProcessorInitializer, SP_VARIABLE};
+use crate::hashtuple::{HashModel, LookupTable, Statement};
+
+pub const LD_PURGE: &str = "http://purl.org/linked-delta/purge";
+
+pub struct PurgeProcessor<'a> {
+    pub(crate) lookup_table: &'a LookupTable,
+}
+
+impl<'a> ProcessorInitializer for PurgeProcessor<'a> {
+    fn initialize(lookup_table: &mut LookupTable) {
+        lookup_table.ensure_value(&String::from(LD_PURGE));
+        lookup_table.ensure_value(&String::from(SP_VARIABLE));
+    }
+}
+
+/// Purges the current statements matching <subj> <pred> <object> <ld:purge>, any of which can be
+/// sp:Variable to match all. A given object must match on value, datatype and language.
+impl<'a> DeltaProcessor<'a> for PurgeProcessor<'a> {
+    #[rustfmt::skip]
+    fn matches(&self, statement: Statement) -> bool {
+        let graph = statement.graph;
+
+        graph == self.lookup_table.get_by_value(String::from(LD_PURGE))
+    }
+
+    fn process(
+        &self,
+        cur: &HashModel,
+        _: &HashModel,
+        st: Statement,
+    ) -> (HashModel, HashModel, HashModel) {
+        let var = self.lookup_table.get_by_value(String::from(SP_VARIABLE));
+        let removes = cur
+            .iter()
+            .filter(|h| {
+                (st.subject == var || h.subject == st.subject)
+                    && (st.predicate == var || h.predicate == st.predicate)
+                    && (st.value == var
+                        || (h.value == st.value
+                            && h.datatype == st.datatype
+                            && h.language == st.language))
+            })
+            .cloned()
+            .collect();
+
+        (Vec::with_capacity(0), Vec::with_capacity(0), removes)
+    }
+}
@@ -0,0 +1,47 @@
+use crate::delta::delta_processor::DeltaProcessor;
+use crate::delta::processor::{ProcessorInitializer, SP_VARIABLE};
+use crate::hashtuple::{HashModel, LookupTable, Statement};
+
+pub const LD_REMOVE: &str = "http://purl.org/linked-delta/remove";
+
+pub struct RemoveProcessor<'a> {
+    pub(crate) lookup_table: &'a LookupTable,
+}
+
+impl<'a> ProcessorInitializer for RemoveProcessor<'a> {
+    fn initialize(lookup_table: &mut LookupTable) {
+        lookup_table.ensure_value(&String::from(LD_REMOVE));
+        lookup_table.ensure_value(&String::from(SP_VARIABLE));
+    }
+}
+
+/// Removes the current statements matching <subj> <pred> <value> <ld:remove>, any of which can
+/// be sp:Variable to match all.
+impl<'a> DeltaProcessor<'a> for RemoveProcessor<'a> {
+    #[rustfmt::skip]
+    fn matches(&self, statement: Statement) -> bool {
+        let graph = statement.graph;
+
+        graph == self.lookup_table.get_by_value(String::from(LD_REMOVE))
+    }
+
+    fn process(
+        &self,
+        cur: &HashModel,
+        _: &HashModel,
+        st: Statement,
+    ) -> (HashModel, HashModel, HashModel) {
+        let var = self.lookup_table.get_by_value(String::from(SP_VARIABLE));
+        let removes = cur
+            .iter()
+            .filter(|h| {
+                (st.subject == var || h.subject == st.subject)
+                    && (st.predicate == var || h.predicate == st.predicate)
+                    && (st.value == var || h.value == st.value)
+            })
+            .cloned()
+            .collect();
+
+        (Vec::with_capacity(0), Vec::with_capacity(0), removes)
+    }
+}
@@ -2,7 +2,7 @@ use crate::delta::delta_processor::DeltaProcessor;
 use crate::delta::processor::ProcessorInitializer;
 use crate::hashtuple::{HashModel, LookupTable, Statement};
 
-const LD_REPLACE: &str = "http://purl.org/linked-delta/replace";
+pub const LD_REPLACE: &str = "http://purl.org/linked-delta/replace";
 
 pub struct ReplaceProcessor<'a> {
     pub(crate) lookup_table: &'a LookupTable,
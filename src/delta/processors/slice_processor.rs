@@ -0,0 +1,47 @@
+use crate::delta::delta_processor::DeltaProcessor;
+use crate::delta::processor::ProcessorInitializer;
+use crate::hashtuple::{HashModel, LookupTable, Statement};
+
+pub const LD_SLICE: &str = "http://purl.org/linked-delta/slice";
+
+pub struct SliceProcessor<'a> {
+    pub(crate) lookup_table: &'a LookupTable,
+}
+
+impl<'a> ProcessorInitializer for SliceProcessor<'a> {
+    fn initialize(lookup_table: &mut LookupTable) {
+        lookup_table.ensure_value(&String::from(LD_SLICE));
+    }
+}
+
+/// Removes exactly the current statements equal to <subj> <pred> <object> <ld:slice>, including
+/// datatype and language.
+impl<'a> DeltaProcessor<'a> for SliceProcessor<'a> {
+    #[rustfmt::skip]
+    fn matches(&self, statement: Statement) -> bool {
+        let graph = statement.graph;
+
+        graph == self.lookup_table.get_by_value(String::from(LD_SLICE))
+    }
+
+    fn process(
+        &self,
+        cur: &HashModel,
+        _: &HashModel,
+        st: Statement,
+    ) -> (HashModel, HashModel, HashModel) {
+        let removes = cur
+            .iter()
+            .filter(|h| {
+                h.subject == st.subject
+                    && h.predicate == st.predicate
+                    && h.value == st.value
+                    && h.datatype == st.datatype
+                    && h.language == st.language
+            })
+            .cloned()
+            .collect();
+
+        (Vec::with_capacity(0), Vec::with_capacity(0), removes)
+    }
+}
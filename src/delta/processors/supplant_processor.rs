@@ -2,8 +2,8 @@ use crate::delta::delta_processor::DeltaProcessor;
 use crate::delta::processor::ProcessorInitializer;
 use crate::hashtuple::{HashModel, LookupTable, Statement};
 
-const LD_SUPPLANT: &str = "http://purl.org/linked-delta/supplant";
-const LL_SUPPLANT: &str = "http://purl.org/link-lib/supplant";
+pub const LD_SUPPLANT: &str = "http://purl.org/linked-delta/supplant";
+pub const LL_SUPPLANT: &str = "http://purl.org/link-lib/supplant";
 
 pub struct SupplantProcessor<'a> {
     pub(crate) lookup_table: &'a LookupTable,
@@ -0,0 +1,221 @@
+use crate::delta::delta_processor::DeltaProcessor;
+use crate::delta::processor::ProcessorInitializer;
+use crate::delta::processors::add_processor::{AddProcessor, LD_ADD};
+use crate::delta::processors::invalidate_processor::{InvalidateProcessor, ONT_INVALIDATE};
+use crate::delta::processors::purge_processor::{PurgeProcessor, LD_PURGE};
+use crate::delta::processors::remove_processor::{RemoveProcessor, LD_REMOVE};
+use crate::delta::processors::replace_processor::{ReplaceProcessor, LD_REPLACE};
+use crate::delta::processors::slice_processor::{SliceProcessor, LD_SLICE};
+use crate::delta::processors::supplant_processor::{SupplantProcessor, LD_SUPPLANT, LL_SUPPLANT};
+use crate::hashtuple::LookupTable;
+use lazy_static::lazy_static;
+use std::sync::{RwLock, RwLockReadGuard};
+
+/// Creates a processor which borrows the lookup table for the duration of a delta.
+pub type ProcessorFactory = for<'a> fn(&'a LookupTable) -> Box<dyn DeltaProcessor<'a> + 'a>;
+
+#[derive(Clone)]
+pub struct ProcessorRegistration {
+    /// The operator IRIs (the graph of delta statements) the processor handles.
+    pub operators: Vec<String>,
+    /// Adds the values the processor matches against to the lookup table.
+    pub initialize: fn(&mut LookupTable),
+    pub factory: ProcessorFactory,
+}
+
+/// The processors used to apply deltas, statements are handled by the first matching processor.
+#[derive(Clone)]
+pub struct ProcessorRegistry {
+    registrations: Vec<ProcessorRegistration>,
+}
+
+impl ProcessorRegistry {
+    /// A registry without any processors, see `default` for one with the built-in processors.
+    pub fn empty() -> ProcessorRegistry {
+        ProcessorRegistry {
+            registrations: vec![],
+        }
+    }
+
+    pub fn register(&mut self, registration: ProcessorRegistration) {
+        self.registrations.push(registration);
+    }
+
+    pub fn initialize(&self, lookup_table: &mut LookupTable) {
+        for registration in &self.registrations {
+            for operator in &registration.operators {
+                lookup_table.ensure_value(operator);
+            }
+            (registration.initialize)(lookup_table);
+        }
+    }
+
+    pub fn processors<'a>(
+        &self,
+        lookup_table: &'a LookupTable,
+    ) -> Vec<Box<dyn DeltaProcessor<'a> + 'a>> {
+        self.registrations
+            .iter()
+            .map(|registration| (registration.factory)(lookup_table))
+            .collect()
+    }
+
+    pub fn operators(&self) -> Vec<String> {
+        self.registrations
+            .iter()
+            .flat_map(|registration| registration.operators.clone())
+            .collect()
+    }
+}
+
+impl Default for ProcessorRegistry {
+    fn default() -> Self {
+        let mut registry = ProcessorRegistry::empty();
+
+        registry.register(ProcessorRegistration {
+            operators: vec![LD_SUPPLANT.into(), LL_SUPPLANT.into()],
+            initialize: SupplantProcessor::initialize,
+            factory: |lookup_table| Box::new(SupplantProcessor { lookup_table }),
+        });
+        registry.register(ProcessorRegistration {
+            operators: vec![LD_ADD.into()],
+            initialize: AddProcessor::initialize,
+            factory: |lookup_table| Box::new(AddProcessor { lookup_table }),
+        });
+        registry.register(ProcessorRegistration {
+            operators: vec![LD_REPLACE.into()],
+            initialize: ReplaceProcessor::initialize,
+            factory: |lookup_table| Box::new(ReplaceProcessor { lookup_table }),
+        });
+        registry.register(ProcessorRegistration {
+            operators: vec![ONT_INVALIDATE.into()],
+            initialize: InvalidateProcessor::initialize,
+            factory: |lookup_table| Box::new(InvalidateProcessor { lookup_table }),
+        });
+        registry.register(ProcessorRegistration {
+            operators: vec![LD_REMOVE.into()],
+            initialize: RemoveProcessor::initialize,
+            factory: |lookup_table| Box::new(RemoveProcessor { lookup_table }),
+        });
+        registry.register(ProcessorRegistration {
+            operators: vec![LD_PURGE.into()],
+            initialize: PurgeProcessor::initialize,
+            factory: |lookup_table| Box::new(PurgeProcessor { lookup_table }),
+        });
+        registry.register(ProcessorRegistration {
+            operators: vec![LD_SLICE.into()],
+            initialize: SliceProcessor::initialize,
+            factory: |lookup_table| Box::new(SliceProcessor { lookup_table }),
+        });
+
+        registry
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<ProcessorRegistry> = RwLock::new(ProcessorRegistry::default());
+}
+
+/// Adds a custom processor to the registry used by the importers and the update endpoint, call
+/// this before starting either.
+pub fn register_processor(registration: ProcessorRegistration) {
+    REGISTRY
+        .write()
+        .expect("Processor registry poisoned")
+        .register(registration);
+}
+
+/// The currently registered processors, registering waits until the guard is dropped.
+pub fn registry() -> RwLockReadGuard<'static, ProcessorRegistry> {
+    REGISTRY.read().expect("Processor registry poisoned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::processor::apply_delta_with;
+    use crate::hashtuple::{HashModel, Statement, STRING_IRI};
+    use crate::serving::service_info::service_info;
+    use actix_web::{test, App};
+    use std::sync::PoisonError;
+
+    const TEST_ADD: &str = "https://example.com/ns#testAdd";
+
+    struct TestAddProcessor<'a> {
+        lookup_table: &'a LookupTable,
+    }
+
+    impl<'a> DeltaProcessor<'a> for TestAddProcessor<'a> {
+        fn matches(&self, statement: Statement) -> bool {
+            statement.graph == self.lookup_table.get_by_value(String::from(TEST_ADD))
+        }
+
+        fn process(
+            &self,
+            _: &HashModel,
+            _: &HashModel,
+            statement: Statement,
+        ) -> (HashModel, HashModel, HashModel) {
+            (vec![statement], vec![], vec![])
+        }
+    }
+
+    fn registration() -> ProcessorRegistration {
+        ProcessorRegistration {
+            operators: vec![TEST_ADD.into()],
+            initialize: |_| (),
+            factory: |lookup_table| Box::new(TestAddProcessor { lookup_table }),
+        }
+    }
+
+    /// Removes the test processor from the global registry when dropped.
+    struct Registered;
+
+    impl Drop for Registered {
+        fn drop(&mut self) {
+            REGISTRY
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .registrations
+                .retain(|r| !r.operators.iter().any(|operator| operator == TEST_ADD));
+        }
+    }
+
+    #[test]
+    fn test_custom_processor() {
+        let mut registry = ProcessorRegistry::default();
+        registry.register(registration());
+
+        let mut lookup_table = LookupTable::new(1);
+        registry.initialize(&mut lookup_table);
+        let id = lookup_table.ensure_value("https://example.com/1");
+        let name = lookup_table.ensure_value("https://schema.org/name");
+        let bob = lookup_table.ensure_value("Bob");
+        let string = lookup_table.ensure_value(STRING_IRI);
+        let empty = lookup_table.ensure_value("");
+        let operator = lookup_table.ensure_value(TEST_ADD);
+
+        let patch = vec![Statement::new(id, name, bob, string, empty, operator)];
+        let (out, _) = apply_delta_with(&registry, &lookup_table, &vec![], &patch);
+        assert_eq!(
+            out,
+            vec![Statement::new(id, name, bob, string, empty, empty)]
+        );
+
+        let operators = registry.operators();
+        assert!(operators.contains(&String::from(TEST_ADD)));
+        assert!(operators.contains(&String::from(LD_ADD)));
+    }
+
+    #[actix_rt::test]
+    async fn test_register_processor() {
+        register_processor(registration());
+        let _registered = Registered;
+
+        let mut app = test::init_service(App::new().service(service_info)).await;
+        let req = test::TestRequest::get().uri("/.well-known/ld").to_request();
+        let body: serde_json::Value = test::read_response_json(&mut app, req).await;
+        let operators = body["operators"].as_array().unwrap();
+        assert!(operators.contains(&serde_json::Value::from(TEST_ADD)));
+    }
+}
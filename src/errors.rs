@@ -28,6 +28,8 @@ error_chain! {
 
         ExpiredSession
 
+        ClientDisconnected
+
         CookieInvalidSignature
 
         DeltaWithoutOperator {
@@ -0,0 +1,72 @@
+//! Change feed publishing
+//!
+//! When `CHANGES_CHANNEL` is set, the deltas committed by the importer are published on that redis
+//! channel so the servers can forward them to their subscribed clients.
+
+use crate::app_config::AppConfig;
+use crate::hashtuple::LookupTable;
+use crate::importing::parsing::DocumentSet;
+use crate::serving::serialization::hash_model_to_hextuples;
+use lazy_static::lazy_static;
+use redis::Commands;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref PUBLISHER: Mutex<Option<redis::Connection>> = Mutex::new(None);
+}
+
+/// A delta committed to a document.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Change {
+    pub iri: String,
+    /// The delta as hextuples, one per line.
+    pub delta: String,
+}
+
+/// Serializes the deltas of the documents, empty when no changes are published.
+pub(crate) fn changes_for(
+    config: &AppConfig,
+    docs: &DocumentSet,
+    table: &LookupTable,
+) -> Vec<Change> {
+    if config.changes_channel.is_none() {
+        return vec![];
+    }
+
+    docs.iter()
+        .map(|(iri, delta)| Change {
+            iri: iri.clone(),
+            delta: String::from_utf8_lossy(&hash_model_to_hextuples((delta.clone(), table)))
+                .into_owned(),
+        })
+        .collect()
+}
+
+/// Publishes committed changes. Failures are only logged since the deltas are already stored.
+pub(crate) fn publish_changes(config: &AppConfig, changes: Vec<Change>) {
+    let channel = match &config.changes_channel {
+        Some(channel) => channel,
+        None => return,
+    };
+    let mut publisher = PUBLISHER.lock().expect("Change publisher poisoned");
+
+    for change in changes {
+        if publisher.is_none() {
+            match config.create_redis_consumer() {
+                Ok(conn) => *publisher = Some(conn),
+                Err(e) => {
+                    warn!(target: "apex", "Couldn't connect to publish changes: {}", e);
+                    return;
+                }
+            }
+        }
+
+        let payload = serde_json::to_string(&change).expect("Change can't be serialized");
+        let conn = publisher.as_mut().unwrap();
+        if let Err(e) = conn.publish::<_, _, ()>(channel, payload) {
+            warn!(target: "apex", "Error publishing change to {}: {}", change.iri, e);
+            *publisher = None;
+        }
+    }
+}
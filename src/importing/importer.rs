@@ -1,74 +1,60 @@
-use crate::db::db_context::DbContext;
-use crate::db::document::{delete_all_document_data, reset_document};
-use crate::db::properties::insert_properties;
-use crate::db::resources::insert_resources;
+use crate::app_config::{AppConfig, OutboxConfig};
+use crate::db::storage::StorageContext;
 use crate::delta::processor::{add_processor_methods_to_table, apply_delta};
 use crate::errors::ErrorKind;
+use crate::importing::changes::{changes_for, publish_changes};
 use crate::importing::events::{DeltaProcessingTiming, MessageTiming};
+use crate::importing::outbox::enqueue_change;
 use crate::importing::parsing::DocumentSet;
-use diesel::prelude::*;
-use diesel::result::Error::RollbackTransaction;
-use std::collections::HashMap;
+use actix_web::web;
 use std::time::{Duration, Instant};
 
-#[allow(unused_must_use)]
 pub(crate) async fn process_message(
-    ctx: &mut DbContext<'_>,
+    ctx: &mut dyn StorageContext,
     docs: DocumentSet,
+    config: &AppConfig,
 ) -> Result<MessageTiming, ErrorKind> {
-    let mut result: Result<MessageTiming, ErrorKind> =
-        Err(ErrorKind::Unexpected("No result from processing".into()));
-
-    ctx.db_pool
-        .get()
-        .unwrap()
-        .transaction::<(), diesel::result::Error, _>(|| match process_delta(ctx, docs) {
-            Ok(timing) => {
-                result = Ok(timing);
-
-                Ok(())
-            }
-            Err(e) => {
-                result = Err(e);
-                Err(RollbackTransaction)
-            }
-        });
+    let mut timing = None;
+    let changes = changes_for(config, &docs, ctx.lookup_table());
+    let mut docs = Some(docs);
+
+    ctx.transaction(&mut |ctx| {
+        let docs = docs.take().expect("Transaction ran twice");
+        timing = Some(process_delta(ctx, docs, config.outbox.as_ref())?);
+
+        Ok(())
+    })?;
+    if !changes.is_empty() {
+        // Publishing talks to redis synchronously, so it's kept off the executor.
+        let config = config.clone();
+        let _ = web::block(move || -> Result<(), ()> {
+            publish_changes(&config, changes);
+            Ok(())
+        })
+        .await;
+    }
 
-    result
+    timing.ok_or_else(|| ErrorKind::Unexpected("No result from processing".into()))
 }
 
-#[allow(unused_must_use)]
 pub(crate) async fn process_invalidate(
-    ctx: &mut DbContext<'_>,
+    ctx: &mut dyn StorageContext,
 ) -> Result<MessageTiming, ErrorKind> {
     debug!(target: "apex", "Invalidating all data");
-    let mut result: Result<MessageTiming, ErrorKind> =
-        Err(ErrorKind::Unexpected("No result from processing".into()));
 
-    ctx.db_pool
-        .get()
-        .unwrap()
-        .transaction(|| match delete_all_document_data(&ctx.get_conn()) {
-            Ok(_) => {
-                result = Ok(MessageTiming::new());
-                Ok(())
-            }
-            Err(e) => {
-                result = Err(ErrorKind::Unexpected(e.to_string()));
-                Err(RollbackTransaction)
-            }
-        });
+    ctx.transaction(&mut |ctx| ctx.delete_all())?;
 
-    result
+    Ok(MessageTiming::new())
 }
 
 pub(crate) fn process_delta(
-    mut ctx: &mut DbContext,
+    ctx: &mut dyn StorageContext,
     docs: DocumentSet,
+    outbox: Option<&OutboxConfig>,
 ) -> Result<MessageTiming, ErrorKind> {
     let parse_start = Instant::now();
 
-    add_processor_methods_to_table(&mut ctx.lookup_table);
+    add_processor_methods_to_table(ctx.lookup_table());
 
     let parse_time = Instant::now().duration_since(parse_start);
     let mut fetch_time = Duration::new(0, 0);
@@ -77,23 +63,28 @@ pub(crate) fn process_delta(
 
     for (iri, delta) in docs {
         let fetch_start = Instant::now();
-
-        let (existing_doc, existing_model) = reset_document(&mut ctx, &iri);
-        fetch_time += Instant::now().duration_since(fetch_start);
-
-        let (next, delta_timing) = apply_delta(&ctx.lookup_table, &existing_model, &delta);
-        delta_time += delta_timing;
-
-        let insert_start = Instant::now();
-        let resources =
-            insert_resources(&ctx.get_conn(), &ctx.lookup_table, &next, existing_doc.id);
-        let mut resource_id_map = HashMap::<String, i64>::new();
-        for resource in resources {
-            resource_id_map.insert(resource.iri.clone(), resource.id);
+        let mut applied = None;
+        let mut emptied = false;
+
+        let changed = ctx.update_document(&iri, &mut |table, existing_model| {
+            let delta_start = Instant::now();
+            let (next, delta_timing) = apply_delta(table, existing_model, &delta);
+            applied = Some((delta_start, Instant::now(), delta_timing));
+            emptied = next.is_empty();
+
+            next
+        })?;
+        if changed {
+            enqueue_change(ctx, outbox, &iri, &delta, emptied)?;
+        } else {
+            trace!(target: "apex", "Document {} unchanged", iri);
         }
 
-        insert_properties(ctx, &next, resource_id_map);
-        insert_time += Instant::now().duration_since(insert_start);
+        if let Some((delta_start, delta_end, delta_timing)) = applied {
+            fetch_time += delta_start.duration_since(fetch_start);
+            delta_time += delta_timing;
+            insert_time += Instant::now().duration_since(delta_end);
+        }
     }
 
     Ok(MessageTiming {
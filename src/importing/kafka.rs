@@ -27,7 +27,7 @@ pub async fn import_kafka(
     let mut stream = consumer.start();
 
     let config = AppConfig::default();
-    let pool = DbContext::default_pool(config.database_url, config.database_pool_size)?;
+    let pool = DbContext::default_pool(config.database_url.clone(), config.database_pool_size)?;
     let mut ctx = DbContext::new(&pool);
     println!("Start listening for messages");
     let mut last_listen_time = Instant::now();
@@ -46,7 +46,7 @@ pub async fn import_kafka(
                         &mut ctx.lookup_table,
                         &String::from_utf8(Vec::from(payload)).unwrap(),
                     ) {
-                        Ok(model) => match process_message(&mut ctx, model).await {
+                        Ok(model) => match process_message(&mut ctx, model, &config).await {
                             Ok(timing) => {
                                 if let Err(e) = consumer.store_offset(&msg) {
                                     warn!(target: "apex", "Error while storing offset: {}", e);
@@ -79,7 +79,8 @@ pub async fn import_kafka(
     Ok(())
 }
 
-fn create_kafka_consumer() -> KafkaResult<StreamConsumer> {
+/// The connection to the cluster, shared by the consumer and the outbox producer.
+pub(crate) fn kafka_client_config() -> ClientConfig {
     let mut config = ClientConfig::new();
     config.set(
         "bootstrap.servers",
@@ -97,6 +98,11 @@ fn create_kafka_consumer() -> KafkaResult<StreamConsumer> {
         env::var("KAFKA_PASSWORD").unwrap().as_str(),
     );
 
+    config
+}
+
+fn create_kafka_consumer() -> KafkaResult<StreamConsumer> {
+    let mut config = kafka_client_config();
     config.set("group.id", env::var("KAFKA_GROUP_ID").unwrap().as_str());
     // config.set("queue.buffering.max.ms", "0");
     //    config.set("allow.auto.create.topics", "false");
@@ -1,6 +1,8 @@
+pub mod changes;
 pub mod events;
 pub mod importer;
 pub mod kafka;
+pub mod outbox;
 pub mod parsing;
 pub mod redis;
 pub mod redis_invalidator;
@@ -0,0 +1,198 @@
+//! Outbox of committed changes
+//!
+//! When an outbox target is configured, the importer adds an event for each changed document to
+//! the outbox of the storage within the transaction of the change. The relay delivers the events
+//! in order to the Kafka topic or Redis stream and removes them once they are accepted, so events
+//! are delivered at least once.
+//!
+//! The `outbox_relay` binary relays the Postgres outbox, the server relays the outbox of the
+//! embedded backends since they can't be opened by another process.
+
+use crate::app_config::{AppConfig, OutboxConfig, OutboxTarget};
+use crate::db::storage::{storage_from_config, Operation, OutboxEvent, Storage, StorageContext};
+use crate::errors::ErrorKind;
+use crate::hashtuple::Statement;
+use crate::importing::kafka::kafka_client_config;
+use crate::serving::serialization::hash_model_to_hextuples;
+use actix_web::web;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::delay_for;
+
+/// The amount of events read from the outbox at once.
+const BATCH_SIZE: usize = 100;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Adds an event for the changed document to the outbox if it's enabled.
+pub(crate) fn enqueue_change(
+    ctx: &mut dyn StorageContext,
+    outbox: Option<&OutboxConfig>,
+    iri: &str,
+    delta: &[Statement],
+    emptied: bool,
+) -> Result<(), ErrorKind> {
+    let outbox = match outbox {
+        Some(outbox) => outbox,
+        None => return Ok(()),
+    };
+
+    let operation = if emptied {
+        Operation::Delete
+    } else {
+        Operation::Update
+    };
+    let delta = if outbox.include_delta {
+        let hextuples = hash_model_to_hextuples((delta.to_vec(), ctx.lookup_table()));
+        Some(String::from_utf8_lossy(&hextuples).into_owned())
+    } else {
+        None
+    };
+
+    ctx.enqueue_event(iri, operation, delta)
+}
+
+/// Relays the outbox of the configured storage.
+pub async fn relay() -> Result<(), String> {
+    let config = AppConfig::default();
+    let storage = storage_from_config(&config)?;
+
+    relay_outbox(storage, config).await
+}
+
+/// Delivers the events in the outbox in order, removing them once they are accepted.
+///
+/// The storage and Redis are blocking, so they are used from the blocking thread pool.
+pub(crate) async fn relay_outbox(
+    storage: Arc<dyn Storage>,
+    config: AppConfig,
+) -> Result<(), String> {
+    let target = match config.outbox.as_ref() {
+        Some(outbox) => outbox.target.clone(),
+        None => return Err(String::from("No outbox target configured")),
+    };
+    let mut sink = None;
+
+    loop {
+        if sink.is_none() {
+            let (config, target) = (config.clone(), target.clone());
+            match web::block(move || Sink::connect(&config, &target)).await {
+                Ok(connected) => sink = Some(connected),
+                Err(e) => {
+                    error!(target: "apex", "Couldn't connect to relay the outbox: {}", e);
+                    delay_for(RETRY_DELAY).await;
+                    continue;
+                }
+            }
+        }
+
+        let store = Arc::clone(&storage);
+        let pending = web::block(move || store.open(None).pending_events(BATCH_SIZE)).await;
+        let events = match pending {
+            Ok(events) => events,
+            Err(e) => {
+                error!(target: "apex", "Error reading the outbox: {}", e);
+                delay_for(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        if events.is_empty() {
+            delay_for(POLL_INTERVAL).await;
+            continue;
+        }
+
+        let mut relayed = Vec::with_capacity(events.len());
+        let mut failed = false;
+        for (id, event) in &events {
+            // Stop at the first failure, so the events stay in order.
+            if let Err(e) = sink.as_mut().unwrap().send(event).await {
+                warn!(target: "apex", "Error relaying change to {}: {}", event.iri, e);
+                sink = None;
+                failed = true;
+                break;
+            }
+            relayed.push(*id);
+        }
+        trace!(target: "apex", "Relayed {} of {} events", relayed.len(), events.len());
+
+        let store = Arc::clone(&storage);
+        if let Err(e) = web::block(move || store.open(None).remove_events(&relayed)).await {
+            error!(target: "apex", "Error removing relayed events: {}", e);
+            failed = true;
+        }
+        if failed {
+            delay_for(RETRY_DELAY).await;
+        }
+    }
+}
+
+enum Sink {
+    Kafka {
+        producer: FutureProducer,
+        topic: String,
+    },
+    Redis {
+        conn: Arc<Mutex<redis::Connection>>,
+        stream: String,
+    },
+}
+
+impl Sink {
+    fn connect(config: &AppConfig, target: &OutboxTarget) -> Result<Sink, String> {
+        match target {
+            OutboxTarget::Kafka(topic) => Ok(Sink::Kafka {
+                producer: create_kafka_producer().map_err(|e| e.to_string())?,
+                topic: topic.clone(),
+            }),
+            OutboxTarget::Redis(stream) => Ok(Sink::Redis {
+                conn: Arc::new(Mutex::new(
+                    config.create_redis_consumer().map_err(|e| e.to_string())?,
+                )),
+                stream: stream.clone(),
+            }),
+        }
+    }
+
+    async fn send(&mut self, event: &OutboxEvent) -> Result<(), String> {
+        match self {
+            Sink::Kafka { producer, topic } => {
+                let payload = serde_json::to_string(event).map_err(|e| e.to_string())?;
+                let record = FutureRecord::to(topic).key(&event.iri).payload(&payload);
+
+                match producer.send(record, 0).await {
+                    Ok(Ok(_)) => Ok(()),
+                    Ok(Err((e, _))) => Err(e.to_string()),
+                    Err(_) => Err(String::from("Delivery canceled")),
+                }
+            }
+            Sink::Redis { conn, stream } => {
+                let mut cmd = redis::cmd("XADD");
+                cmd.arg(stream.as_str())
+                    .arg("*")
+                    .arg("iri")
+                    .arg(&event.iri)
+                    .arg("operation")
+                    .arg(event.operation.as_str())
+                    .arg("updated_at")
+                    .arg(event.updated_at.to_string());
+                if let Some(delta) = &event.delta {
+                    cmd.arg("delta").arg(delta);
+                }
+
+                let conn = Arc::clone(conn);
+                web::block(move || {
+                    let mut conn = conn.lock().map_err(|e| e.to_string())?;
+                    cmd.query::<String>(&mut *conn).map_err(|e| e.to_string())
+                })
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+fn create_kafka_producer() -> rdkafka::error::KafkaResult<FutureProducer> {
+    kafka_client_config().create()
+}
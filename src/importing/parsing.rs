@@ -1,20 +1,24 @@
+use crate::delta::processors::supplant_processor::LD_SUPPLANT;
 use crate::errors::ErrorKind;
 use crate::hashtuple::{
     LookupTable, Statement, BLANK_NODE_IRI, LANG_STRING_IRI, NAMED_NODE_IRI, STRING_IRI,
 };
 use crate::rdf::iri_utils::stem_iri;
+use crate::rdf::sparql::{XSD_BOOLEAN_IRI, XSD_DOUBLE_IRI, XSD_INTEGER_IRI};
 use percent_encoding::percent_decode_str;
 use rio_api::model::{Literal, NamedOrBlankNode, Term};
-use rio_api::parser::QuadsParser;
-use rio_turtle::{NQuadsParser, TurtleError};
+use rio_api::parser::{QuadsParser, TriplesParser};
+use rio_turtle::{NQuadsParser, NTriplesParser, TriGParser, TurtleError, TurtleParser};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::io;
 use std::io::{BufRead, BufReader};
+use uuid::Uuid;
 
 /// A set of documents with their IRI as key and their resources as value
 pub(crate) type DocumentSet = HashMap<String, Vec<Statement>>;
 
 const EMPTY: &str = "";
+const RDF_TYPE_IRI: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
 
 pub(crate) fn parse_hndjson<'a>(
     lookup_table: &mut LookupTable,
@@ -77,57 +81,286 @@ pub(crate) fn parse_nquads(
     lookup_table: &mut LookupTable,
     payload: &String,
 ) -> Result<DocumentSet, ErrorKind> {
+    let parser = NQuadsParser::new(payload.as_bytes())?;
+
+    parse_quads(lookup_table, parser)
+}
+
+/// Parses TriG, the named graphs contain the delta operators.
+pub(crate) fn parse_trig(
+    lookup_table: &mut LookupTable,
+    payload: &[u8],
+) -> Result<DocumentSet, ErrorKind> {
+    let parser = TriGParser::new(payload, "")?;
+
+    parse_quads(lookup_table, parser)
+}
+
+/// Parses Turtle, all triples supplant the existing data of their resource.
+pub(crate) fn parse_turtle(
+    lookup_table: &mut LookupTable,
+    payload: &[u8],
+) -> Result<DocumentSet, ErrorKind> {
+    let parser = TurtleParser::new(payload, "")?;
+
+    parse_triples(lookup_table, parser)
+}
+
+/// Parses N-Triples, all triples supplant the existing data of their resource.
+pub(crate) fn parse_ntriples(
+    lookup_table: &mut LookupTable,
+    payload: &[u8],
+) -> Result<DocumentSet, ErrorKind> {
+    let parser = NTriplesParser::new(payload)?;
+
+    parse_triples(lookup_table, parser)
+}
+
+/// Parses expanded JSON-LD, the named graphs contain the delta operators.
+///
+/// Contexts aren't supported, so properties and types must be absolute IRIs.
+pub(crate) fn parse_jsonld(
+    lookup_table: &mut LookupTable,
+    payload: &[u8],
+) -> Result<DocumentSet, ErrorKind> {
+    let document: Value =
+        serde_json::from_slice(payload).map_err(|e| ErrorKind::ParserError(e.to_string()))?;
+    let mut reader = JsonLdReader {
+        lookup_table,
+        docs: HashMap::new(),
+        blank_node_prefix: format!("jsonld{}", Uuid::new_v4().to_simple()),
+        blank_nodes: 0,
+    };
+    reader.read_graph(&document, None)?;
+
+    Ok(reader.docs)
+}
+
+fn parse_quads<P: QuadsParser>(
+    lookup_table: &mut LookupTable,
+    mut parser: P,
+) -> Result<DocumentSet, ErrorKind>
+where
+    ErrorKind: From<P::Error>,
+{
     let mut docs: DocumentSet = HashMap::new();
 
-    match NQuadsParser::new(payload.as_bytes()) {
-        Err(e) => Err(ErrorKind::ParserError(e.to_string())),
-        Ok(mut model) => {
-            // The parse_all method forces TurtleError, so we're circumventing that here.
-            let mut real_error = None;
-            let result = model.parse_all(&mut |q| -> Result<(), TurtleError> {
-                let subj = str_from_iri_or_bn(&q.subject);
-                let pred = String::from(q.predicate.iri);
-                let graph = match q.graph_name {
-                    Some(g) => str_from_iri_or_bn(&g),
-                    None => {
-                        real_error = Some(ErrorKind::DeltaWithoutOperator);
-
-                        return Err(TurtleError::from(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "Unexpected error",
-                        )));
-                    }
-                };
+    parser.parse_all(&mut |q| -> Result<(), ErrorKind> {
+        let graph = match q.graph_name {
+            Some(g) => str_from_iri_or_bn(&g),
+            None => return Err(ErrorKind::DeltaWithoutOperator),
+        };
+        let obj = str_from_term(q.object);
+
+        create_hashtuple(
+            lookup_table,
+            &mut docs,
+            &str_from_iri_or_bn(&q.subject),
+            q.predicate.iri,
+            &obj[0],
+            &obj[1],
+            &obj[2],
+            &graph,
+        )
+    })?;
+
+    Ok(docs)
+}
+
+fn parse_triples<P: TriplesParser>(
+    lookup_table: &mut LookupTable,
+    mut parser: P,
+) -> Result<DocumentSet, ErrorKind>
+where
+    ErrorKind: From<P::Error>,
+{
+    let mut docs: DocumentSet = HashMap::new();
+
+    parser.parse_all(&mut |t| -> Result<(), ErrorKind> {
+        let obj = str_from_term(t.object);
+
+        create_hashtuple(
+            lookup_table,
+            &mut docs,
+            &str_from_iri_or_bn(&t.subject),
+            t.predicate.iri,
+            &obj[0],
+            &obj[1],
+            &obj[2],
+            LD_SUPPLANT,
+        )
+    })?;
+
+    Ok(docs)
+}
+
+impl From<TurtleError> for ErrorKind {
+    fn from(e: TurtleError) -> Self {
+        ErrorKind::ParserError(e.to_string())
+    }
+}
+
+struct JsonLdReader<'a> {
+    lookup_table: &'a mut LookupTable,
+    docs: DocumentSet,
+    /// Unique per payload, so generated labels don't collide with earlier requests.
+    blank_node_prefix: String,
+    /// The amount of generated blank node labels, for nodes without an `@id`.
+    blank_nodes: usize,
+}
 
-                let obj = str_from_term(q.object);
-                let res = create_hashtuple(
-                    lookup_table,
-                    &mut docs,
-                    &subj,
-                    &pred,
-                    &obj[0],
-                    &obj[1],
-                    &obj[2],
-                    &graph,
-                );
-
-                match res {
-                    Err(e) => {
-                        real_error = Some(e);
-                        Err(TurtleError::from(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "Unexpected error",
-                        )))
+impl<'a> JsonLdReader<'a> {
+    fn read_graph(&mut self, value: &Value, graph: Option<&str>) -> Result<(), ErrorKind> {
+        match value {
+            Value::Array(items) => items
+                .iter()
+                .try_for_each(|item| self.read_graph(item, graph)),
+            Value::Object(object) if object.contains_key("@context") => Err(
+                ErrorKind::ParserError(String::from("JSON-LD contexts aren't supported")),
+            ),
+            Value::Object(object) => match (object.get("@graph"), object.get("@id")) {
+                (Some(nodes), Some(Value::String(id))) => self.read_graph(nodes, Some(id)),
+                (Some(nodes), None) => self.read_graph(nodes, graph),
+                _ => self.read_node(object, graph).map(|_| ()),
+            },
+            _ => Err(ErrorKind::ParserError(String::from(
+                "Expected a JSON-LD node object",
+            ))),
+        }
+    }
+
+    /// Reads the statements of the node, returns its id and datatype.
+    fn read_node(
+        &mut self,
+        node: &Map<String, Value>,
+        graph: Option<&str>,
+    ) -> Result<(String, &'static str), ErrorKind> {
+        let (subject, kind) = match node.get("@id") {
+            Some(Value::String(id)) => jsonld_id(id),
+            Some(_) => return Err(ErrorKind::ParserError(String::from("Invalid @id"))),
+            None => {
+                self.blank_nodes += 1;
+                (
+                    format!("{}b{}", self.blank_node_prefix, self.blank_nodes),
+                    BLANK_NODE_IRI,
+                )
+            }
+        };
+
+        for (key, values) in node {
+            let values = match values {
+                Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+
+            if key == "@type" {
+                for value in values {
+                    match value {
+                        Value::String(t) => {
+                            self.insert(&subject, RDF_TYPE_IRI, t, NAMED_NODE_IRI, EMPTY, graph)?
+                        }
+                        _ => return Err(ErrorKind::ParserError(String::from("Invalid @type"))),
                     }
-                    Ok(_) => Ok(()),
                 }
-            });
-
-            match result {
-                Ok(_) => Ok(docs),
-                Err(_) => Err(real_error.unwrap()),
+            } else if !key.starts_with('@') {
+                if !key.contains(':') {
+                    return Err(ErrorKind::ParserError(format!(
+                        "Property '{}' isn't an absolute IRI",
+                        key
+                    )));
+                }
+                for value in values {
+                    self.read_value(&subject, key, value, graph)?;
+                }
             }
         }
+
+        Ok((subject, kind))
+    }
+
+    fn read_value(
+        &mut self,
+        subject: &str,
+        predicate: &str,
+        value: &Value,
+        graph: Option<&str>,
+    ) -> Result<(), ErrorKind> {
+        let number;
+        let (value, datatype, language) = match value {
+            Value::Null => return Ok(()),
+            Value::String(s) => (s.as_str(), STRING_IRI, EMPTY),
+            Value::Bool(b) => (if *b { "true" } else { "false" }, XSD_BOOLEAN_IRI, EMPTY),
+            Value::Number(n) => {
+                number = n.to_string();
+                let datatype = if n.is_f64() {
+                    XSD_DOUBLE_IRI
+                } else {
+                    XSD_INTEGER_IRI
+                };
+                (number.as_str(), datatype, EMPTY)
+            }
+            Value::Object(object) => match (object.get("@value"), object.get("@type")) {
+                (Some(Value::String(value)), Some(Value::String(datatype))) => {
+                    (value.as_str(), datatype.as_str(), EMPTY)
+                }
+                (Some(value), None) => match object.get("@language") {
+                    Some(Value::String(language)) => match value {
+                        Value::String(value) => {
+                            (value.as_str(), LANG_STRING_IRI, language.as_str())
+                        }
+                        _ => return Err(ErrorKind::ParserError(String::from("Invalid @value"))),
+                    },
+                    _ => return self.read_value(subject, predicate, value, graph),
+                },
+                (Some(_), _) => return Err(ErrorKind::ParserError(String::from("Invalid @value"))),
+                (None, _) if object.contains_key("@list") => {
+                    return Err(ErrorKind::ParserError(String::from(
+                        "JSON-LD lists aren't supported",
+                    )))
+                }
+                (None, _) => {
+                    let (id, kind) = self.read_node(object, graph)?;
+                    return self.insert(subject, predicate, &id, kind, EMPTY, graph);
+                }
+            },
+            Value::Array(_) => {
+                return Err(ErrorKind::ParserError(String::from(
+                    "Nested arrays aren't supported",
+                )))
+            }
+        };
+
+        self.insert(subject, predicate, value, datatype, language, graph)
+    }
+
+    fn insert(
+        &mut self,
+        subject: &str,
+        predicate: &str,
+        value: &str,
+        datatype: &str,
+        language: &str,
+        graph: Option<&str>,
+    ) -> Result<(), ErrorKind> {
+        let graph = graph.ok_or(ErrorKind::DeltaWithoutOperator)?;
+
+        create_hashtuple(
+            self.lookup_table,
+            &mut self.docs,
+            subject,
+            predicate,
+            value,
+            datatype,
+            language,
+            graph,
+        )
+    }
+}
+
+fn jsonld_id(id: &str) -> (String, &'static str) {
+    match id.strip_prefix("_:") {
+        Some(label) => (label.to_string(), BLANK_NODE_IRI),
+        None => (id.to_string(), NAMED_NODE_IRI),
     }
 }
 
@@ -151,16 +384,31 @@ fn create_hashtuple(
         }
     };
 
-    let doc_iri = if split_graph.len() < 2 {
+    let (doc_iri, named_graph) = if split_graph.len() < 2 {
         let stemmed = stem_iri(subj);
         debug!(target: "apex", "Graph is empty, defaulting to stemmed subject: {}", stemmed);
 
-        Some(stemmed)
+        (Some(stemmed), None)
     } else {
         let s = split_graph.last().unwrap();
-        let decoded = percent_decode_str(&s).decode_utf8().unwrap();
+        let decoded = percent_decode_str(&s).decode_utf8().unwrap().into_owned();
 
-        Some(decoded.into_owned())
+        // A graph with a fragment is a named graph within the document.
+        match decoded.find('#') {
+            Some(i) => (Some(decoded[..i].to_string()), Some(decoded)),
+            None => (Some(decoded), None),
+        }
+    };
+
+    lookup_table.ensure_value(EMPTY);
+    let graph = match named_graph {
+        Some(named_graph) => {
+            lookup_table.ensure_value(&delta_op);
+            lookup_table.ensure_value(&named_graph);
+
+            format!("{}?graph={}", delta_op, named_graph)
+        }
+        None => delta_op.to_string(),
     };
 
     match doc_iri {
@@ -177,7 +425,7 @@ fn create_hashtuple(
                 lookup_table.ensure_value(&value),
                 lookup_table.ensure_value(&datatype),
                 lookup_table.ensure_value(&language),
-                lookup_table.ensure_value(&delta_op.to_string()),
+                lookup_table.ensure_value(&graph),
             ));
 
             Ok(())
@@ -215,3 +463,62 @@ fn str_from_term(t: Term) -> [String; 3] {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jsonld() {
+        let mut table = LookupTable::new(0);
+        let payload = br#"[{
+            "@id": "http://purl.org/linked-delta/replace?graph=https%3A%2F%2Fexample.com%2Fa",
+            "@graph": [{
+                "@id": "https://example.com/a",
+                "@type": "http://schema.org/Thing",
+                "http://schema.org/name": [{ "@value": "Naam", "@language": "nl" }],
+                "http://schema.org/author": { "http://schema.org/age": 42 }
+            }]
+        }]"#;
+
+        let docs = parse_jsonld(&mut table, payload).unwrap();
+        let statements = &docs["https://example.com/a"];
+        assert_eq!(statements.len(), 4);
+        let name = statements
+            .iter()
+            .find(|s| table.get_by_hash(s.predicate).unwrap() == "http://schema.org/name")
+            .unwrap();
+        assert_eq!(table.get_by_hash(name.language).unwrap(), "nl");
+
+        let with_context = br#"{ "@context": {}, "@id": "https://example.com/a" }"#;
+        assert!(parse_jsonld(&mut table, with_context).is_err());
+    }
+
+    #[test]
+    fn test_parse_jsonld_blank_nodes_are_unique() {
+        let payload = br#"{
+            "@id": "http://purl.org/linked-delta/replace?graph=https%3A%2F%2Fexample.com%2Fa",
+            "@graph": { "http://schema.org/name": "Anonymous" }
+        }"#;
+        let subject = |payload: &[u8]| {
+            let mut table = LookupTable::new(0);
+            let docs = parse_jsonld(&mut table, payload).unwrap();
+            let statement = docs.values().next().unwrap()[0];
+            table.get_by_hash(statement.subject).unwrap().to_string()
+        };
+
+        let first = subject(payload);
+        assert!(first.starts_with("jsonld"));
+        assert_ne!(first, subject(payload));
+    }
+
+    #[test]
+    fn test_parse_turtle_supplants() {
+        let mut table = LookupTable::new(0);
+        let payload = b"<https://example.com/a> <http://schema.org/name> \"A\" .";
+
+        let docs = parse_turtle(&mut table, payload).unwrap();
+        let statement = docs["https://example.com/a"][0];
+        assert_eq!(table.get_by_hash(statement.graph).unwrap(), LD_SUPPLANT);
+    }
+}
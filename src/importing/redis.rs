@@ -1,5 +1,7 @@
 use crate::app_config::AppConfig;
 use crate::db::db_context::DbContext;
+use crate::delta::processor::SP_VARIABLE;
+use crate::delta::processors::invalidate_processor::ONT_INVALIDATE;
 use crate::errors::ErrorKind;
 use crate::importing::events::MessageTiming;
 use crate::importing::importer::{process_invalidate, process_message};
@@ -18,7 +20,7 @@ pub async fn import_redis(
     println!("Initialized redis config");
 
     let config = AppConfig::default();
-    let pool = DbContext::default_pool(config.database_url, config.database_pool_size)?;
+    let pool = DbContext::default_pool(config.database_url.clone(), config.database_pool_size)?;
     let mut ctx = DbContext::new(&pool);
 
     let mut pubsub = consumer.as_pubsub();
@@ -58,7 +60,7 @@ pub async fn import_redis(
                                 let result = if is_invalidate_cmd(&mut ctx, &model) {
                                     process_invalidate(&mut ctx).await
                                 } else {
-                                    process_message(&mut ctx, model).await
+                                    process_message(&mut ctx, model, &config).await
                                 };
 
                                 match result {
@@ -118,17 +120,13 @@ fn is_invalidate_cmd(ctx: &mut DbContext, model: &DocumentSet) -> bool {
         return false;
     }
 
-    let var = model.get("http://spinrdf.org/sp#Variable");
+    let var = model.get(SP_VARIABLE);
     if var.is_none() || var.unwrap().len() != 1 {
         return false;
     }
 
-    let var_uu128 = ctx
-        .lookup_table
-        .ensure_value("http://spinrdf.org/sp#Variable");
-    let inval_uu128 = ctx
-        .lookup_table
-        .ensure_value("https://ns.ontola.io/core#invalidate");
+    let var_uu128 = ctx.lookup_table.ensure_value(SP_VARIABLE);
+    let inval_uu128 = ctx.lookup_table.ensure_value(ONT_INVALIDATE);
     let q = var.unwrap().get(0).unwrap();
 
     q.subject == var_uu128
@@ -1,12 +1,13 @@
 use crate::app_config::AppConfig;
 use crate::db::db_context::DbContext;
-use crate::db::document::delete_document_data;
+use crate::db::storage::StorageContext;
+use crate::delta::processor::SP_VARIABLE;
+use crate::delta::processors::invalidate_processor::ONT_INVALIDATE;
 use crate::errors::ErrorKind;
 use crate::importing::events::MessageTiming;
 use crate::importing::importer::process_invalidate;
 use crate::importing::parsing::{parse_hndjson, DocumentSet};
 use crate::importing::redis::create_redis_consumer;
-use diesel::Connection;
 use log::Level;
 use redis::ConnectionLike;
 use std::env;
@@ -162,24 +163,26 @@ pub async fn invalidator_redis(
 }
 
 pub(crate) async fn process_message(
-    ctx: &mut DbContext<'_>,
+    ctx: &mut dyn StorageContext,
     docs: DocumentSet,
 ) -> Result<MessageTiming, ErrorKind> {
-    ctx.db_pool
-        .get()
-        .map_err(|e| ErrorKind::Unhandled(e.to_string()))?
-        .transaction::<MessageTiming, diesel::result::Error, _>(|| {
-            for (iri, _) in docs {
-                trace!(target: "apex", "Invalidating resource: {}", iri);
-                delete_document_data(&ctx.get_conn(), &iri);
-
-                let https_iri = iri.replace("http://", "https://");
-                delete_document_data(&ctx.get_conn(), &https_iri);
+    ctx.transaction(&mut |ctx| {
+        for iri in docs.keys() {
+            trace!(target: "apex", "Invalidating resource: {}", iri);
+            let https_iri = iri.replace("http://", "https://");
+
+            for iri in &[iri.clone(), https_iri] {
+                match ctx.delete_document(iri) {
+                    Ok(_) | Err(ErrorKind::NotFound) => (),
+                    Err(e) => return Err(e),
+                }
             }
+        }
+
+        Ok(())
+    })?;
 
-            Ok(MessageTiming::new())
-        })
-        .map_err(|e| ErrorKind::Unexpected(e.to_string()))
+    Ok(MessageTiming::new())
 }
 
 fn is_invalidate_all_cmd(ctx: &mut DbContext, model: &DocumentSet) -> bool {
@@ -187,17 +190,13 @@ fn is_invalidate_all_cmd(ctx: &mut DbContext, model: &DocumentSet) -> bool {
         return false;
     }
 
-    let var = model.get("http://spinrdf.org/sp#Variable");
+    let var = model.get(SP_VARIABLE);
     if var.is_none() || var.unwrap().len() != 1 {
         return false;
     }
 
-    let var_uu128 = ctx
-        .lookup_table
-        .ensure_value("http://spinrdf.org/sp#Variable");
-    let inval_uu128 = ctx
-        .lookup_table
-        .ensure_value("https://ns.ontola.io/core#invalidate");
+    let var_uu128 = ctx.lookup_table.ensure_value(SP_VARIABLE);
+    let inval_uu128 = ctx.lookup_table.ensure_value(ONT_INVALIDATE);
     let q = var.unwrap().get(0).unwrap();
 
     q.subject == var_uu128
@@ -1 +1,2 @@
 pub mod iri_utils;
+pub mod sparql;
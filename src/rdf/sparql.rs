@@ -0,0 +1,816 @@
+//! Parser for the subset of SPARQL 1.1 queries the `/sparql` endpoint supports.
+//!
+//! Supported are SELECT (with DISTINCT and `*`) and CONSTRUCT queries over a group of triple
+//! patterns with FILTER and (non-nested) OPTIONAL, followed by ORDER BY, LIMIT and OFFSET.
+//! Property paths, sub-queries, GRAPH, UNION, BIND, aggregates and the like are rejected.
+
+use crate::errors::ErrorKind;
+use crate::hashtuple::{LANG_STRING_IRI, STRING_IRI};
+use std::collections::HashMap;
+
+const RDF_TYPE_IRI: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+pub(crate) const XSD_BOOLEAN_IRI: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+pub(crate) const XSD_INTEGER_IRI: &str = "http://www.w3.org/2001/XMLSchema#integer";
+pub(crate) const XSD_DECIMAL_IRI: &str = "http://www.w3.org/2001/XMLSchema#decimal";
+pub(crate) const XSD_DOUBLE_IRI: &str = "http://www.w3.org/2001/XMLSchema#double";
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Term {
+    Variable(String),
+    Iri(String),
+    Literal {
+        value: String,
+        datatype: String,
+        language: String,
+    },
+}
+
+impl Term {
+    fn literal(value: &str, datatype: &str) -> Term {
+        Term::Literal {
+            value: value.into(),
+            datatype: datatype.into(),
+            language: String::new(),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        match self {
+            Term::Literal { datatype, .. } => {
+                datatype == XSD_INTEGER_IRI
+                    || datatype == XSD_DECIMAL_IRI
+                    || datatype == XSD_DOUBLE_IRI
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TriplePattern {
+    pub subject: Term,
+    pub predicate: Term,
+    pub object: Term,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct GroupPattern {
+    pub triples: Vec<TriplePattern>,
+    pub filters: Vec<Expression>,
+    pub optionals: Vec<GroupPattern>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Expression {
+    Term(Term),
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Box<Expression>, Comparison, Box<Expression>),
+    /// A built-in function call, the name is upper-cased.
+    Function(String, Vec<Expression>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OrderCondition {
+    pub expression: Expression,
+    pub descending: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum QueryForm {
+    Select {
+        distinct: bool,
+        /// The projected variables, `None` for `SELECT *`.
+        variables: Option<Vec<String>>,
+    },
+    Construct {
+        template: Vec<TriplePattern>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Query {
+    pub form: QueryForm,
+    pub pattern: GroupPattern,
+    pub order: Vec<OrderCondition>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+impl Query {
+    /// The variables of the query in order of appearance, excluding blank nodes.
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables = vec![];
+        collect_variables(&self.pattern, &mut variables);
+
+        variables
+    }
+
+    /// The variables in the result, in order.
+    pub fn projection(&self) -> Vec<String> {
+        match &self.form {
+            QueryForm::Select {
+                variables: Some(variables),
+                ..
+            } => variables.clone(),
+            _ => self.variables(),
+        }
+    }
+}
+
+fn collect_variables(group: &GroupPattern, variables: &mut Vec<String>) {
+    for triple in &group.triples {
+        for term in &[&triple.subject, &triple.predicate, &triple.object] {
+            if let Term::Variable(name) = term {
+                if !name.starts_with("_:") && !variables.contains(name) {
+                    variables.push(name.clone());
+                }
+            }
+        }
+    }
+    for optional in &group.optionals {
+        collect_variables(optional, variables);
+    }
+}
+
+pub(crate) fn parse_query(query: &str) -> Result<Query, ErrorKind> {
+    let tokens = tokenize(query)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        prefixes: HashMap::new(),
+        base: None,
+    };
+
+    parser.query()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Iri(String),
+    PrefixedName(String, String),
+    Variable(String),
+    BlankNode(String),
+    String(String),
+    Number(String, &'static str),
+    LangTag(String),
+    Word(String),
+    Punct(&'static str),
+}
+
+const PUNCTUATION: [&str; 18] = [
+    "^^", "&&", "||", "!=", "<=", ">=", "{", "}", "(", ")", ".", ";", ",", "*", "=", "<", ">", "!",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ErrorKind> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().take(3).collect();
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '<' && iri_end(&chars, i).is_some() {
+            let end = iri_end(&chars, i).unwrap();
+            tokens.push(Token::Iri(chars[i + 1..end].iter().collect()));
+            i = end + 1;
+        } else if (c == '?' || c == '$') && matches!(chars.get(i + 1), Some(c) if is_name_char(*c))
+        {
+            let end = scan(&chars, i + 1, is_name_char);
+            tokens.push(Token::Variable(chars[i + 1..end].iter().collect()));
+            i = end;
+        } else if c == '_' && chars.get(i + 1) == Some(&':') {
+            let end = scan(&chars, i + 2, is_name_char);
+            tokens.push(Token::BlankNode(chars[i + 2..end].iter().collect()));
+            i = end;
+        } else if c == '"' || c == '\'' {
+            let (value, end) = string(&chars, i)?;
+            tokens.push(Token::String(value));
+            i = end;
+        } else if c == '@' {
+            let end = scan(&chars, i + 1, |c| c.is_ascii_alphanumeric() || c == '-');
+            tokens.push(Token::LangTag(chars[i + 1..end].iter().collect()));
+            i = end;
+        } else if c.is_ascii_digit()
+            || ((c == '-' || c == '+' || c == '.')
+                && matches!(chars.get(i + 1), Some(c) if c.is_ascii_digit()))
+        {
+            let (number, datatype, end) = number(&chars, i);
+            tokens.push(Token::Number(number, datatype));
+            i = end;
+        } else if c.is_alphabetic() || c == ':' {
+            let end = scan(&chars, i, |c| is_name_char(c) || c == '-');
+            let word: String = chars[i..end].iter().collect();
+            if chars.get(end) == Some(&':') || c == ':' {
+                let local_start = if c == ':' { i + 1 } else { end + 1 };
+                let mut local_end = scan(&chars, local_start, |c| {
+                    is_name_char(c) || c == '-' || c == '.' || c == ':' || c == '%'
+                });
+                while local_end > local_start && chars[local_end - 1] == '.' {
+                    local_end -= 1;
+                }
+                let prefix = if c == ':' { String::new() } else { word };
+                tokens.push(Token::PrefixedName(
+                    prefix,
+                    chars[local_start..local_end].iter().collect(),
+                ));
+                i = local_end;
+            } else {
+                tokens.push(Token::Word(word));
+                i = end;
+            }
+        } else {
+            match PUNCTUATION.iter().find(|p| rest.starts_with(*p)) {
+                Some(p) => {
+                    tokens.push(Token::Punct(p));
+                    i += p.len();
+                }
+                None => bail!(ErrorKind::ParserError(format!(
+                    "Unexpected character '{}'",
+                    c
+                ))),
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn scan(chars: &[char], start: usize, f: impl Fn(char) -> bool) -> usize {
+    let mut end = start;
+    while end < chars.len() && f(chars[end]) {
+        end += 1;
+    }
+
+    end
+}
+
+/// The position of the closing `>` if an IRI starts at `start`.
+fn iri_end(chars: &[char], start: usize) -> Option<usize> {
+    for (i, c) in chars.iter().enumerate().skip(start + 1) {
+        match c {
+            '>' => return Some(i),
+            '<' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' => return None,
+            c if c.is_whitespace() => return None,
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn string(chars: &[char], start: usize) -> Result<(String, usize), ErrorKind> {
+    let quote = chars[start];
+    let long = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+    let mut i = if long { start + 3 } else { start + 1 };
+    let mut value = String::new();
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == quote
+            && (!long || (chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote)))
+        {
+            return Ok((value, if long { i + 3 } else { i + 1 }));
+        } else if c == '\\' {
+            let escaped = match chars.get(i + 1) {
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('b') => '\u{8}',
+                Some('f') => '\u{c}',
+                Some('"') => '"',
+                Some('\'') => '\'',
+                Some('\\') => '\\',
+                Some('u') => {
+                    let hex: String = chars.iter().skip(i + 2).take(4).collect();
+                    i += 4;
+                    u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                        .ok_or_else(|| ErrorKind::ParserError("Invalid unicode escape".into()))?
+                }
+                _ => bail!(ErrorKind::ParserError("Invalid escape sequence".into())),
+            };
+            value.push(escaped);
+            i += 2;
+        } else if !long && (c == '\n' || c == '\r') {
+            break;
+        } else {
+            value.push(c);
+            i += 1;
+        }
+    }
+
+    Err(ErrorKind::ParserError("Unterminated string".into()))
+}
+
+fn number(chars: &[char], start: usize) -> (String, &'static str, usize) {
+    let mut end = start;
+    if chars[end] == '-' || chars[end] == '+' {
+        end += 1;
+    }
+    end = scan(chars, end, |c| c.is_ascii_digit());
+    let mut datatype = XSD_INTEGER_IRI;
+
+    if chars.get(end) == Some(&'.') && matches!(chars.get(end + 1), Some(c) if c.is_ascii_digit()) {
+        end = scan(chars, end + 1, |c| c.is_ascii_digit());
+        datatype = XSD_DECIMAL_IRI;
+    }
+    if chars.get(end) == Some(&'e') || chars.get(end) == Some(&'E') {
+        let mut exponent = end + 1;
+        if chars.get(exponent) == Some(&'-') || chars.get(exponent) == Some(&'+') {
+            exponent += 1;
+        }
+        if matches!(chars.get(exponent), Some(c) if c.is_ascii_digit()) {
+            end = scan(chars, exponent, |c| c.is_ascii_digit());
+            datatype = XSD_DOUBLE_IRI;
+        }
+    }
+
+    (chars[start..end].iter().collect(), datatype, end)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    prefixes: HashMap<String, String>,
+    base: Option<String>,
+}
+
+impl Parser {
+    fn query(&mut self) -> Result<Query, ErrorKind> {
+        self.prologue()?;
+
+        let form = if self.eat_keyword("SELECT") {
+            let distinct = self.eat_keyword("DISTINCT") || self.eat_keyword("REDUCED");
+            let variables = if self.eat(&Token::Punct("*")) {
+                None
+            } else {
+                let mut variables = vec![];
+                while let Some(Token::Variable(name)) = self.peek().cloned() {
+                    self.position += 1;
+                    variables.push(name);
+                }
+                if variables.is_empty() {
+                    bail!(self.error("Expected variables or '*' after SELECT"));
+                }
+                Some(variables)
+            };
+
+            QueryForm::Select {
+                distinct,
+                variables,
+            }
+        } else if self.eat_keyword("CONSTRUCT") {
+            self.expect(&Token::Punct("{"))?;
+            let template = self.triples_until("}")?;
+            self.expect(&Token::Punct("}"))?;
+            if template.iter().any(|t| {
+                [&t.subject, &t.predicate, &t.object]
+                    .iter()
+                    .any(|t| matches!(t, Term::Variable(name) if name.starts_with("_:")))
+            }) {
+                bail!(self.error("Blank nodes in CONSTRUCT templates aren't supported"));
+            }
+
+            QueryForm::Construct { template }
+        } else {
+            bail!(self.error("Only SELECT and CONSTRUCT queries are supported"));
+        };
+
+        self.eat_keyword("WHERE");
+        let pattern = self.group(true)?;
+
+        let mut order = vec![];
+        if self.eat_keyword("ORDER") {
+            if !self.eat_keyword("BY") {
+                bail!(self.error("Expected BY after ORDER"));
+            }
+            while let Some(condition) = self.order_condition()? {
+                order.push(condition);
+            }
+            if order.is_empty() {
+                bail!(self.error("Expected an order condition"));
+            }
+        }
+
+        let mut limit = None;
+        let mut offset = None;
+        loop {
+            if self.eat_keyword("LIMIT") {
+                limit = Some(self.integer()?);
+            } else if self.eat_keyword("OFFSET") {
+                offset = Some(self.integer()?);
+            } else {
+                break;
+            }
+        }
+
+        if self.position < self.tokens.len() {
+            bail!(self.error("Unexpected trailing input"));
+        }
+
+        Ok(Query {
+            form,
+            pattern,
+            order,
+            limit,
+            offset,
+        })
+    }
+
+    fn prologue(&mut self) -> Result<(), ErrorKind> {
+        loop {
+            if self.eat_keyword("PREFIX") {
+                let prefix = match self.next() {
+                    Some(Token::PrefixedName(prefix, local)) if local.is_empty() => prefix,
+                    _ => bail!(self.error("Expected a prefix name")),
+                };
+                let iri = self.iri_ref()?;
+                self.prefixes.insert(prefix, iri);
+            } else if self.eat_keyword("BASE") {
+                self.base = Some(self.iri_ref()?);
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn group(&mut self, allow_optional: bool) -> Result<GroupPattern, ErrorKind> {
+        self.expect(&Token::Punct("{"))?;
+        let mut group = GroupPattern::default();
+
+        loop {
+            if self.eat(&Token::Punct("}")) {
+                return Ok(group);
+            } else if self.eat(&Token::Punct(".")) {
+                continue;
+            } else if self.eat_keyword("FILTER") {
+                let expression = match self.peek() {
+                    Some(Token::Punct("(")) => self.bracketted()?,
+                    _ => self.primary()?,
+                };
+                group.filters.push(expression);
+            } else if self.eat_keyword("OPTIONAL") {
+                if !allow_optional {
+                    bail!(self.error("Nested OPTIONAL groups aren't supported"));
+                }
+                group.optionals.push(self.group(false)?);
+            } else if let Some(Token::Word(word)) = self.peek() {
+                bail!(self.error(&format!("'{}' isn't supported", word)));
+            } else {
+                group.triples.extend(self.triples_same_subject()?);
+            }
+        }
+    }
+
+    fn triples_until(&mut self, end: &'static str) -> Result<Vec<TriplePattern>, ErrorKind> {
+        let mut triples = vec![];
+        while self.peek() != Some(&Token::Punct(end)) {
+            if self.eat(&Token::Punct(".")) {
+                continue;
+            }
+            triples.extend(self.triples_same_subject()?);
+        }
+
+        Ok(triples)
+    }
+
+    fn triples_same_subject(&mut self) -> Result<Vec<TriplePattern>, ErrorKind> {
+        let subject = self.term()?;
+        let mut triples = vec![];
+
+        loop {
+            let predicate = if self.eat_keyword_exact("a") {
+                Term::Iri(RDF_TYPE_IRI.into())
+            } else {
+                self.term()?
+            };
+            loop {
+                triples.push(TriplePattern {
+                    subject: subject.clone(),
+                    predicate: predicate.clone(),
+                    object: self.term()?,
+                });
+                if !self.eat(&Token::Punct(",")) {
+                    break;
+                }
+            }
+            if !self.eat(&Token::Punct(";")) {
+                return Ok(triples);
+            }
+            match self.peek() {
+                Some(Token::Punct(".")) | Some(Token::Punct("}")) | None => return Ok(triples),
+                _ => (),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, ErrorKind> {
+        let term = match self.next() {
+            Some(Token::Variable(name)) => Term::Variable(name),
+            Some(Token::BlankNode(label)) => Term::Variable(format!("_:{}", label)),
+            Some(Token::Iri(iri)) => Term::Iri(self.resolve(&iri)),
+            Some(Token::PrefixedName(prefix, local)) => Term::Iri(self.expand(&prefix, &local)?),
+            Some(Token::Number(value, datatype)) => Term::literal(&value, datatype),
+            Some(Token::Word(word)) if word == "true" || word == "false" => {
+                Term::literal(&word, XSD_BOOLEAN_IRI)
+            }
+            Some(Token::String(value)) => match self.peek().cloned() {
+                Some(Token::LangTag(language)) => {
+                    self.position += 1;
+                    Term::Literal {
+                        value,
+                        datatype: LANG_STRING_IRI.into(),
+                        language,
+                    }
+                }
+                Some(Token::Punct("^^")) => {
+                    self.position += 1;
+                    let datatype = match self.term()? {
+                        Term::Iri(iri) => iri,
+                        _ => bail!(self.error("Expected a datatype IRI")),
+                    };
+                    Term::literal(&value, &datatype)
+                }
+                _ => Term::literal(&value, STRING_IRI),
+            },
+            _ => bail!(self.error("Expected a term")),
+        };
+
+        Ok(term)
+    }
+
+    fn order_condition(&mut self) -> Result<Option<OrderCondition>, ErrorKind> {
+        let descending = if self.eat_keyword("DESC") {
+            true
+        } else if self.eat_keyword("ASC") {
+            false
+        } else {
+            return match self.peek() {
+                Some(Token::Variable(_)) | Some(Token::Punct("(")) => Ok(Some(OrderCondition {
+                    expression: self.primary()?,
+                    descending: false,
+                })),
+                _ => Ok(None),
+            };
+        };
+
+        Ok(Some(OrderCondition {
+            expression: self.bracketted()?,
+            descending,
+        }))
+    }
+
+    fn bracketted(&mut self) -> Result<Expression, ErrorKind> {
+        self.expect(&Token::Punct("("))?;
+        let expression = self.expression()?;
+        self.expect(&Token::Punct(")"))?;
+
+        Ok(expression)
+    }
+
+    fn expression(&mut self) -> Result<Expression, ErrorKind> {
+        let mut left = self.conjunction()?;
+        while self.eat(&Token::Punct("||")) {
+            left = Expression::Or(Box::new(left), Box::new(self.conjunction()?));
+        }
+
+        Ok(left)
+    }
+
+    fn conjunction(&mut self) -> Result<Expression, ErrorKind> {
+        let mut left = self.relational()?;
+        while self.eat(&Token::Punct("&&")) {
+            left = Expression::And(Box::new(left), Box::new(self.relational()?));
+        }
+
+        Ok(left)
+    }
+
+    fn relational(&mut self) -> Result<Expression, ErrorKind> {
+        let left = self.unary()?;
+        let comparison = match self.peek() {
+            Some(Token::Punct("=")) => Comparison::Eq,
+            Some(Token::Punct("!=")) => Comparison::Ne,
+            Some(Token::Punct("<")) => Comparison::Lt,
+            Some(Token::Punct(">")) => Comparison::Gt,
+            Some(Token::Punct("<=")) => Comparison::Le,
+            Some(Token::Punct(">=")) => Comparison::Ge,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+
+        Ok(Expression::Compare(
+            Box::new(left),
+            comparison,
+            Box::new(self.unary()?),
+        ))
+    }
+
+    fn unary(&mut self) -> Result<Expression, ErrorKind> {
+        if self.eat(&Token::Punct("!")) {
+            Ok(Expression::Not(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, ErrorKind> {
+        match self.peek().cloned() {
+            Some(Token::Punct("(")) => self.bracketted(),
+            Some(Token::Word(name)) if name != "true" && name != "false" => {
+                self.position += 1;
+                self.expect(&Token::Punct("("))?;
+                let mut arguments = vec![];
+                if !self.eat(&Token::Punct(")")) {
+                    loop {
+                        arguments.push(self.expression()?);
+                        if self.eat(&Token::Punct(")")) {
+                            break;
+                        }
+                        self.expect(&Token::Punct(","))?;
+                    }
+                }
+
+                Ok(Expression::Function(name.to_ascii_uppercase(), arguments))
+            }
+            _ => Ok(Expression::Term(self.term()?)),
+        }
+    }
+
+    fn integer(&mut self) -> Result<u64, ErrorKind> {
+        match self.next() {
+            Some(Token::Number(value, XSD_INTEGER_IRI)) => value
+                .parse()
+                .map_err(|_| self.error("Expected a non-negative integer")),
+            _ => Err(self.error("Expected an integer")),
+        }
+    }
+
+    fn iri_ref(&mut self) -> Result<String, ErrorKind> {
+        match self.next() {
+            Some(Token::Iri(iri)) => Ok(self.resolve(&iri)),
+            _ => Err(self.error("Expected an IRI")),
+        }
+    }
+
+    fn resolve(&self, iri: &str) -> String {
+        match &self.base {
+            Some(base) if !iri.contains(':') => format!("{}{}", base, iri),
+            _ => iri.into(),
+        }
+    }
+
+    fn expand(&self, prefix: &str, local: &str) -> Result<String, ErrorKind> {
+        match self.prefixes.get(prefix) {
+            Some(namespace) => Ok(format!("{}{}", namespace, local)),
+            None => Err(self.error(&format!("Undefined prefix '{}'", prefix))),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn eat_keyword_exact(&mut self, keyword: &str) -> bool {
+        self.eat(&Token::Word(keyword.into()))
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), ErrorKind> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {:?}", token)))
+        }
+    }
+
+    fn error(&self, message: &str) -> ErrorKind {
+        ErrorKind::ParserError(format!("{} at token {}", message, self.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_select() {
+        let query = parse_query(
+            "PREFIX schema: <https://schema.org/>
+            SELECT DISTINCT ?name ?age WHERE {
+                ?person a schema:Person ;
+                    schema:name ?name .
+                OPTIONAL { ?person schema:age ?age }
+                FILTER (lang(?name) = \"en\" && !bound(?age) || ?age >= 18)
+            }
+            ORDER BY DESC(?age) ?name
+            LIMIT 10 OFFSET 20",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.form,
+            QueryForm::Select {
+                distinct: true,
+                variables: Some(vec!["name".into(), "age".into()]),
+            }
+        );
+        assert_eq!(query.pattern.triples.len(), 2);
+        assert_eq!(
+            query.pattern.triples[0].predicate,
+            Term::Iri(RDF_TYPE_IRI.into())
+        );
+        assert_eq!(
+            query.pattern.triples[1].object,
+            Term::Variable("name".into())
+        );
+        assert_eq!(query.pattern.optionals.len(), 1);
+        assert!(matches!(query.pattern.filters[0], Expression::Or(_, _)));
+        assert_eq!(query.order.len(), 2);
+        assert!(query.order[0].descending);
+        assert_eq!(query.limit, Some(10));
+        assert_eq!(query.offset, Some(20));
+        assert_eq!(query.variables(), vec!["person", "name", "age"]);
+    }
+
+    #[test]
+    fn test_parse_construct_with_literals() {
+        let query = parse_query(
+            "CONSTRUCT { ?s <https://schema.org/name> ?o } WHERE {
+                ?s <https://schema.org/name> ?o , \"Bob\"@EN , 'x\\'y'^^<http://example.com/t> , -1.5e3 .
+            }",
+        )
+        .unwrap();
+
+        let objects: Vec<Term> = query
+            .pattern
+            .triples
+            .iter()
+            .map(|t| t.object.clone())
+            .collect();
+        assert_eq!(
+            objects,
+            vec![
+                Term::Variable("o".into()),
+                Term::Literal {
+                    value: "Bob".into(),
+                    datatype: LANG_STRING_IRI.into(),
+                    language: "EN".into(),
+                },
+                Term::literal("x'y", "http://example.com/t"),
+                Term::literal("-1.5e3", XSD_DOUBLE_IRI),
+            ]
+        );
+        assert!(parse_query("SELECT * WHERE { ?s ?p ?o } GROUP BY ?s").is_err());
+        assert!(parse_query("ASK { ?s ?p ?o }").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::app_config::AppConfig;
+use crate::db::bgp::{BGPQuery, BGPQueryRequest, Solution};
+use crate::db::db_context::{DbContext, DbPool};
+use crate::db::hpf::Position;
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, LookupTable};
+use crate::serving::negotiation::{negotiate_from, not_acceptable};
+use crate::serving::response_type::{ResponseType, RDF_RESPONSE_TYPES};
+use crate::serving::responses::set_default_headers;
+use crate::serving::serialization::{bindings_to_sparql_json, serialize_bulk, Binding};
+use actix_web::error::BlockingError;
+use actix_web::{post, web, HttpResponse, Responder};
+use humantime::format_duration;
+use std::collections::HashSet;
+use std::time::Instant;
+
+type Bindings = (Vec<(String, usize, Position)>, Vec<Solution>, LookupTable);
+
+/// Evaluates a basic graph pattern, responding with the variable bindings as SPARQL JSON results
+/// or with the matched statements when an RDF format is requested.
+#[post("/bgp")]
+pub(crate) async fn bgp(
+    req: actix_web::HttpRequest,
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    payload: web::Json<BGPQueryRequest>,
+) -> impl Responder {
+    let available = [&[ResponseType::SPARQLJSON], &RDF_RESPONSE_TYPES[..]].concat();
+    let response_type = match negotiate_from(req.headers(), &available) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
+    };
+    let pl = pool.into_inner();
+
+    let res = web::block(move || -> Result<Bindings, ErrorKind> {
+        let mut ctx = DbContext::new(&pl);
+        let query = BGPQuery::parse(&mut ctx, &payload)?;
+
+        let fetch_start = Instant::now();
+        let solutions = query.execute(&mut ctx)?;
+        let fetch_time = Instant::now().duration_since(fetch_start);
+        debug!(target: "apex", "Fetching cost: {}", format_duration(fetch_time));
+
+        Ok((query.variables(), solutions, ctx.lookup_table))
+    })
+    .await;
+
+    let (variables, solutions, table) = match res {
+        Ok(res) => res,
+        Err(err) => {
+            error!(target: "apex", "Caught error: {:?}", err);
+            return match err {
+                BlockingError::Error(ErrorKind::ParserError(msg)) => {
+                    HttpResponse::BadRequest().body(msg)
+                }
+                _ => HttpResponse::InternalServerError().finish(),
+            };
+        }
+    };
+
+    let body = match response_type {
+        ResponseType::SPARQLJSON => {
+            let names: Vec<String> = variables.iter().map(|(name, _, _)| name.clone()).collect();
+            let bindings: Vec<Binding> = solutions
+                .iter()
+                .map(|solution| {
+                    variables
+                        .iter()
+                        .map(|(_, pattern, position)| Some((solution[*pattern], *position)))
+                        .collect()
+                })
+                .collect();
+
+            bindings_to_sparql_json(&names, &bindings, &table)
+        }
+        _ => serialize_bulk(
+            &response_type,
+            (vec![Some(matched(solutions))], table),
+            &config.turtle_prefixes,
+        )
+        .unwrap(),
+    };
+
+    set_default_headers(&mut HttpResponse::Ok(), &response_type).body(body)
+}
+
+/// The distinct statements of all solutions.
+fn matched(solutions: Vec<Solution>) -> HashModel {
+    let mut seen = HashSet::new();
+
+    solutions
+        .into_iter()
+        .flatten()
+        .filter(|statement| seen.insert(*statement))
+        .collect()
+}
@@ -1,7 +1,6 @@
 use crate::app_config::AppConfig;
 use crate::db::cache_control::CacheControl;
-use crate::db::db_context::{DbContext, DbPool};
-use crate::db::document::{doc_by_iri, update_cache_control};
+use crate::db::storage::{Storage, StorageContext};
 use crate::errors::ErrorKind;
 use crate::hashtuple::{HashModel, LookupTable, Statement};
 use crate::importing::importer::process_message;
@@ -9,14 +8,16 @@ use crate::importing::parsing::{parse_hndjson, DocumentSet};
 use crate::models::Document;
 use crate::rdf::iri_utils::stem_iri;
 use crate::serving::bulk_ctx::BulkCtx;
+use crate::serving::negotiation::{negotiate, not_acceptable};
 use crate::serving::reporter::Reporter;
-use crate::serving::response_type::{ResponseType, NQUADS_MIME, NTRIPLES_MIME};
+use crate::serving::response_type::ResponseType;
 use crate::serving::responses::set_default_headers;
 use crate::serving::route::route;
 use crate::serving::serialization::{
-    bulk_result_to_hextuples, bulk_result_to_nquads, bulk_result_to_ntriples,
+    is_streamable, serialize_bulk, to_document_graph, to_document_graphs,
 };
 use crate::serving::sessions::{session_id, session_info};
+use crate::serving::streaming::{error_response, response_channel, ChunkSender};
 use crate::serving::timings::{AuthorizeTiming, BulkTiming};
 use actix_http::error::BlockingError;
 use actix_web::client::SendRequestError;
@@ -27,9 +28,9 @@ use itertools::Itertools;
 use log::Level;
 use percent_encoding::percent_decode_str;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct FormData {
@@ -82,7 +83,7 @@ pub(crate) struct Resource {
 #[post("/link-lib/bulk")]
 pub(crate) async fn bulk<'a>(
     req: actix_web::HttpRequest,
-    pool: web::Data<DbPool>,
+    storage: web::Data<dyn Storage>,
     config: web::Data<AppConfig>,
     reporter: web::Data<Reporter>,
     payload: web::Payload,
@@ -90,22 +91,16 @@ pub(crate) async fn bulk<'a>(
     let parse_start = Instant::now();
     reporter.register_bulk_request();
 
-    let pl = pool.clone().into_inner();
-
-    let lang = match session_id(&req) {
-        Ok(sid) => match session_info(&sid).await {
-            Ok(info) => Some(info.user.language),
-            Err(e) => match e {
-                ErrorKind::ExpiredSession => {
-                    // TODO: REFRESH
-                    debug!(target: "apex", "EXPIRED SESSION");
-                    None
-                }
-                _ => Some(String::from("en")), // TODO: Take from manifest
-            },
-        },
-        Err(_) => Some(String::from("en")),
+    let response_type = match negotiate(req.headers()) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
     };
+    let document_graphs =
+        response_type == ResponseType::TRIG || document_graphs_requested(req.query_string());
+
+    let store = Arc::clone(&storage);
+
+    let lang = session_language(&req).await;
     let mut req = BulkCtx::new(req, config, lang);
 
     let resources = match parse_request(payload).await {
@@ -123,10 +118,26 @@ pub(crate) async fn bulk<'a>(
     let parse_end = Instant::now();
     let parse_time = parse_end.duration_since(parse_start);
 
-    let (mut bulk_docs, mut lookup_table) = match lookup_resources(&req, pl, bulk_resources).await {
-        Ok(res) => (res.0, res.1),
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    if is_streamable(&response_type) {
+        let (chunks, response) = response_channel(response_type);
+        actix_rt::spawn(stream_bulk(
+            req,
+            storage,
+            reporter,
+            chunks,
+            resources,
+            document_graphs,
+            parse_time,
+        ));
+
+        return response.await;
+    }
+
+    let (mut bulk_docs, mut lookup_table) =
+        match lookup_resources(&req, store, bulk_resources).await {
+            Ok(res) => (res.0, res.1),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
     let lookup_end = Instant::now();
     let lookup_time = lookup_end.duration_since(parse_end);
 
@@ -142,7 +153,7 @@ pub(crate) async fn bulk<'a>(
     let authorize_timing = if private_or_missing.len() > 0 {
         let t = process_private_and_missing(
             &mut req,
-            pool,
+            storage,
             lookup_table,
             &mut bulk_docs,
             &private_or_missing,
@@ -151,7 +162,7 @@ pub(crate) async fn bulk<'a>(
         .await;
         let (table, timing) = match t {
             Ok(t) => t,
-            Err(res) => return res,
+            Err(e) => return error_response(e),
         };
         lookup_table = table;
 
@@ -163,55 +174,196 @@ pub(crate) async fn bulk<'a>(
 
     let serialize_start = Instant::now();
     // 8. RS sends response back to client
+    let iris: Vec<String> = bulk_docs.iter().map(|r| r.iri.clone()).collect();
     let bulk_docs = bulk_docs
         .into_iter()
-        .map(|resource| {
-            if resource.status == 404 {
-                Some(vec![status_code_statement(
-                    &mut lookup_table,
-                    &resource.iri,
-                    404,
-                )])
-            } else {
-                let mut model = resource.data;
-                model.push(status_code_statement(
-                    &mut lookup_table,
-                    &resource.iri,
-                    resource.status,
-                ));
-
-                Some(model)
-            }
-        })
+        .map(|resource| Some(resource_model(&mut lookup_table, resource)))
         .collect();
 
-    let bulk_docs = (bulk_docs, lookup_table);
+    let bulk_docs = if document_graphs {
+        to_document_graphs((bulk_docs, lookup_table), &iris)
+    } else {
+        (bulk_docs, lookup_table)
+    };
 
-    let (body, response_type) = if let Some(accept) = req.req.headers().get(header::ACCEPT) {
-        let accept = accept.to_str().unwrap();
-        if accept == NQUADS_MIME {
-            (bulk_result_to_nquads(bulk_docs), ResponseType::NQUADS)
-        } else if accept == NTRIPLES_MIME {
-            (bulk_result_to_ntriples(bulk_docs), ResponseType::NTRIPLES)
-        } else {
-            (bulk_result_to_hextuples(bulk_docs), ResponseType::HEXTUPLE)
+    let body = serialize_bulk(&response_type, bulk_docs, &req.config.turtle_prefixes).unwrap();
+    let response = set_default_headers(&mut HttpResponse::Ok(), &response_type).body(body);
+    let serialize_time = Instant::now().duration_since(serialize_start);
+
+    let timing = BulkTiming::from_durations(
+        parse_time,
+        lookup_time,
+        sort_time,
+        authorize_timing,
+        serialize_time,
+    );
+    timing.report();
+    reporter.add_bulk_timing(timing);
+
+    response
+}
+
+/// Sends the public documents while they are loaded, and the others once they are authorized.
+///
+/// Public documents are serialized while loading, so their serialization counts towards the lookup
+/// timing.
+async fn stream_bulk(
+    mut req: BulkCtx,
+    storage: web::Data<dyn Storage>,
+    reporter: web::Data<Reporter>,
+    mut chunks: ChunkSender,
+    resources: Vec<String>,
+    document_graphs: bool,
+    parse_time: Duration,
+) {
+    let lookup_start = Instant::now();
+    let store = Arc::clone(&storage);
+    let disable_persistence = req.config.disable_persistence;
+    let lang = req.language.clone();
+
+    let loaded = web::block(move || {
+        let mut ctx = store.open(lang);
+        let mut private_or_missing = vec![];
+        let mut resources_in_store = vec![];
+
+        for iri in resources {
+            let resource = if disable_persistence {
+                missing_resource(iri)
+            } else {
+                load_resource(&mut *ctx, iri)
+            };
+            if resource.status == 200 && !resource.data.is_empty() {
+                resources_in_store.push(resource.iri.clone());
+            }
+            if resource.cache_control != CacheControl::Public || resource.status != 200 {
+                private_or_missing.push(resource);
+                continue;
+            }
+
+            let iri = resource.iri.clone();
+            let mut model = resource_model(ctx.lookup_table(), resource);
+            if document_graphs {
+                model = to_document_graph(ctx.lookup_table(), model, &iri);
+            }
+            if let Err(e) = chunks.send(model, ctx.lookup_table()) {
+                chunks.finish(Err(e));
+                return Err(());
+            }
         }
+
+        Ok((
+            chunks,
+            private_or_missing,
+            resources_in_store,
+            ctx.into_lookup_table(),
+        ))
+    })
+    .await;
+    let (mut chunks, mut bulk_docs, resources_in_store, mut lookup_table) = match loaded {
+        Ok(loaded) => loaded,
+        Err(_) => return,
+    };
+    let lookup_time = Instant::now().duration_since(lookup_start);
+
+    let authorize_timing = if !bulk_docs.is_empty() {
+        let private_or_missing = bulk_docs.iter().map(|r| r.iri.clone()).collect();
+        let t = process_private_and_missing(
+            &mut req,
+            storage,
+            lookup_table,
+            &mut bulk_docs,
+            &private_or_missing,
+            &resources_in_store,
+        )
+        .await;
+        let (table, timing) = match t {
+            Ok(t) => t,
+            Err(e) => {
+                chunks.finish(Err(e));
+                return;
+            }
+        };
+        lookup_table = table;
+
+        Some(timing)
     } else {
-        (bulk_result_to_hextuples(bulk_docs), ResponseType::HEXTUPLE)
+        debug!(target: "apex", "All resources are present and public");
+        None
     };
+
+    let serialize_start = Instant::now();
+    let sent = web::block(move || -> Result<(), ()> {
+        let result = bulk_docs.into_iter().try_for_each(|resource| {
+            let iri = resource.iri.clone();
+            let mut model = resource_model(&mut lookup_table, resource);
+            if document_graphs {
+                model = to_document_graph(&mut lookup_table, model, &iri);
+            }
+
+            chunks.send(model, &lookup_table)
+        });
+        chunks.finish(result);
+
+        Ok(())
+    })
+    .await;
+    if let Err(e) = sent {
+        error!(target: "apex", "Streaming task failed: {:?}", e);
+    }
     let serialize_time = Instant::now().duration_since(serialize_start);
 
     let timing = BulkTiming::from_durations(
         parse_time,
         lookup_time,
-        sort_time,
+        Duration::default(),
         authorize_timing,
         serialize_time,
     );
     timing.report();
     reporter.add_bulk_timing(timing);
+}
 
-    set_default_headers(&mut HttpResponse::Ok(), &response_type).body(body)
+/// The language of the user of the session of the request.
+pub(crate) async fn session_language(req: &actix_web::HttpRequest) -> Option<String> {
+    match session_id(req) {
+        Ok(sid) => match session_info(&sid).await {
+            Ok(info) => Some(info.user.language),
+            Err(e) => match e {
+                ErrorKind::ExpiredSession => {
+                    // TODO: REFRESH
+                    debug!(target: "apex", "EXPIRED SESSION");
+                    None
+                }
+                _ => Some(String::from("en")), // TODO: Take from manifest
+            },
+        },
+        Err(_) => Some(String::from("en")),
+    }
+}
+
+/// Whether the client asked for each document in its own named graph with `?graph=document`.
+fn document_graphs_requested(query: &str) -> bool {
+    serde_qs::from_str::<HashMap<String, String>>(query)
+        .ok()
+        .and_then(|params| params.get("graph").cloned())
+        .as_deref()
+        == Some("document")
+}
+
+/// The statements of the resource with its status code, missing resources only have the latter.
+fn resource_model(lookup_table: &mut LookupTable, resource: Resource) -> HashModel {
+    let mut model = if resource.status == 404 {
+        vec![]
+    } else {
+        resource.data
+    };
+    model.push(status_code_statement(
+        lookup_table,
+        &resource.iri,
+        resource.status,
+    ));
+
+    model
 }
 
 fn status_code_statement(lookup_table: &mut LookupTable, iri: &str, status: u16) -> Statement {
@@ -225,6 +377,21 @@ fn status_code_statement(lookup_table: &mut LookupTable, iri: &str, status: u16)
     }
 }
 
+/// The resources the session of the request may read, without fetching their bodies.
+pub(crate) async fn authorized_resources(
+    req: &mut BulkCtx,
+    resources: &Vec<String>,
+) -> Result<Vec<String>, ErrorKind> {
+    let authorized = authorize_partitioned(req, resources, resources)
+        .await?
+        .into_iter()
+        .filter(|r| r.status == 200 || r.status == 204)
+        .map(|r| r.iri)
+        .collect();
+
+    Ok(authorized)
+}
+
 async fn authorize_partitioned(
     req: &mut BulkCtx,
     resources: &Vec<String>,
@@ -374,72 +541,71 @@ async fn authorize_via_bulk(
 
 async fn lookup_resources(
     req: &BulkCtx,
-    pl: Arc<DbPool>,
+    storage: Arc<dyn Storage>,
     bulk_resources: Vec<String>,
 ) -> Result<(Vec<Resource>, LookupTable), BlockingError<i32>> {
     let disable_persistence = req.config.disable_persistence.clone();
     let lang = req.language.clone();
 
     web::block(move || -> Result<(Vec<Resource>, LookupTable), i32> {
-        let mut ctx = DbContext::new_with_lang(&pl, lang);
+        let mut ctx = storage.open(lang);
         let resources = bulk_resources.into_iter().map(stem_iri);
 
         let models: Vec<Resource> = if disable_persistence {
-            resources
-                .map(|iri| Resource {
-                    iri,
-                    status: 404,
-                    cache_control: CacheControl::Private,
-                    data: HashModel::new(),
-                })
-                .collect()
+            resources.map(missing_resource).collect()
         } else {
-            resources
-                .map(|iri| match doc_by_iri(&mut ctx, &iri) {
-                    Ok((doc, data)) => {
-                        trace!(target: "apex", "Load success: {}", iri);
-                        Resource {
-                            iri,
-                            status: if data.is_empty() { 204 } else { 200 },
-                            cache_control: doc.cache_control.into(),
-                            data,
-                        }
-                    }
-                    Err(ErrorKind::EmptyDocument) => {
-                        trace!(target: "apex", "Load failed emtpy: {}", iri);
-                        Resource {
-                            iri,
-                            status: 404,
-                            cache_control: CacheControl::Private,
-                            data: HashModel::new(),
-                        }
-                    }
-                    Err(e) => {
-                        trace!(target: "apex", "Load failed: {}, {}", iri, e);
-                        Resource {
-                            iri,
-                            status: 500,
-                            cache_control: CacheControl::Private,
-                            data: HashModel::new(),
-                        }
-                    }
-                })
-                .collect()
+            resources.map(|iri| load_resource(&mut *ctx, iri)).collect()
         };
 
-        Ok((models, ctx.lookup_table))
+        Ok((models, ctx.into_lookup_table()))
     })
     .await
 }
 
+fn load_resource(ctx: &mut dyn StorageContext, iri: String) -> Resource {
+    match ctx.get_document(&iri) {
+        Ok((doc, data)) => {
+            trace!(target: "apex", "Load success: {}", iri);
+            Resource {
+                iri,
+                status: if data.is_empty() { 204 } else { 200 },
+                cache_control: doc.cache_control.into(),
+                data,
+            }
+        }
+        Err(ErrorKind::EmptyDocument) => {
+            trace!(target: "apex", "Load failed emtpy: {}", iri);
+            missing_resource(iri)
+        }
+        Err(e) => {
+            trace!(target: "apex", "Load failed: {}, {}", iri, e);
+            Resource {
+                iri,
+                status: 500,
+                cache_control: CacheControl::Private,
+                data: HashModel::new(),
+            }
+        }
+    }
+}
+
+fn missing_resource(iri: String) -> Resource {
+    Resource {
+        iri,
+        status: 404,
+        cache_control: CacheControl::Private,
+        data: HashModel::new(),
+    }
+}
+
 async fn process_private_and_missing(
     mut req: &mut BulkCtx,
-    pool: web::Data<DbPool>,
+    storage: web::Data<dyn Storage>,
     mut lookup_table: LookupTable,
     bulk_docs: &mut Vec<Resource>,
     non_public_resources: &Vec<String>,
     resources_in_store: &Vec<String>,
-) -> Result<(LookupTable, AuthorizeTiming), actix_http::Response> {
+) -> Result<(LookupTable, AuthorizeTiming), ErrorKind> {
     let authorize_start = Instant::now();
 
     trace!(target: "apex", "Authorize / fetch {} documents", non_public_resources.len());
@@ -464,13 +630,13 @@ async fn process_private_and_missing(
             }
             Err(ErrorKind::ParserError(msg)) => {
                 debug!(target: "apex", "Error while authorizing: {}", msg);
-                return Err(HttpResponse::BadRequest().finish());
+                return Err(ErrorKind::ParserError(msg));
             }
-            Err(ErrorKind::BackendUnavailable) => return Err(HttpResponse::BadGateway().finish()),
-            Err(ErrorKind::Timeout) => return Err(HttpResponse::GatewayTimeout().finish()),
+            Err(ErrorKind::BackendUnavailable) => return Err(ErrorKind::BackendUnavailable),
+            Err(ErrorKind::Timeout) => return Err(ErrorKind::Timeout),
             Err(err) => {
                 error!(target: "apex", "Unexpected error while authorizing: {}", err);
-                return Err(HttpResponse::InternalServerError().finish());
+                return Err(ErrorKind::Unexpected(err.to_string()));
             }
         };
 
@@ -526,7 +692,7 @@ async fn process_private_and_missing(
             Err(e) => {
                 debug!(target: "apex", "Error while processing bulk request {}", e);
 
-                return Err(HttpResponse::InternalServerError().finish());
+                return Err(ErrorKind::Unexpected(e.to_string()));
             }
         }
     }
@@ -542,22 +708,23 @@ async fn process_private_and_missing(
 
     if !req.config.disable_persistence && !unstored_and_storable.is_empty() {
         trace!(target: "apex", "Storing {} new resources", unstored_and_storable.len());
-        let pl = pool.into_inner();
-        let mut ctx = DbContext::new_with_lang(&pl, req.language.clone());
-        ctx.lookup_table = lookup_table;
+        let mut ctx = storage.open(req.language.clone());
+        *ctx.lookup_table() = lookup_table;
 
         for doc in &unstored_and_storable {
             trace!(target: "apex", "Storing {} with cache control {}", doc.iri, doc.cache_control);
             let docset = document_to_docset(doc);
-            if let Err(e) = process_message(&mut ctx, docset).await {
+            if let Err(e) = process_message(&mut *ctx, docset, &req.config).await {
                 error!(target: "apex", "Error writing resource to database: {}", e);
-                return Err(HttpResponse::InternalServerError().finish());
+                return Err(ErrorKind::Unexpected(e.to_string()));
             }
         }
 
-        update_cache_control(&ctx.get_conn(), &unstored_and_storable);
+        if let Err(e) = ctx.update_cache_control(&unstored_and_storable) {
+            error!(target: "apex", "Error updating cache control: {}", e);
+        }
 
-        lookup_table = ctx.lookup_table
+        lookup_table = ctx.into_lookup_table()
     }
     let authorize_process_end = Instant::now();
     let authorize_process_time = authorize_process_end.duration_since(authorize_process_end);
@@ -689,3 +856,74 @@ fn sort<'a>(resources: Vec<String>, bulk_docs: &mut Vec<Resource>) -> (Vec<Strin
 
     (resources_in_store, private_or_missing)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_config::HistoryRetention;
+    use crate::db::memory::MemoryStorage;
+    use crate::hashtuple::STRING_IRI;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+
+    fn public_document(storage: &dyn Storage, iri: &str, name: &str) {
+        let mut ctx = storage.open(Some(String::from("en")));
+        let table = ctx.lookup_table();
+        let statement = Statement::new(
+            table.ensure_value(iri),
+            table.ensure_value("http://schema.org/name"),
+            table.ensure_value(name),
+            table.ensure_value(STRING_IRI),
+            table.ensure_value(""),
+            table.ensure_value(""),
+        );
+        ctx.update_document(iri, &mut |_, _| vec![statement])
+            .unwrap();
+        ctx.update_cache_control(&[Document {
+            iri: String::from(iri),
+            status: 200,
+            cache_control: CacheControl::Public,
+            language: None,
+            data: vec![],
+        }])
+        .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_bulk_streams_public_documents() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new(HistoryRetention::default()));
+        public_document(&*storage, "https://example.com/a", "A");
+        public_document(&*storage, "https://example.com/b", "B");
+
+        let mut app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(storage))
+                .data(AppConfig::default())
+                .data(Reporter::default())
+                .service(bulk),
+        )
+        .await;
+        let req = TestRequest::post()
+            .uri("/link-lib/bulk")
+            .header(header::ACCEPT, "application/n-triples")
+            .set_payload(
+                "resource[]=https%3A%2F%2Fexample.com%2Fa&resource[]=https%3A%2F%2Fexample.com%2Fb",
+            )
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status().as_u16(), 200);
+
+        let body = test::read_body(res).await;
+        let mut lines: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "<https://example.com/a> <http://schema.org/name> \"A\"^^<http://www.w3.org/2001/XMLSchema#string> .",
+                "<https://example.com/a> <http://www.w3.org/2011/http#statusCode> \"200\"^^<http://www.w3.org/2001/XMLSchema#integer> .",
+                "<https://example.com/b> <http://schema.org/name> \"B\"^^<http://www.w3.org/2001/XMLSchema#string> .",
+                "<https://example.com/b> <http://www.w3.org/2011/http#statusCode> \"200\"^^<http://www.w3.org/2001/XMLSchema#integer> .",
+            ]
+        );
+    }
+}
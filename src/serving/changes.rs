@@ -0,0 +1,304 @@
+//! Change feed
+//!
+//! Clients subscribe to a set of documents and receive the deltas committed to them as
+//! Server-Sent Events. The importers publish the deltas on the configured redis channel, each
+//! server forwards them to its subscribers.
+//!
+//! Subscribers which fell behind get a `reset` event instead of the skipped deltas. The access of
+//! the session is checked again periodically, the stream ends once it may read none of the
+//! documents.
+
+use crate::app_config::AppConfig;
+use crate::errors::ErrorKind;
+use crate::importing::changes::Change;
+use crate::rdf::iri_utils::stem_iri;
+use crate::serving::bulk::{authorized_resources, session_language};
+use crate::serving::bulk_ctx::BulkCtx;
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use futures::stream;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::RecvError;
+use tokio::time::{interval, Instant, Interval};
+
+/// The amount of changes kept for subscribers which are behind, older changes are skipped.
+const FEED_CAPACITY: usize = 1024;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How often a comment is sent so proxies don't close idle subscriptions.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+/// How often the session is checked again for access to the subscribed documents.
+const REAUTHORIZE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone)]
+pub(crate) struct ChangeFeed {
+    sender: broadcast::Sender<Arc<Change>>,
+}
+
+impl ChangeFeed {
+    /// Forwards the changes published on the channel to the subscribers of the feed.
+    pub(crate) fn listen(config: &AppConfig, channel: String) -> ChangeFeed {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        let forward = sender.clone();
+        let config = config.clone();
+
+        thread::spawn(move || loop {
+            if let Err(e) = forward_changes(&config, &channel, &forward) {
+                error!(target: "apex", "Change feed disconnected: {}", e);
+            }
+            thread::sleep(RECONNECT_DELAY);
+        });
+
+        ChangeFeed { sender }
+    }
+}
+
+fn forward_changes(
+    config: &AppConfig,
+    channel: &str,
+    sender: &broadcast::Sender<Arc<Change>>,
+) -> redis::RedisResult<()> {
+    let mut consumer = config.create_redis_consumer()?;
+    let mut pubsub = consumer.as_pubsub();
+    pubsub.subscribe(channel)?;
+    debug!(target: "apex", "Listening for changes on {}", channel);
+
+    loop {
+        let payload = pubsub.get_message()?.get_payload::<Vec<u8>>()?;
+        match serde_json::from_slice::<Change>(&payload) {
+            // Sending only fails when nobody is subscribed.
+            Ok(change) => drop(sender.send(Arc::new(change))),
+            Err(e) => warn!(target: "apex", "Ignoring invalid change: {}", e),
+        }
+    }
+}
+
+/// Streams the deltas to the documents given with `?iri=` which the session may read.
+#[get("/changes")]
+pub(crate) async fn changes(
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    feed: web::Data<ChangeFeed>,
+) -> HttpResponse {
+    let resources: Vec<String> = url::form_urlencoded::parse(req.query_string().as_bytes())
+        .filter(|(key, _)| key == "iri")
+        .map(|(_, iri)| stem_iri(iri))
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect();
+    if resources.is_empty() {
+        return HttpResponse::BadRequest().body("No iri given");
+    }
+
+    let lang = session_language(&req).await;
+    let mut ctx = BulkCtx::new(req, config, lang);
+    let authorized: HashSet<String> = match authorized_resources(&mut ctx, &resources).await {
+        Ok(authorized) => authorized.into_iter().collect(),
+        Err(ErrorKind::NoTenant) => return HttpResponse::NotFound().finish(),
+        Err(ErrorKind::ParserError(msg)) => {
+            debug!(target: "apex", "Error while authorizing: {}", msg);
+            return HttpResponse::BadRequest().finish();
+        }
+        Err(ErrorKind::BackendUnavailable) => return HttpResponse::BadGateway().finish(),
+        Err(ErrorKind::Timeout) => return HttpResponse::GatewayTimeout().finish(),
+        Err(e) => {
+            error!(target: "apex", "Unexpected error while authorizing: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if authorized.is_empty() {
+        return HttpResponse::Forbidden().finish();
+    }
+    debug!(target: "apex", "Subscribed to {} of {} documents", authorized.len(), resources.len());
+
+    let subscription = Subscription {
+        receiver: feed.sender.subscribe(),
+        keep_alive: interval(KEEP_ALIVE_INTERVAL),
+        authorized_at: Instant::now(),
+        ctx,
+        resources,
+        authorized,
+    };
+    let events = stream::unfold(subscription, |mut subscription| async move {
+        let event = subscription.next().await?;
+
+        Some((Ok::<_, actix_web::Error>(event), subscription))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .streaming(Box::pin(events))
+}
+
+struct Subscription {
+    ctx: BulkCtx,
+    resources: Vec<String>,
+    authorized: HashSet<String>,
+    authorized_at: Instant,
+    receiver: broadcast::Receiver<Arc<Change>>,
+    keep_alive: Interval,
+}
+
+impl Subscription {
+    /// The next event for the client, `None` when the subscription has ended.
+    async fn next(&mut self) -> Option<Bytes> {
+        let tick = tokio::select! {
+            event = next_event(&mut self.receiver, &self.authorized) => return event,
+            tick = self.keep_alive.tick() => tick,
+        };
+
+        if tick.duration_since(self.authorized_at) >= REAUTHORIZE_INTERVAL
+            && !self.reauthorize().await
+        {
+            return None;
+        }
+
+        Some(Bytes::from_static(KEEP_ALIVE))
+    }
+
+    /// Checks again which documents the session may read, false when none are left.
+    async fn reauthorize(&mut self) -> bool {
+        self.authorized_at = Instant::now();
+
+        match authorized_resources(&mut self.ctx, &self.resources).await {
+            Ok(authorized) => {
+                self.authorized = authorized.into_iter().collect();
+                !self.authorized.is_empty()
+            }
+            Err(e) => {
+                warn!(target: "apex", "Ending change feed subscription, authorizing failed: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Waits for the next change to one of the authorized documents. Subscribers which fall behind
+/// get a `reset` event since they missed changes.
+async fn next_event(
+    receiver: &mut broadcast::Receiver<Arc<Change>>,
+    authorized: &HashSet<String>,
+) -> Option<Bytes> {
+    loop {
+        match receiver.recv().await {
+            Ok(change) if authorized.contains(&change.iri) => return Some(delta_event(&change)),
+            Ok(_) => (),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(target: "apex", "Change feed subscriber skipped {} changes", skipped);
+                return Some(reset_event(skipped));
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Formats the change as a `delta` event, its data is the hextuple delta of the document.
+fn delta_event(change: &Change) -> Bytes {
+    let mut event = String::from("event: delta\n");
+    for line in change.delta.lines() {
+        event.push_str("data: ");
+        event.push_str(line);
+        event.push('\n');
+    }
+    event.push('\n');
+
+    Bytes::from(event)
+}
+
+/// Formats a `reset` event, its data is the amount of skipped changes. Clients should fetch the
+/// documents again.
+fn reset_event(skipped: u64) -> Bytes {
+    Bytes::from(format!("event: reset\ndata: {}\n\n", skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(iri: &str, delta: &str) -> Arc<Change> {
+        Arc::new(Change {
+            iri: String::from(iri),
+            delta: String::from(delta),
+        })
+    }
+
+    #[test]
+    fn test_delta_event() {
+        let event = delta_event(&change("https://example.com/a", "[\"a\"]\n[\"b\"]\n"));
+
+        assert_eq!(
+            event,
+            Bytes::from("event: delta\ndata: [\"a\"]\ndata: [\"b\"]\n\n")
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_next_event() {
+        let (sender, mut receiver) = broadcast::channel(2);
+        let authorized: HashSet<String> = vec![String::from("https://example.com/a")]
+            .into_iter()
+            .collect();
+
+        sender
+            .send(change("https://example.com/b", "[\"b\"]\n"))
+            .unwrap();
+        sender
+            .send(change("https://example.com/a", "[\"a\"]\n"))
+            .unwrap();
+        assert_eq!(
+            next_event(&mut receiver, &authorized).await,
+            Some(Bytes::from("event: delta\ndata: [\"a\"]\n\n"))
+        );
+
+        for _ in 0..3 {
+            sender
+                .send(change("https://example.com/a", "[\"a\"]\n"))
+                .unwrap();
+        }
+        assert_eq!(
+            next_event(&mut receiver, &authorized).await,
+            Some(Bytes::from("event: reset\ndata: 1\n\n"))
+        );
+
+        drop(sender);
+        assert!(next_event(&mut receiver, &authorized).await.is_some());
+        assert!(next_event(&mut receiver, &authorized).await.is_some());
+        assert_eq!(next_event(&mut receiver, &authorized).await, None);
+    }
+
+    #[actix_rt::test]
+    async fn test_subscription_keep_alive() {
+        let (sender, receiver) = broadcast::channel(2);
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        let mut subscription = Subscription {
+            ctx: BulkCtx::new(req, web::Data::new(AppConfig::default()), None),
+            resources: vec![String::from("https://example.com/a")],
+            authorized: vec![String::from("https://example.com/a")]
+                .into_iter()
+                .collect(),
+            authorized_at: Instant::now(),
+            receiver,
+            keep_alive: interval(KEEP_ALIVE_INTERVAL),
+        };
+
+        // The first tick of the interval is immediate.
+        assert_eq!(
+            subscription.next().await,
+            Some(Bytes::from_static(KEEP_ALIVE))
+        );
+
+        sender
+            .send(change("https://example.com/a", "[\"a\"]\n"))
+            .unwrap();
+        assert_eq!(
+            subscription.next().await,
+            Some(Bytes::from("event: delta\ndata: [\"a\"]\n\n"))
+        );
+    }
+}
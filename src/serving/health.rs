@@ -1,5 +1,5 @@
 use crate::app_config::AppConfig;
-use crate::db::db_context::{DbContext, DbPool};
+use crate::db::storage::Storage;
 use crate::serving::response_type::ResponseType::JSON;
 use crate::serving::responses::set_default_headers;
 use crate::serving::ua::basic_ua;
@@ -14,11 +14,10 @@ struct Status {
 }
 
 #[get("/link-lib/d/health")]
-pub(crate) async fn health<'a>(pool: web::Data<DbPool>) -> impl Responder {
-    let ctx = DbContext::new(&pool);
+pub(crate) async fn health<'a>(storage: web::Data<dyn Storage>) -> impl Responder {
     let name = basic_ua();
 
-    let counts = ctx.est_counts();
+    let est_documents = storage.open(None).count_documents();
     let db = AppConfig::default();
     let database_name = if cfg!(debug_assertions) {
         Some(db.database_name)
@@ -29,7 +28,7 @@ pub(crate) async fn health<'a>(pool: web::Data<DbPool>) -> impl Responder {
     let status = Status {
         name,
         database_name,
-        est_documents: counts.documents,
+        est_documents,
     };
 
     set_default_headers(&mut HttpResponse::Ok(), &JSON).json(status)
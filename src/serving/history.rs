@@ -0,0 +1,91 @@
+use crate::db::storage::Storage;
+use crate::errors::ErrorKind;
+use crate::serving::response_type::ResponseType::JSON;
+use crate::serving::responses::{set_default_headers, VARY};
+use actix_web::error::BlockingError;
+use actix_web::http::header;
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub(crate) struct HistoryRequest {
+    iri: String,
+}
+
+/// Lists the recorded revisions of a document, oldest first.
+#[get("/history")]
+pub(crate) async fn history(
+    storage: web::Data<dyn Storage>,
+    query: web::Query<HistoryRequest>,
+) -> HttpResponse {
+    let storage = Arc::clone(&storage);
+    let iri = query.into_inner().iri;
+
+    let revisions = web::block(move || storage.open(None).revisions(&iri)).await;
+
+    match revisions {
+        Ok(revisions) if revisions.is_empty() => not_found(),
+        Ok(revisions) => set_default_headers(&mut HttpResponse::Ok(), &JSON).json(revisions),
+        Err(BlockingError::Error(ErrorKind::NotFound)) => not_found(),
+        Err(e) => {
+            error!(target: "apex", "Error listing revisions: {}", e);
+
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound()
+        .set_header(header::VARY, VARY)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_config::HistoryRetention;
+    use crate::db::memory::MemoryStorage;
+    use crate::hashtuple::Statement;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_history_vary() {
+        let storage = Arc::new(MemoryStorage::new(HistoryRetention {
+            max_versions: 2,
+            max_age_days: None,
+        }));
+        let mut ctx = storage.open(Some(String::from("en")));
+        let table = ctx.lookup_table();
+        let name = Statement::new(
+            table.ensure_value("https://example.com/a"),
+            table.ensure_value("http://schema.org/name"),
+            table.ensure_value("A"),
+            table.ensure_value("http://www.w3.org/2001/XMLSchema#string"),
+            table.ensure_value(""),
+            table.ensure_value(""),
+        );
+        ctx.update_document("https://example.com/a", &mut |_, _| vec![name])
+            .unwrap();
+        drop(ctx);
+
+        let storage: Arc<dyn Storage> = storage;
+        let mut app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(storage))
+                .service(history),
+        )
+        .await;
+        for (iri, status) in [("a", 200), ("b", 404)].iter() {
+            let req = test::TestRequest::get()
+                .uri(&format!("/history?iri=https%3A%2F%2Fexample.com%2F{}", iri))
+                .to_request();
+            let res = test::call_service(&mut app, req).await;
+
+            assert_eq!(res.status().as_u16(), *status);
+            let vary = res.headers().get(header::VARY).unwrap().to_str().unwrap();
+            assert!(vary.contains("Accept-Datetime"));
+        }
+    }
+}
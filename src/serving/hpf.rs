@@ -1,57 +1,166 @@
+use crate::app_config::AppConfig;
 use crate::db::db_context::{DbContext, DbPool};
-use crate::db::hpf::{HPFQuery, HPFQueryRequest, TPFQueryRequest};
+use crate::db::hpf::{pattern_header, HPFQuery, HPFQueryRequest, QPFQueryRequest, TPFQueryRequest};
+use crate::db::storage::Storage;
 use crate::errors::ErrorKind;
 use crate::hashtuple::{HashModel, LookupTable};
-use crate::serving::response_type::{ResponseType, NQUADS_MIME, NTRIPLES_MIME};
+use crate::serving::negotiation::{negotiate, negotiate_from, not_acceptable};
+use crate::serving::response_type::{ResponseType, RDF_RESPONSE_TYPES};
 use crate::serving::responses::set_default_headers;
-use crate::serving::serialization::{
-    bulk_result_to_hextuples, bulk_result_to_nquads, bulk_result_to_ntriples,
-};
+use crate::serving::serialization::{is_streamable, serialize_bulk};
+use crate::serving::streaming::{error_response, stream_response};
 use actix_web::error::BlockingError;
-use actix_web::http::{header, HeaderMap, HeaderValue};
+use actix_web::http::{HeaderMap, HeaderValue};
 use actix_web::{get, web, HttpResponse, Responder};
 use humantime::format_duration;
 use std::env;
+use std::sync::Arc;
 use std::time::Instant;
 
 #[get("/hpf")]
 pub(crate) async fn hpf(
     req: actix_web::HttpRequest,
     pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
     payload: web::Query<HPFQueryRequest>,
 ) -> impl Responder {
+    let response_type = match negotiate(req.headers()) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
+    };
     let origin = origin_or_default(req.headers());
-    let pl = pool.into_inner();
-
-    let res = web::block(move || -> Result<(HashModel, LookupTable), ErrorKind> {
-        let mut ctx = DbContext::new(&pl);
-        let query = HPFQuery::parse(&mut ctx, &payload)?;
 
-        fetch(ctx, &origin, query)
-    })
-    .await;
-
-    respond(req, res)
+    fragment(
+        response_type,
+        pool.into_inner(),
+        config,
+        origin,
+        move |ctx| HPFQuery::parse(ctx, &payload),
+    )
+    .await
 }
 
 #[get("/tpf")]
 pub(crate) async fn tpf(
     req: actix_web::HttpRequest,
     pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
     payload: web::Query<TPFQueryRequest>,
 ) -> impl Responder {
+    let response_type = match negotiate(req.headers()) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
+    };
     let origin = origin_or_default(req.headers());
-    let pl = pool.into_inner();
+
+    fragment(
+        response_type,
+        pool.into_inner(),
+        config,
+        origin,
+        move |ctx| HPFQuery::parse_tpf(ctx, &payload),
+    )
+    .await
+}
+
+/// Triple pattern fragments for backends without SQL access.
+#[get("/tpf")]
+pub(crate) async fn tpf_storage(
+    req: actix_web::HttpRequest,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<AppConfig>,
+    payload: web::Query<TPFQueryRequest>,
+) -> impl Responder {
+    let response_type = match negotiate(req.headers()) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
+    };
+    let origin = origin_or_default(req.headers());
+    let storage = Arc::clone(&storage);
 
     let res = web::block(move || -> Result<(HashModel, LookupTable), ErrorKind> {
-        let mut ctx = DbContext::new(&pl);
-        let query = HPFQuery::parse_tpf(&mut ctx, &payload)?;
+        let mut ctx = storage.open(None);
+        let pattern = payload.pattern();
+        let page = ctx.match_pattern(&pattern)?;
+        let header = pattern_header(ctx.lookup_table(), &origin, &pattern, &page);
+
+        let doc = [header.as_slice(), page.statements.as_slice()].concat();
 
-        fetch(ctx, &origin, query)
+        Ok((doc, ctx.into_lookup_table()))
     })
     .await;
 
-    respond(req, res)
+    respond(response_type, &config, res)
+}
+
+/// Quad pattern fragments, where the graph of a statement is the document it belongs to.
+#[get("/qpf")]
+pub(crate) async fn qpf(
+    req: actix_web::HttpRequest,
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    payload: web::Query<QPFQueryRequest>,
+) -> impl Responder {
+    // Prefer a format which can express the graphs.
+    let available = [
+        &[ResponseType::NQUADS, ResponseType::HEXTUPLE][..],
+        &RDF_RESPONSE_TYPES[..],
+    ]
+    .concat();
+    let response_type = match negotiate_from(req.headers(), &available) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
+    };
+    let origin = origin_or_default(req.headers());
+
+    fragment(
+        response_type,
+        pool.into_inner(),
+        config,
+        origin,
+        move |ctx| HPFQuery::parse_qpf(ctx, &payload),
+    )
+    .await
+}
+
+/// Responds with the fragment of the query built by `parse`, streaming line based formats.
+async fn fragment<F>(
+    response_type: ResponseType,
+    pl: Arc<DbPool>,
+    config: web::Data<AppConfig>,
+    origin: String,
+    parse: F,
+) -> HttpResponse
+where
+    F: FnOnce(&mut DbContext) -> Result<HPFQuery, ErrorKind> + Send + 'static,
+{
+    if !is_streamable(&response_type) {
+        let res = web::block(move || -> Result<(HashModel, LookupTable), ErrorKind> {
+            let mut ctx = DbContext::new(&pl);
+            let query = parse(&mut ctx)?;
+
+            fetch(ctx, &origin, query)
+        })
+        .await;
+
+        return respond(response_type, &config, res);
+    }
+
+    stream_response(response_type, move |chunks| {
+        let mut ctx = DbContext::new(&pl);
+        let query = parse(&mut ctx)?;
+        chunks.start();
+
+        let fetch_start = Instant::now();
+        let (count, next) =
+            query.execute_batched(&mut ctx, |batch, ctx| chunks.send(batch, &ctx.lookup_table))?;
+        let fetch_time = Instant::now().duration_since(fetch_start);
+        debug!(target: "apex", "Fetching and streaming cost: {}", format_duration(fetch_time));
+
+        let header = query.header(&mut ctx, &origin, count, next)?;
+        chunks.send(header, &ctx.lookup_table)
+    })
+    .await
 }
 
 fn fetch(
@@ -60,10 +169,10 @@ fn fetch(
     query: HPFQuery,
 ) -> Result<(HashModel, LookupTable), ErrorKind> {
     let fetch_start = Instant::now();
-    let models = query.execute(&mut ctx)?;
+    let (models, next) = query.execute(&mut ctx)?;
     let fetch_time = Instant::now().duration_since(fetch_start);
     debug!(target: "apex", "Fetching cost: {}", format_duration(fetch_time));
-    let header = query.header(&mut ctx, &origin)?;
+    let header = query.header(&mut ctx, origin, models.len(), next)?;
 
     let doc = [header.as_slice(), models.as_slice()].concat();
 
@@ -71,38 +180,26 @@ fn fetch(
 }
 
 fn respond(
-    req: actix_web::HttpRequest,
+    response_type: ResponseType,
+    config: &AppConfig,
     res: Result<(HashModel, LookupTable), BlockingError<ErrorKind>>,
-) -> impl Responder {
-    if res.is_err() {
-        let err = res.err().unwrap();
-        println!("Caught error: {:?}", err);
-        return HttpResponse::InternalServerError().finish();
-    }
-
-    let (model, table) = res.unwrap();
+) -> HttpResponse {
+    let (model, table) = match res {
+        Ok(res) => res,
+        Err(BlockingError::Error(e)) => return error_response(e),
+        Err(BlockingError::Canceled) => return HttpResponse::InternalServerError().finish(),
+    };
     let bulk_arg = (vec![Some(model)], table);
 
     let convert_start = Instant::now();
-    let (body, response_type) = if let Some(accept) = req.headers().get(header::ACCEPT) {
-        let accept = accept.to_str().unwrap();
-        if accept == NQUADS_MIME {
-            (bulk_result_to_nquads(bulk_arg), ResponseType::NQUADS)
-        } else if accept == NTRIPLES_MIME {
-            (bulk_result_to_ntriples(bulk_arg), ResponseType::NTRIPLES)
-        } else {
-            (bulk_result_to_hextuples(bulk_arg), ResponseType::HEXTUPLE)
-        }
-    } else {
-        (bulk_result_to_hextuples(bulk_arg), ResponseType::HEXTUPLE)
-    };
+    let body = serialize_bulk(&response_type, bulk_arg, &config.turtle_prefixes).unwrap();
     let convert_time = Instant::now().duration_since(convert_start);
     debug!(target: "apex", "Converting cost: {}", format_duration(convert_time));
 
     set_default_headers(&mut HttpResponse::Ok(), &response_type).body(body)
 }
 
-fn origin_or_default(headers: &HeaderMap) -> String {
+pub(crate) fn origin_or_default(headers: &HeaderMap) -> String {
     let default_host = env::var("HOSTNAME").expect("No default hostname given");
     let default_origin = format!("https://{}", default_host);
     let default_as_header = HeaderValue::from_str(&default_origin).unwrap();
@@ -1,20 +1,28 @@
 mod assets;
+mod bgp;
 mod bulk;
 mod bulk_ctx;
+mod changes;
 mod health;
+mod history;
 mod hpf;
 mod metrics;
+mod negotiation;
 pub(crate) mod reporter;
 mod request_headers;
 mod response_type;
 mod responses;
 mod route;
+mod search;
 pub(crate) mod serialization;
 mod server;
-mod service_info;
+pub(crate) mod service_info;
 pub(crate) mod sessions;
 mod show_resource;
+mod sparql;
+mod streaming;
 pub(crate) mod timings;
+mod turtle;
 pub(crate) mod ua;
 mod update;
 
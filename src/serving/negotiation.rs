@@ -0,0 +1,282 @@
+use crate::serving::response_type::{ResponseType, RDF_RESPONSE_TYPES};
+use actix_web::http::{header, HeaderMap};
+use actix_web::HttpResponse;
+use std::cmp::Reverse;
+
+/// Quality values are kept in thousandths to avoid comparing floats.
+const MAX_QUALITY: u16 = 1000;
+/// The namespace of the JSON-LD document forms a client can ask for with the `profile` parameter.
+const JSONLD_FORMS: &str = "http://www.w3.org/ns/json-ld#";
+const JSONLD_EXPANDED: &str = "http://www.w3.org/ns/json-ld#expanded";
+
+#[derive(Debug, PartialEq)]
+struct MediaRange {
+    main: String,
+    sub: String,
+    params: Vec<(String, String)>,
+    quality: u16,
+}
+
+impl MediaRange {
+    fn parse(range: &str) -> Option<MediaRange> {
+        let mut parts = split_unquoted(range, ';').into_iter();
+        let mut essence = parts.next()?.splitn(2, '/');
+        let main = essence.next()?.trim().to_ascii_lowercase();
+        let sub = essence.next()?.trim().to_ascii_lowercase();
+
+        if main.is_empty() || sub.is_empty() || (main == "*" && sub != "*") {
+            return None;
+        }
+
+        let mut params = vec![];
+        let mut quality = MAX_QUALITY;
+        for param in parts {
+            let mut kv = param.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim().to_ascii_lowercase();
+            let value = kv.next().unwrap_or("").trim().trim_matches('"').to_string();
+
+            if key == "q" {
+                // Parameters after the quality value are accept-extensions.
+                quality = parse_quality(&value)?;
+                break;
+            } else if !key.is_empty() {
+                params.push((key, value));
+            }
+        }
+
+        Some(MediaRange {
+            main,
+            sub,
+            params,
+            quality,
+        })
+    }
+
+    /// Whether the JSON-LD forms asked for with `profile` are served, only expanded JSON-LD is.
+    fn form_matches(&self, mime: &MediaRange) -> bool {
+        if mime.main != "application" || mime.sub != "ld+json" {
+            return true;
+        }
+
+        self.params
+            .iter()
+            .filter(|(key, _)| key == "profile")
+            .flat_map(|(_, value)| value.split_whitespace())
+            .filter(|profile| profile.starts_with(JSONLD_FORMS))
+            .all(|profile| profile == JSONLD_EXPANDED)
+    }
+
+    /// How specific this range matches `mime`, or `None` if it doesn't match.
+    fn specificity(&self, mime: &MediaRange) -> Option<u8> {
+        let charset_matches = self
+            .params
+            .iter()
+            .filter(|(key, _)| key == "charset")
+            .all(|(_, value)| value.eq_ignore_ascii_case("utf-8"));
+
+        if !charset_matches || !self.form_matches(mime) {
+            None
+        } else if self.main == "*" {
+            Some(0)
+        } else if self.main != mime.main {
+            None
+        } else if self.sub == "*" {
+            Some(1)
+        } else if self.sub == mime.sub {
+            Some(2)
+        } else {
+            None
+        }
+    }
+}
+
+/// Picks the best RDF response type the client accepts, `None` if none are acceptable.
+pub(crate) fn negotiate(headers: &HeaderMap) -> Option<ResponseType> {
+    negotiate_from(headers, &RDF_RESPONSE_TYPES)
+}
+
+/// Picks the best of `available` the client accepts, in order of preference when tied.
+pub(crate) fn negotiate_from(
+    headers: &HeaderMap,
+    available: &[ResponseType],
+) -> Option<ResponseType> {
+    let accept = headers
+        .get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<&str>>()
+        .join(",");
+
+    best_match(&accept, available)
+}
+
+/// The response for when none of the offered response types is acceptable.
+pub(crate) fn not_acceptable() -> HttpResponse {
+    HttpResponse::NotAcceptable()
+        .set_header(header::VARY, "Accept")
+        .finish()
+}
+
+fn best_match(accept: &str, available: &[ResponseType]) -> Option<ResponseType> {
+    if accept.trim().is_empty() {
+        return available.first().cloned();
+    }
+
+    let ranges: Vec<MediaRange> = split_unquoted(accept, ',')
+        .into_iter()
+        .filter_map(MediaRange::parse)
+        .collect();
+
+    available
+        .iter()
+        .enumerate()
+        .filter_map(|(preference, response_type)| {
+            let mime = MediaRange::parse(&response_type.to_mime())?;
+            let (position, specificity, quality) = ranges
+                .iter()
+                .enumerate()
+                .filter_map(|(position, range)| {
+                    range
+                        .specificity(&mime)
+                        .map(|specificity| (position, specificity, range.quality))
+                })
+                .max_by_key(|(position, specificity, _)| (*specificity, Reverse(*position)))?;
+
+            if quality == 0 {
+                return None;
+            }
+
+            Some((
+                (quality, specificity, Reverse(position), Reverse(preference)),
+                response_type,
+            ))
+        })
+        .max_by_key(|(rank, _)| *rank)
+        .map(|(_, response_type)| response_type.clone())
+}
+
+fn parse_quality(value: &str) -> Option<u16> {
+    let mut parts = value.splitn(2, '.');
+    let whole = parts.next()?;
+    let fraction = parts.next().unwrap_or("");
+
+    if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let quality = match whole {
+        "0" => format!("{:0<3}", fraction).parse::<u16>().ok()?,
+        "1" if fraction.chars().all(|c| c == '0') => MAX_QUALITY,
+        _ => return None,
+    };
+
+    Some(quality)
+}
+
+/// Splits `value` on `separator`, ignoring separators within quoted strings.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quoted {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best_match_with_quality_values() {
+        let accept = "text/turtle;q=0.9, application/n-triples";
+        assert_eq!(
+            best_match(accept, &RDF_RESPONSE_TYPES),
+            Some(ResponseType::NTRIPLES)
+        );
+
+        let accept = "application/n-triples;q=0.5, text/*;q=0.8, */*;q=0";
+        assert_eq!(
+            best_match(accept, &RDF_RESPONSE_TYPES),
+            Some(ResponseType::TURTLE)
+        );
+
+        let accept = "text/turtle;q=0, text/*";
+        assert_eq!(
+            best_match(accept, &RDF_RESPONSE_TYPES),
+            Some(ResponseType::N3)
+        );
+
+        assert_eq!(best_match("application/json", &RDF_RESPONSE_TYPES), None);
+    }
+
+    #[test]
+    fn test_best_match_with_wildcards_and_params() {
+        assert_eq!(
+            best_match("", &RDF_RESPONSE_TYPES),
+            Some(ResponseType::HEXTUPLE)
+        );
+        assert_eq!(
+            best_match("*/*", &RDF_RESPONSE_TYPES),
+            Some(ResponseType::HEXTUPLE)
+        );
+        assert_eq!(
+            best_match("*/*;q=0.1, application/rdf+xml", &RDF_RESPONSE_TYPES),
+            Some(ResponseType::RDFXML)
+        );
+        assert_eq!(
+            best_match(
+                "application/hex+x-ndjson; charset=utf-8",
+                &RDF_RESPONSE_TYPES
+            ),
+            Some(ResponseType::HEXTUPLE)
+        );
+        assert_eq!(
+            best_match(
+                "application/ld+json;profile=\"a,b\";q=0.7, text/turtle;charset=latin1",
+                &RDF_RESPONSE_TYPES
+            ),
+            Some(ResponseType::JSONLD)
+        );
+    }
+
+    #[test]
+    fn test_best_match_with_jsonld_profiles() {
+        let compacted = "application/ld+json;profile=\"http://www.w3.org/ns/json-ld#compacted\"";
+        assert_eq!(best_match(compacted, &RDF_RESPONSE_TYPES), None);
+        assert_eq!(
+            best_match(
+                &format!("{}, text/turtle;q=0.5", compacted),
+                &RDF_RESPONSE_TYPES
+            ),
+            Some(ResponseType::TURTLE)
+        );
+        assert_eq!(
+            best_match(
+                "application/ld+json;profile=\"http://www.w3.org/ns/json-ld#flattened http://www.w3.org/ns/json-ld#compacted\"",
+                &RDF_RESPONSE_TYPES
+            ),
+            None
+        );
+        assert_eq!(
+            best_match(
+                "application/ld+json;profile=\"http://www.w3.org/ns/json-ld#expanded\"",
+                &RDF_RESPONSE_TYPES
+            ),
+            Some(ResponseType::JSONLD)
+        );
+    }
+}
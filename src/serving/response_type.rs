@@ -16,15 +16,53 @@ pub(crate) const TURTLE_EXT: &str = "ttl";
 pub(crate) const JSONLD_MIME: &str = "application/ld+json";
 pub(crate) const JSONLD_EXT: &str = "jsonld";
 
+pub(crate) const RDFJSON_MIME: &str = "application/rdf+json";
+pub(crate) const RDFJSON_EXT: &str = "rj";
+
+pub(crate) const RDFXML_MIME: &str = "application/rdf+xml";
+pub(crate) const RDFXML_EXT: &str = "rdf";
+
+pub(crate) const N3_MIME: &str = "text/n3";
+pub(crate) const N3_EXT: &str = "n3";
+
+pub(crate) const TRIG_MIME: &str = "application/trig";
+pub(crate) const TRIG_EXT: &str = "trig";
+
+pub(crate) const SPARQLJSON_MIME: &str = "application/sparql-results+json";
+pub(crate) const SPARQLJSON_EXT: &str = "srj";
+
+pub(crate) const SPARQLXML_MIME: &str = "application/sparql-results+xml";
+pub(crate) const SPARQLXML_EXT: &str = "srx";
+
 pub(crate) const JSON_MIME: &str = "application/json";
 pub(crate) const JSON_EXT: &str = "json";
 
+/// The response types which have an RDF serializer.
+pub(crate) const RDF_RESPONSE_TYPES: [ResponseType; 9] = [
+    ResponseType::HEXTUPLE,
+    ResponseType::TURTLE,
+    ResponseType::NQUADS,
+    ResponseType::NTRIPLES,
+    ResponseType::JSONLD,
+    ResponseType::RDFJSON,
+    ResponseType::RDFXML,
+    ResponseType::N3,
+    ResponseType::TRIG,
+];
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum ResponseType {
     HEXTUPLE,
     TURTLE,
     NQUADS,
     NTRIPLES,
     JSONLD,
+    RDFJSON,
+    RDFXML,
+    N3,
+    TRIG,
+    SPARQLJSON,
+    SPARQLXML,
     JSON,
 }
 
@@ -36,6 +74,12 @@ impl ResponseType {
             NTRIPLES_EXT => Ok(ResponseType::NTRIPLES),
             TURTLE_EXT => Ok(ResponseType::TURTLE),
             JSONLD_EXT => Ok(ResponseType::JSONLD),
+            RDFJSON_EXT => Ok(ResponseType::RDFJSON),
+            RDFXML_EXT => Ok(ResponseType::RDFXML),
+            N3_EXT => Ok(ResponseType::N3),
+            TRIG_EXT => Ok(ResponseType::TRIG),
+            SPARQLJSON_EXT => Ok(ResponseType::SPARQLJSON),
+            SPARQLXML_EXT => Ok(ResponseType::SPARQLXML),
             JSON_EXT => Ok(ResponseType::JSON),
             _ => Err(()),
         }
@@ -48,6 +92,12 @@ impl ResponseType {
             ResponseType::NTRIPLES => String::from(NTRIPLES_EXT),
             ResponseType::TURTLE => String::from(TURTLE_EXT),
             ResponseType::JSONLD => String::from(JSONLD_EXT),
+            ResponseType::RDFJSON => String::from(RDFJSON_EXT),
+            ResponseType::RDFXML => String::from(RDFXML_EXT),
+            ResponseType::N3 => String::from(N3_EXT),
+            ResponseType::TRIG => String::from(TRIG_EXT),
+            ResponseType::SPARQLJSON => String::from(SPARQLJSON_EXT),
+            ResponseType::SPARQLXML => String::from(SPARQLXML_EXT),
             ResponseType::JSON => String::from(JSON_EXT),
         }
     }
@@ -56,10 +106,16 @@ impl ResponseType {
         match mime {
             HEXTUPLE_MIME => Ok(ResponseType::HEXTUPLE),
             HEXTUPLE_MIME_BASE => Ok(ResponseType::HEXTUPLE),
-            NQUADS_MIME => Ok(ResponseType::NTRIPLES),
-            NTRIPLES_MIME => Ok(ResponseType::NQUADS),
+            NQUADS_MIME => Ok(ResponseType::NQUADS),
+            NTRIPLES_MIME => Ok(ResponseType::NTRIPLES),
             TURTLE_MIME => Ok(ResponseType::TURTLE),
             JSONLD_MIME => Ok(ResponseType::JSONLD),
+            RDFJSON_MIME => Ok(ResponseType::RDFJSON),
+            RDFXML_MIME => Ok(ResponseType::RDFXML),
+            N3_MIME => Ok(ResponseType::N3),
+            TRIG_MIME => Ok(ResponseType::TRIG),
+            SPARQLJSON_MIME => Ok(ResponseType::SPARQLJSON),
+            SPARQLXML_MIME => Ok(ResponseType::SPARQLXML),
             JSON_MIME => Ok(ResponseType::JSON),
             _ => Err(()),
         }
@@ -72,6 +128,12 @@ impl ResponseType {
             ResponseType::NTRIPLES => String::from(NTRIPLES_MIME),
             ResponseType::TURTLE => String::from(TURTLE_MIME),
             ResponseType::JSONLD => String::from(JSONLD_MIME),
+            ResponseType::RDFJSON => String::from(RDFJSON_MIME),
+            ResponseType::RDFXML => String::from(RDFXML_MIME),
+            ResponseType::N3 => String::from(N3_MIME),
+            ResponseType::TRIG => String::from(TRIG_MIME),
+            ResponseType::SPARQLJSON => String::from(SPARQLJSON_MIME),
+            ResponseType::SPARQLXML => String::from(SPARQLXML_MIME),
             ResponseType::JSON => String::from(JSON_MIME),
         }
     }
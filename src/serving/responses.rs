@@ -3,6 +3,11 @@ use crate::serving::ua::basic_ua;
 use actix_web::dev::HttpResponseBuilder;
 use actix_web::http::header;
 
+/// The request headers the responses depend on, also set on the responses without a body since
+/// those differ between the current and the previous states of a document.
+pub(crate) const VARY: &str =
+    "Accept, Accept-Datetime, Accept-Encoding, Authorization, Content-Type, Origin";
+
 pub(crate) fn set_default_headers<'a>(
     res: &'a mut HttpResponseBuilder,
     response_type: &'a ResponseType,
@@ -19,8 +24,5 @@ pub(crate) fn set_default_headers_str<'a>(
         .set_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
         .set_header(header::ACCESS_CONTROL_ALLOW_METHODS, "POST, GET, OPTIONS")
         .set_header(header::ACCESS_CONTROL_MAX_AGE, 86400u32.to_string())
-        .set_header(
-            header::VARY,
-            "Accept, Accept-Encoding, Authorization, Content-Type, Origin",
-        )
+        .set_header(header::VARY, VARY)
 }
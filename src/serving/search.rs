@@ -0,0 +1,67 @@
+use crate::app_config::AppConfig;
+use crate::db::db_context::{DbContext, DbPool};
+use crate::db::search::{SearchQuery, SearchRequest};
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, LookupTable};
+use crate::serving::hpf::origin_or_default;
+use crate::serving::negotiation::{negotiate, not_acceptable};
+use crate::serving::responses::set_default_headers;
+use crate::serving::serialization::serialize_bulk;
+use actix_web::error::BlockingError;
+use actix_web::{get, web, HttpResponse, Responder};
+use humantime::format_duration;
+use std::time::Instant;
+
+/// Searches the literals of all stored documents, responding with the ranked matching subjects.
+#[get("/search")]
+pub(crate) async fn search(
+    req: actix_web::HttpRequest,
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    payload: web::Query<SearchRequest>,
+) -> impl Responder {
+    let response_type = match negotiate(req.headers()) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
+    };
+    let query = match SearchQuery::parse(&payload) {
+        Ok(query) => query,
+        Err(ErrorKind::ParserError(msg)) => return HttpResponse::BadRequest().body(msg),
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+    let origin = origin_or_default(req.headers());
+    let pl = pool.into_inner();
+
+    let res = web::block(move || -> Result<(HashModel, LookupTable), ErrorKind> {
+        let mut ctx = DbContext::new(&pl);
+
+        let fetch_start = Instant::now();
+        let model = query.execute(&mut ctx, &origin)?;
+        let fetch_time = Instant::now().duration_since(fetch_start);
+        debug!(target: "apex", "Search cost: {}", format_duration(fetch_time));
+
+        Ok((model, ctx.lookup_table))
+    })
+    .await;
+
+    let (model, table) = match res {
+        Ok(res) => res,
+        Err(err) => {
+            error!(target: "apex", "Caught error: {:?}", err);
+            return match err {
+                BlockingError::Error(ErrorKind::ParserError(msg)) => {
+                    HttpResponse::BadRequest().body(msg)
+                }
+                _ => HttpResponse::InternalServerError().finish(),
+            };
+        }
+    };
+    let body = serialize_bulk(
+        &response_type,
+        (vec![Some(model)], table),
+        &config.turtle_prefixes,
+    )
+    .unwrap();
+
+    set_default_headers(&mut HttpResponse::Ok(), &response_type).body(body)
+}
@@ -1,16 +1,95 @@
+use crate::db::hpf::Position;
 use crate::hashtuple::{
-    HashModel, LookupTable, Statement, BLANK_NODE_IRI, LANG_STRING_IRI, NAMED_NODE_IRI,
+    HashModel, LookupTable, Statement, BLANK_NODE_IRI, LANG_STRING_IRI, NAMED_NODE_IRI, STRING_IRI,
 };
+use crate::serving::response_type::ResponseType;
+use crate::serving::turtle::statements_to_turtle;
 use rio_api::formatter::{QuadsFormatter, TriplesFormatter};
 use rio_api::model::{BlankNode, Literal, NamedNode, NamedOrBlankNode, Quad, Term, Triple};
-use rio_turtle::{NQuadsFormatter, NTriplesFormatter, TurtleFormatter};
+use rio_turtle::{NQuadsFormatter, NTriplesFormatter, TriGFormatter};
+use rio_xml::RdfXmlFormatter;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
 
 pub(crate) type Hextuple<'a> = [&'a String; 6];
 pub(crate) type HexModel<'a> = Vec<Hextuple<'a>>;
 pub(crate) type BulkInput = (Vec<Option<HashModel>>, LookupTable);
 
+/// The variable bindings of a solution, in the order of the variables. Variables are bound to
+/// the term at a position of a statement.
+pub(crate) type Binding = Vec<Option<(Statement, Position)>>;
+
 pub(crate) const ND_DELIMITER: u8 = b'\n';
 
+const RDF_TYPE_IRI: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+/// Serializes a single model, returns `None` if there is no RDF serializer for `response_type`.
+///
+/// Turtle and N3 compact IRIs with `prefixes`.
+pub(crate) fn serialize_model(
+    response_type: &ResponseType,
+    model: (HashModel, &LookupTable),
+    prefixes: &BTreeMap<String, String>,
+) -> Option<Vec<u8>> {
+    let serialization = match response_type {
+        ResponseType::HEXTUPLE => hash_model_to_hextuples(model),
+        ResponseType::NTRIPLES => hash_model_to_ntriples(model),
+        ResponseType::NQUADS => hash_model_to_nquads(model),
+        ResponseType::TURTLE => hash_model_to_turtle(model, prefixes),
+        ResponseType::N3 => hash_model_to_n3(model, prefixes),
+        ResponseType::JSONLD => hash_model_to_jsonld(model),
+        ResponseType::RDFJSON => hash_model_to_rdfjson(model),
+        ResponseType::RDFXML => hash_model_to_rdfxml(model),
+        ResponseType::TRIG => hash_model_to_trig(model),
+        ResponseType::SPARQLJSON | ResponseType::SPARQLXML | ResponseType::JSON => return None,
+    };
+
+    Some(serialization)
+}
+
+/// Serializes a set of documents, like `serialize_model`.
+pub(crate) fn serialize_bulk(
+    response_type: &ResponseType,
+    input: BulkInput,
+    prefixes: &BTreeMap<String, String>,
+) -> Option<Vec<u8>> {
+    let serialization = match response_type {
+        ResponseType::HEXTUPLE => bulk_result_to_hextuples(input),
+        ResponseType::NTRIPLES => bulk_result_to_ntriples(input),
+        ResponseType::NQUADS => bulk_result_to_nquads(input),
+        ResponseType::TURTLE => bulk_result_to_turtle(input, prefixes),
+        ResponseType::N3 => bulk_result_to_n3(input, prefixes),
+        ResponseType::JSONLD => bulk_result_to_jsonld(input),
+        ResponseType::RDFJSON => bulk_result_to_rdfjson(input),
+        ResponseType::RDFXML => bulk_result_to_rdfxml(input),
+        ResponseType::TRIG => bulk_result_to_trig(input),
+        ResponseType::SPARQLJSON | ResponseType::SPARQLXML | ResponseType::JSON => return None,
+    };
+
+    Some(serialization)
+}
+
+/// Whether the format is line based, so separately serialized models can be concatenated.
+pub(crate) fn is_streamable(response_type: &ResponseType) -> bool {
+    matches!(
+        response_type,
+        ResponseType::HEXTUPLE | ResponseType::NTRIPLES | ResponseType::NQUADS
+    )
+}
+
+/// Serializes a single model in a line based format, returns `None` for the other formats.
+pub(crate) fn serialize_lines(
+    response_type: &ResponseType,
+    model: (HashModel, &LookupTable),
+) -> Option<Vec<u8>> {
+    match response_type {
+        ResponseType::HEXTUPLE => Some(hash_model_to_hextuples(model)),
+        ResponseType::NTRIPLES => Some(hash_model_to_ntriples(model)),
+        ResponseType::NQUADS => Some(hash_model_to_nquads(model)),
+        _ => None,
+    }
+}
+
 pub(crate) fn hash_model_to_hextuples(model: (HashModel, &LookupTable)) -> Vec<u8> {
     let (doc, filled_table) = model;
 
@@ -37,14 +116,72 @@ pub(crate) fn hash_model_to_ntriples(model: (HashModel, &LookupTable)) -> Vec<u8
     formatter.finish()
 }
 
-pub(crate) fn hash_model_to_turtle(model: (HashModel, &LookupTable)) -> Vec<u8> {
-    let mut formatter = TurtleFormatter::new(Vec::default());
+pub(crate) fn hash_model_to_nquads((doc, filled_table): (HashModel, &LookupTable)) -> Vec<u8> {
+    let mut formatter = NQuadsFormatter::new(Vec::default());
+
+    hash_to_rio(doc, filled_table).iter().for_each(|term| {
+        formatter.format(term).unwrap();
+    });
+
+    formatter.finish()
+}
+
+pub(crate) fn hash_model_to_trig((doc, filled_table): (HashModel, &LookupTable)) -> Vec<u8> {
+    let mut formatter = TriGFormatter::new(Vec::default());
+
+    hash_to_rio(doc, filled_table).iter().for_each(|term| {
+        formatter.format(term).unwrap();
+    });
+
+    formatter.finish().unwrap()
+}
+
+pub(crate) fn hash_model_to_turtle(
+    (doc, filled_table): (HashModel, &LookupTable),
+    prefixes: &BTreeMap<String, String>,
+) -> Vec<u8> {
+    statements_to_turtle(&doc, filled_table, prefixes)
+}
+
+/// Turtle is a subset of N3, so N3 is written as Turtle.
+pub(crate) fn hash_model_to_n3(
+    model: (HashModel, &LookupTable),
+    prefixes: &BTreeMap<String, String>,
+) -> Vec<u8> {
+    hash_model_to_turtle(model, prefixes)
+}
+
+/// Serializes the model as RDF/JSON, blank nodes are prefixed with `_:`.
+pub(crate) fn hash_model_to_rdfjson(model: (HashModel, &LookupTable)) -> Vec<u8> {
+    let (doc, filled_table) = model;
+    let mut subjects = Map::new();
+
+    extend_rdfjson(&mut subjects, doc, filled_table);
+
+    serde_json::to_vec(&Value::Object(subjects)).unwrap()
+}
+
+pub(crate) fn hash_model_to_rdfxml(model: (HashModel, &LookupTable)) -> Vec<u8> {
+    let mut formatter = RdfXmlFormatter::new(Vec::default()).unwrap();
 
     format_model(&mut formatter, model);
 
     formatter.finish().unwrap()
 }
 
+/// Serializes the model as expanded JSON-LD.
+///
+/// Statements in a non-default graph are nested in a named graph object. Other forms aren't
+/// served, negotiation rejects a `profile` asking for compacted, flattened or framed JSON-LD.
+pub(crate) fn hash_model_to_jsonld(model: (HashModel, &LookupTable)) -> Vec<u8> {
+    let (doc, filled_table) = model;
+    let mut graphs = JsonLdGraphs::default();
+
+    graphs.extend(doc, filled_table);
+
+    graphs.finish()
+}
+
 pub(crate) fn bulk_result_to_hextuples((docs, filled_table): BulkInput) -> Vec<u8> {
     let mut output = Vec::new();
 
@@ -96,6 +233,176 @@ pub(crate) fn bulk_result_to_nquads((docs, filled_table): BulkInput) -> Vec<u8>
     formatter.finish()
 }
 
+pub(crate) fn bulk_result_to_trig((docs, filled_table): BulkInput) -> Vec<u8> {
+    let mut formatter = TriGFormatter::new(Vec::default());
+
+    for doc in docs.into_iter().flatten() {
+        hash_to_rio(doc, &filled_table).iter().for_each(|term| {
+            formatter.format(term).unwrap();
+        });
+    }
+
+    formatter.finish().unwrap()
+}
+
+/// Places the statements of every document in a named graph equal to the document IRI, replacing
+/// the delta operators.
+pub(crate) fn to_document_graphs(
+    (docs, mut filled_table): BulkInput,
+    iris: &[String],
+) -> BulkInput {
+    let docs = docs
+        .into_iter()
+        .zip(iris)
+        .map(|(doc, iri)| doc.map(|doc| to_document_graph(&mut filled_table, doc, iri)))
+        .collect();
+
+    (docs, filled_table)
+}
+
+/// Places the statements of the document in a named graph equal to its IRI, see
+/// `to_document_graphs`.
+pub(crate) fn to_document_graph(
+    filled_table: &mut LookupTable,
+    doc: HashModel,
+    iri: &str,
+) -> HashModel {
+    let graph = filled_table.ensure_value(iri);
+
+    doc.into_iter()
+        .map(|statement| Statement { graph, ..statement })
+        .collect()
+}
+
+pub(crate) fn bulk_result_to_turtle(
+    (docs, filled_table): BulkInput,
+    prefixes: &BTreeMap<String, String>,
+) -> Vec<u8> {
+    let statements: HashModel = docs.into_iter().flatten().flatten().collect();
+
+    statements_to_turtle(&statements, &filled_table, prefixes)
+}
+
+/// Turtle is a subset of N3, so N3 is written as Turtle.
+pub(crate) fn bulk_result_to_n3(input: BulkInput, prefixes: &BTreeMap<String, String>) -> Vec<u8> {
+    bulk_result_to_turtle(input, prefixes)
+}
+
+pub(crate) fn bulk_result_to_rdfjson((docs, filled_table): BulkInput) -> Vec<u8> {
+    let mut subjects = Map::new();
+
+    for doc in docs.into_iter().flatten() {
+        extend_rdfjson(&mut subjects, doc, &filled_table);
+    }
+
+    serde_json::to_vec(&Value::Object(subjects)).unwrap()
+}
+
+pub(crate) fn bulk_result_to_rdfxml((docs, filled_table): BulkInput) -> Vec<u8> {
+    let mut formatter = RdfXmlFormatter::new(Vec::default()).unwrap();
+
+    for doc in docs.into_iter().flatten() {
+        format_model(&mut formatter, (doc, &filled_table));
+    }
+
+    formatter.finish().unwrap()
+}
+
+pub(crate) fn bulk_result_to_jsonld((docs, filled_table): BulkInput) -> Vec<u8> {
+    let mut graphs = JsonLdGraphs::default();
+
+    for doc in docs {
+        match doc {
+            None => (),
+            Some(doc) => graphs.extend(doc, &filled_table),
+        }
+    }
+
+    graphs.finish()
+}
+
+/// Serializes variable bindings as SPARQL JSON results.
+pub(crate) fn bindings_to_sparql_json(
+    variables: &[String],
+    bindings: &[Binding],
+    lookup_table: &LookupTable,
+) -> Vec<u8> {
+    let bindings = bindings
+        .iter()
+        .map(|binding| {
+            let mut object = Map::new();
+            for (name, term) in variables.iter().zip(binding) {
+                if let Some(term) = term.and_then(|t| to_sparql_term(t, lookup_table)) {
+                    let mut value = Map::new();
+                    value.insert("type".into(), Value::String(term.kind.into()));
+                    value.insert("value".into(), Value::String(term.value.into()));
+                    if let Some(datatype) = term.datatype {
+                        value.insert("datatype".into(), Value::String(datatype.into()));
+                    }
+                    if let Some(language) = term.language {
+                        value.insert("xml:lang".into(), Value::String(language.into()));
+                    }
+                    object.insert(name.clone(), Value::Object(value));
+                }
+            }
+
+            Value::Object(object)
+        })
+        .collect();
+
+    let mut head = Map::new();
+    head.insert(
+        "vars".into(),
+        Value::Array(variables.iter().cloned().map(Value::String).collect()),
+    );
+    let mut results = Map::new();
+    results.insert("bindings".into(), Value::Array(bindings));
+    let mut output = Map::new();
+    output.insert("head".into(), Value::Object(head));
+    output.insert("results".into(), Value::Object(results));
+
+    serde_json::to_vec(&Value::Object(output)).unwrap()
+}
+
+/// Serializes variable bindings as SPARQL XML results.
+pub(crate) fn bindings_to_sparql_xml(
+    variables: &[String],
+    bindings: &[Binding],
+    lookup_table: &LookupTable,
+) -> Vec<u8> {
+    let mut output = String::from(
+        "<?xml version=\"1.0\"?>\n<sparql xmlns=\"http://www.w3.org/2005/sparql-results#\">\n<head>",
+    );
+    for name in variables {
+        output.push_str(&format!("<variable name=\"{}\"/>", escape_xml(name)));
+    }
+    output.push_str("</head>\n<results>\n");
+
+    for binding in bindings {
+        output.push_str("<result>");
+        for (name, term) in variables.iter().zip(binding) {
+            if let Some(term) = term.and_then(|t| to_sparql_term(t, lookup_table)) {
+                let attributes = match (term.datatype, term.language) {
+                    (_, Some(language)) => format!(" xml:lang=\"{}\"", escape_xml(language)),
+                    (Some(datatype), _) => format!(" datatype=\"{}\"", escape_xml(datatype)),
+                    _ => String::new(),
+                };
+                output.push_str(&format!(
+                    "<binding name=\"{name}\"><{kind}{attributes}>{value}</{kind}></binding>",
+                    name = escape_xml(name),
+                    kind = term.kind,
+                    attributes = attributes,
+                    value = escape_xml(term.value),
+                ));
+            }
+        }
+        output.push_str("</result>\n");
+    }
+    output.push_str("</results>\n</sparql>\n");
+
+    output.into_bytes()
+}
+
 pub(crate) fn hashtuple_to_hextuple<'a>(
     h: &Statement,
     lookup_table: &'a LookupTable,
@@ -161,11 +468,11 @@ fn hash_to_rio(hashtuples: HashModel, lookup_table: &LookupTable) -> Vec<Quad> {
 }
 
 fn to_named_or_blanknode(value: &String) -> NamedOrBlankNode {
-    if value.contains(':') {
+    if !value.starts_with("_:") && value.contains(':') {
         NamedOrBlankNode::NamedNode(NamedNode { iri: value })
     } else {
         NamedOrBlankNode::BlankNode(BlankNode {
-            id: value[2..].into(),
+            id: value.trim_start_matches("_:"),
         })
     }
 }
@@ -173,7 +480,7 @@ fn to_named_or_blanknode(value: &String) -> NamedOrBlankNode {
 fn to_object<'a>(value: &'a String, datatype: &'a String, language: &'a String) -> Term<'a> {
     match datatype.as_str() {
         BLANK_NODE_IRI => Term::BlankNode(BlankNode {
-            id: value[2..].into(),
+            id: value.trim_start_matches("_:"),
         }),
         NAMED_NODE_IRI => Term::NamedNode(NamedNode { iri: value }),
         LANG_STRING_IRI => Term::Literal(Literal::LanguageTaggedString { value, language }),
@@ -183,3 +490,442 @@ fn to_object<'a>(value: &'a String, datatype: &'a String, language: &'a String)
         }),
     }
 }
+
+fn extend_rdfjson(
+    subjects: &mut Map<String, Value>,
+    hashtuples: HashModel,
+    lookup_table: &LookupTable,
+) {
+    for h in hashtuples {
+        let [subject, predicate, value, datatype, language, _] =
+            hashtuple_to_hextuple(&h, lookup_table);
+
+        let predicates = subjects
+            .entry(to_jsonld_id(subject))
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(predicates) = predicates {
+            push_jsonld_value(
+                predicates,
+                predicate,
+                to_rdfjson_object(value, datatype, language),
+            );
+        }
+    }
+}
+
+fn to_rdfjson_object(value: &str, datatype: &str, language: &str) -> Value {
+    let mut object = Map::new();
+
+    match datatype {
+        BLANK_NODE_IRI => {
+            object.insert("type".into(), Value::String("bnode".into()));
+            object.insert("value".into(), Value::String(to_blank_node_id(value)));
+        }
+        NAMED_NODE_IRI => {
+            object.insert("type".into(), Value::String("uri".into()));
+            object.insert("value".into(), Value::String(value.into()));
+        }
+        LANG_STRING_IRI => {
+            object.insert("type".into(), Value::String("literal".into()));
+            object.insert("value".into(), Value::String(value.into()));
+            object.insert("lang".into(), Value::String(language.into()));
+        }
+        _ => {
+            object.insert("type".into(), Value::String("literal".into()));
+            object.insert("value".into(), Value::String(value.into()));
+            object.insert("datatype".into(), Value::String(datatype.into()));
+        }
+    }
+
+    Value::Object(object)
+}
+
+/// Node objects grouped per graph, in order of first appearance.
+#[derive(Default)]
+struct JsonLdGraphs {
+    graphs: Vec<(String, Vec<Map<String, Value>>)>,
+    graph_index: HashMap<String, usize>,
+    node_index: HashMap<(usize, String), usize>,
+}
+
+impl JsonLdGraphs {
+    fn extend(&mut self, hashtuples: HashModel, lookup_table: &LookupTable) {
+        for h in hashtuples {
+            let [subject, predicate, value, datatype, language, graph] =
+                hashtuple_to_hextuple(&h, lookup_table);
+            let node = self.node(graph, &to_jsonld_id(subject));
+
+            if predicate == RDF_TYPE_IRI && datatype == NAMED_NODE_IRI {
+                push_jsonld_value(node, "@type", Value::String(value.clone()));
+            } else {
+                push_jsonld_value(node, predicate, to_jsonld_object(value, datatype, language));
+            }
+        }
+    }
+
+    fn node(&mut self, graph: &str, id: &str) -> &mut Map<String, Value> {
+        let graphs = &mut self.graphs;
+        let graph_pos = *self
+            .graph_index
+            .entry(graph.to_string())
+            .or_insert_with(|| {
+                graphs.push((graph.to_string(), vec![]));
+                graphs.len() - 1
+            });
+
+        let nodes = &mut self.graphs[graph_pos].1;
+        let node_pos = *self
+            .node_index
+            .entry((graph_pos, id.to_string()))
+            .or_insert_with(|| {
+                let mut node = Map::new();
+                node.insert("@id".into(), Value::String(id.to_string()));
+                nodes.push(node);
+                nodes.len() - 1
+            });
+
+        &mut nodes[node_pos]
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut output = vec![];
+
+        for (graph, nodes) in self.graphs {
+            let nodes = nodes.into_iter().map(Value::Object);
+
+            if graph.is_empty() {
+                output.extend(nodes);
+            } else {
+                let mut named_graph = Map::new();
+                named_graph.insert("@id".into(), Value::String(to_jsonld_id(&graph)));
+                named_graph.insert("@graph".into(), Value::Array(nodes.collect()));
+                output.push(Value::Object(named_graph));
+            }
+        }
+
+        serde_json::to_vec(&Value::Array(output)).unwrap()
+    }
+}
+
+fn push_jsonld_value(node: &mut Map<String, Value>, key: &str, value: Value) {
+    match node
+        .entry(key)
+        .or_insert_with(|| Value::Array(Vec::with_capacity(1)))
+    {
+        Value::Array(values) => values.push(value),
+        _ => unreachable!("JSON-LD properties are always expanded to arrays"),
+    }
+}
+
+fn to_jsonld_id(value: &str) -> String {
+    if value.starts_with("_:") || value.contains(':') {
+        value.into()
+    } else {
+        format!("_:{}", value)
+    }
+}
+
+fn to_blank_node_id(value: &str) -> String {
+    if value.starts_with("_:") {
+        value.into()
+    } else {
+        format!("_:{}", value)
+    }
+}
+
+fn to_jsonld_object(value: &str, datatype: &str, language: &str) -> Value {
+    let mut object = Map::new();
+
+    match datatype {
+        BLANK_NODE_IRI => {
+            object.insert("@id".into(), Value::String(to_blank_node_id(value)));
+        }
+        NAMED_NODE_IRI => {
+            object.insert("@id".into(), Value::String(to_jsonld_id(value)));
+        }
+        LANG_STRING_IRI => {
+            object.insert("@value".into(), Value::String(value.into()));
+            object.insert("@language".into(), Value::String(language.into()));
+        }
+        STRING_IRI => {
+            object.insert("@value".into(), Value::String(value.into()));
+        }
+        _ => {
+            object.insert("@value".into(), Value::String(value.into()));
+            object.insert("@type".into(), Value::String(datatype.into()));
+        }
+    }
+
+    Value::Object(object)
+}
+
+/// A term in SPARQL results.
+struct SparqlTerm<'a> {
+    /// One of `uri`, `bnode` or `literal`.
+    kind: &'static str,
+    value: &'a str,
+    datatype: Option<&'a str>,
+    language: Option<&'a str>,
+}
+
+/// The term at `position` of `statement`, `None` for the default graph since it's unnamed.
+fn to_sparql_term<'a>(
+    (statement, position): (Statement, Position),
+    lookup_table: &'a LookupTable,
+) -> Option<SparqlTerm<'a>> {
+    let lookup = |hash| lookup_table.get_by_hash(hash).unwrap().as_str();
+    let term = |kind, value| SparqlTerm {
+        kind,
+        value,
+        datatype: None,
+        language: None,
+    };
+
+    let term = match position {
+        Position::Subject => {
+            let subject = lookup(statement.subject);
+            if subject.starts_with("_:") || !subject.contains(':') {
+                term("bnode", subject.trim_start_matches("_:"))
+            } else {
+                term("uri", subject)
+            }
+        }
+        Position::Predicate => term("uri", lookup(statement.predicate)),
+        Position::Datatype => term("uri", lookup(statement.datatype)),
+        Position::Graph => match lookup(statement.graph) {
+            "" => return None,
+            graph => term("uri", graph),
+        },
+        Position::Language => term("literal", lookup(statement.language)),
+        Position::Value => {
+            let value = lookup(statement.value);
+            match lookup(statement.datatype) {
+                NAMED_NODE_IRI => term("uri", value),
+                BLANK_NODE_IRI => term("bnode", value.trim_start_matches("_:")),
+                LANG_STRING_IRI => SparqlTerm {
+                    language: Some(lookup(statement.language)),
+                    ..term("literal", value)
+                },
+                STRING_IRI => term("literal", value),
+                datatype => SparqlTerm {
+                    datatype: Some(datatype),
+                    ..term("literal", value)
+                },
+            }
+        }
+    };
+
+    Some(term)
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_result_to_trig_with_document_graphs() {
+        let mut table = LookupTable::new(0);
+        let statement = |table: &mut LookupTable, subject: &str| {
+            Statement::new(
+                table.ensure_value(subject),
+                table.ensure_value("http://schema.org/name"),
+                table.ensure_value("Name"),
+                table.ensure_value(STRING_IRI),
+                table.ensure_value(""),
+                table.ensure_value("http://purl.org/link-lib/supplant"),
+            )
+        };
+        let docs = vec![
+            Some(vec![statement(&mut table, "https://example.com/a")]),
+            Some(vec![statement(&mut table, "https://example.com/b")]),
+        ];
+        let iris = vec![
+            String::from("https://example.com/a"),
+            String::from("https://example.com/b"),
+        ];
+
+        let output = bulk_result_to_trig(to_document_graphs((docs, table), &iris));
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("<https://example.com/a> {"));
+        assert!(output.contains("<https://example.com/b> {"));
+        assert!(!output.contains("supplant"));
+    }
+
+    fn literal_model(table: &mut LookupTable) -> HashModel {
+        let mut statement = |predicate: &str, value: &str, datatype: &str, language: &str| {
+            Statement::new(
+                table.ensure_value("https://example.com/a"),
+                table.ensure_value(predicate),
+                table.ensure_value(value),
+                table.ensure_value(datatype),
+                table.ensure_value(language),
+                table.ensure_value(""),
+            )
+        };
+
+        vec![
+            statement("http://schema.org/name", "Naam", LANG_STRING_IRI, "nl"),
+            statement(
+                "http://schema.org/age",
+                "3",
+                "http://www.w3.org/2001/XMLSchema#integer",
+                "",
+            ),
+            statement("http://schema.org/author", "b0", BLANK_NODE_IRI, ""),
+            statement(
+                "http://schema.org/url",
+                "https://example.com/",
+                NAMED_NODE_IRI,
+                "",
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_hash_model_to_rdfjson() {
+        let mut table = LookupTable::new(0);
+        let model = literal_model(&mut table);
+
+        let output: Value =
+            serde_json::from_slice(&hash_model_to_rdfjson((model, &table))).unwrap();
+
+        assert_eq!(
+            output,
+            serde_json::json!({
+                "https://example.com/a": {
+                    "http://schema.org/name": [{ "type": "literal", "value": "Naam", "lang": "nl" }],
+                    "http://schema.org/age": [{
+                        "type": "literal",
+                        "value": "3",
+                        "datatype": "http://www.w3.org/2001/XMLSchema#integer"
+                    }],
+                    "http://schema.org/author": [{ "type": "bnode", "value": "_:b0" }],
+                    "http://schema.org/url": [{ "type": "uri", "value": "https://example.com/" }]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_hash_model_to_rdfxml() {
+        let mut table = LookupTable::new(0);
+        let model = literal_model(&mut table);
+
+        let output = String::from_utf8(hash_model_to_rdfxml((model, &table))).unwrap();
+
+        assert!(output.contains("rdf:about=\"https://example.com/a\""));
+        assert!(output.contains("xml:lang=\"nl\">Naam<"));
+        assert!(output.contains("rdf:datatype=\"http://www.w3.org/2001/XMLSchema#integer\">3<"));
+        assert!(output.contains("rdf:nodeID=\"b0\""));
+        assert!(output.contains("rdf:resource=\"https://example.com/\""));
+    }
+
+    #[test]
+    fn test_hash_model_to_jsonld() {
+        let mut table = LookupTable::new(0);
+        let mut statement = |subject: &str,
+                             predicate: &str,
+                             value: &str,
+                             datatype: &str,
+                             language: &str,
+                             graph: &str| {
+            Statement::new(
+                table.ensure_value(subject),
+                table.ensure_value(predicate),
+                table.ensure_value(value),
+                table.ensure_value(datatype),
+                table.ensure_value(language),
+                table.ensure_value(graph),
+            )
+        };
+        let model = vec![
+            statement(
+                "https://example.com/a",
+                RDF_TYPE_IRI,
+                "http://schema.org/Thing",
+                NAMED_NODE_IRI,
+                "",
+                "",
+            ),
+            statement(
+                "https://example.com/a",
+                "http://schema.org/name",
+                "Naam",
+                LANG_STRING_IRI,
+                "nl",
+                "",
+            ),
+            statement(
+                "https://example.com/a",
+                "http://schema.org/age",
+                "3",
+                "http://www.w3.org/2001/XMLSchema#integer",
+                "",
+                "",
+            ),
+            statement(
+                "https://example.com/a",
+                "http://schema.org/author",
+                "_:b0",
+                BLANK_NODE_IRI,
+                "",
+                "",
+            ),
+            statement(
+                "_:b0",
+                "http://schema.org/name",
+                "Plain",
+                STRING_IRI,
+                "",
+                "",
+            ),
+            statement(
+                "https://example.com/a",
+                "http://schema.org/name",
+                "Meta",
+                STRING_IRI,
+                "",
+                "https://example.com/g",
+            ),
+        ];
+
+        let output: Value = serde_json::from_slice(&hash_model_to_jsonld((model, &table))).unwrap();
+
+        assert_eq!(
+            output,
+            serde_json::json!([
+                {
+                    "@id": "https://example.com/a",
+                    "@type": ["http://schema.org/Thing"],
+                    "http://schema.org/name": [{ "@value": "Naam", "@language": "nl" }],
+                    "http://schema.org/age": [{
+                        "@value": "3",
+                        "@type": "http://www.w3.org/2001/XMLSchema#integer"
+                    }],
+                    "http://schema.org/author": [{ "@id": "_:b0" }]
+                },
+                {
+                    "@id": "_:b0",
+                    "http://schema.org/name": [{ "@value": "Plain" }]
+                },
+                {
+                    "@id": "https://example.com/g",
+                    "@graph": [{
+                        "@id": "https://example.com/a",
+                        "http://schema.org/name": [{ "@value": "Meta" }]
+                    }]
+                }
+            ])
+        );
+    }
+}
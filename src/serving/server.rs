@@ -1,20 +1,29 @@
 use crate::app_config::AppConfig;
-use crate::db::db_context::DbContext;
+use crate::db::storage::storage_from_config;
+use crate::importing::outbox::relay_outbox;
 use crate::serving::assets::favicon;
+use crate::serving::bgp::bgp;
 use crate::serving::bulk::bulk;
+use crate::serving::changes::{changes, ChangeFeed};
 use crate::serving::health::health;
-use crate::serving::hpf::{hpf, tpf};
+use crate::serving::history::history;
+use crate::serving::hpf::{hpf, qpf, tpf, tpf_storage};
 use crate::serving::metrics::metrics;
 use crate::serving::reporter::Reporter;
+use crate::serving::search::search;
 use crate::serving::service_info::service_info;
 use crate::serving::show_resource::{random_resource, show_resource, show_resource_ext};
+use crate::serving::sparql::{sparql, sparql_post};
 use crate::serving::update::update;
 use actix_http::http::{HeaderName, HeaderValue};
 use actix_web::dev::Service;
-use actix_web::{middleware, App, HttpServer};
+use actix_web::{middleware, web, App, HttpServer};
 use futures::io::ErrorKind;
 use uuid::Uuid;
 
+/// The endpoints which query Postgres directly, which other storage backends don't serve.
+const POSTGRES_ENDPOINTS: [&str; 5] = ["/hpf", "/qpf", "/bgp", "/search", "/sparql"];
+
 fn secret_for_print(v: Option<String>) -> isize {
     v.map_or(-1 as isize, |v| v.len() as isize)
 }
@@ -40,7 +49,11 @@ fn print_config(cfg: &AppConfig) {
     service_guest_token: {}
     session_cookie_name: {}
     session_cookie_sig_name: {}
-    session_secret: {}", 
+    session_secret: {}
+    storage_backend: {:?}
+    history_retention: {:?}
+    outbox: {:?}
+    changes_channel: {}", 
             cfg.binding,
             value_for_print(cfg.client_id.clone()),
             secret_for_print(cfg.client_secret.clone()),
@@ -57,6 +70,10 @@ fn print_config(cfg: &AppConfig) {
             value_for_print(cfg.session_cookie_name.clone()),
             value_for_print(cfg.session_cookie_sig_name.clone()),
             secret_for_print(cfg.session_secret.clone()),
+            cfg.storage_backend,
+            cfg.history_retention,
+            cfg.outbox,
+            value_for_print(cfg.changes_channel.clone()),
     );
 }
 
@@ -66,17 +83,36 @@ pub async fn serve() -> std::io::Result<()> {
         print_config(&config);
     }
     let reporter = Reporter::default();
-    let pool = DbContext::default_pool(config.database_url.clone(), config.database_pool_size)
-        .map_err(|e| {
-            error!(target: "apex", "{}", e);
-            ErrorKind::Other
-        })?;
+    let storage = storage_from_config(&config).map_err(|e| {
+        error!(target: "apex", "{}", e);
+        ErrorKind::Other
+    })?;
+    if storage.pool().is_none() && !config.disable_persistence {
+        warn!(
+            target: "apex",
+            "The {:?} storage backend doesn't serve {}",
+            config.storage_backend,
+            POSTGRES_ENDPOINTS.join(", ")
+        );
+    }
+    if config.outbox.is_some() && storage.pool().is_none() {
+        let relay = relay_outbox(storage.clone(), config.clone());
+        actix_rt::spawn(async move {
+            if let Err(e) = relay.await {
+                error!(target: "apex", "Outbox relay stopped: {}", e);
+            }
+        });
+    }
+    let feed = config
+        .changes_channel
+        .clone()
+        .map(|channel| ChangeFeed::listen(&config, channel));
     let address = format!("{}:{}", config.binding, config.port);
 
     HttpServer::new(move || {
         let app = App::new()
             .data(config.clone())
-            .data(pool.clone())
+            .app_data(web::Data::from(storage.clone()))
             .data(reporter.clone())
             .wrap(middleware::Logger::new(
                 r#"[%{X-Request-Id}i] %a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#,
@@ -106,12 +142,26 @@ pub async fn serve() -> std::io::Result<()> {
             .service(favicon)
             .service(bulk)
             .service(health)
+            .service(history)
             .service(service_info);
 
-        let app = if !config.disable_persistence {
-            app.service(tpf).service(hpf)
-        } else {
-            app
+        let app = match storage.pool() {
+            _ if config.disable_persistence => app,
+            Some(pool) => app
+                .data(pool.clone())
+                .service(tpf)
+                .service(hpf)
+                .service(qpf)
+                .service(bgp)
+                .service(search)
+                .service(sparql)
+                .service(sparql_post),
+            None => app.service(tpf_storage),
+        };
+
+        let app = match &feed {
+            Some(feed) => app.data(feed.clone()).service(changes),
+            None => app,
         };
 
         let mut app = app
@@ -1,8 +1,13 @@
-use crate::serving::response_type::ResponseType::JSONLD;
+use crate::db::db_context::DbPool;
+use crate::delta::registry::registry;
+use crate::hashtuple::LookupTable;
+use crate::serving::response_type::ResponseType;
 use crate::serving::responses::set_default_headers;
+use crate::serving::serialization::{serialize_bulk, serialize_model};
 use crate::serving::ua::basic_ua;
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse, Responder};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Deserialize, Serialize)]
 struct Envelope<'a> {
@@ -70,27 +75,41 @@ struct ContentTypeMap {
     rdfxml: bool,
     #[serde(rename = "text/n3")]
     n3: bool,
+    #[serde(rename = "application/trig")]
+    trig: bool,
+}
+
+impl ContentTypeMap {
+    /// The content types which have a serializer for both documents and bulk responses.
+    fn supported() -> ContentTypeMap {
+        let prefixes = BTreeMap::new();
+        let supports = |response_type| {
+            serialize_model(&response_type, (vec![], &LookupTable::new(0)), &prefixes).is_some()
+                && serialize_bulk(&response_type, (vec![], LookupTable::new(0)), &prefixes)
+                    .is_some()
+        };
+
+        ContentTypeMap {
+            turtle: supports(ResponseType::TURTLE),
+            hex: supports(ResponseType::HEXTUPLE),
+            nquads: supports(ResponseType::NQUADS),
+            ntriples: supports(ResponseType::NTRIPLES),
+            jsonld: supports(ResponseType::JSONLD),
+            rdfjson: supports(ResponseType::RDFJSON),
+            rdfxml: supports(ResponseType::RDFXML),
+            n3: supports(ResponseType::N3),
+            trig: supports(ResponseType::TRIG),
+        }
+    }
 }
 
 /// Linked Delta informational endpoint
 #[get("/.well-known/ld")]
-pub(crate) async fn service_info<'a>() -> impl Responder {
-    let ct_map = ContentTypeMap {
-        turtle: true,
-        hex: true,
-        nquads: true,
-        ntriples: true,
-        jsonld: false,
-        rdfjson: false,
-        rdfxml: false,
-        n3: false,
-    };
+pub(crate) async fn service_info<'a>(pool: Option<web::Data<DbPool>>) -> impl Responder {
+    let ct_map = ContentTypeMap::supported();
 
     let name = basic_ua();
-    let operators = vec![
-        "http://purl.org/linked-delta/add",
-        "http://purl.org/linked-delta/replace",
-    ];
+    let operators = registry().operators();
     let arguments = vec!["graph"];
     let endpoints = EndpointMap {
         bulk: Some(EndpointInformation {
@@ -114,7 +133,8 @@ pub(crate) async fn service_info<'a>() -> impl Responder {
             stability: EndpointStability::Experimental,
             info: None,
         }),
-        hpf: Some(EndpointInformation {
+        // Hex pattern fragments are only served by postgres.
+        hpf: pool.map(|_| EndpointInformation {
             path: "/hpf".into(),
             method: "POST".into(),
             content_types: ct_map,
@@ -125,10 +145,41 @@ pub(crate) async fn service_info<'a>() -> impl Responder {
 
     let body = Envelope {
         name: &name,
-        operators,
+        operators: operators.iter().map(String::as_str).collect(),
         arguments,
         endpoints,
     };
 
-    set_default_headers(&mut HttpResponse::Ok(), &JSONLD).json(body)
+    set_default_headers(&mut HttpResponse::Ok(), &ResponseType::JSONLD).json(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serving::response_type::RDF_RESPONSE_TYPES;
+    use actix_web::{test, App};
+
+    #[test]
+    fn test_supported_content_types() {
+        let supported = serde_json::to_value(ContentTypeMap::supported()).unwrap();
+
+        for response_type in RDF_RESPONSE_TYPES.iter() {
+            let mime = response_type.to_mime();
+            assert_eq!(supported[mime.split(';').next().unwrap()], true);
+        }
+        assert_eq!(
+            supported.as_object().unwrap().len(),
+            RDF_RESPONSE_TYPES.len()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_service_info_without_pool() {
+        let mut app = test::init_service(App::new().service(service_info)).await;
+        let req = test::TestRequest::get().uri("/.well-known/ld").to_request();
+        let body: serde_json::Value = test::read_response_json(&mut app, req).await;
+
+        assert_eq!(body["endpoints"]["tpf"]["path"], "/tpf");
+        assert!(body["endpoints"]["hpf"].is_null());
+    }
 }
@@ -1,42 +1,63 @@
-use crate::db::db_context::{DbContext, DbPool};
-use crate::db::document::{doc_by_iri, random_doc};
+use crate::app_config::AppConfig;
+use crate::db::cache_control::CacheControl;
+use crate::db::storage::{RevisionSelector, Storage};
 use crate::errors::ErrorKind;
+use crate::hashtuple::HashModel;
+use crate::serving::negotiation::{negotiate, not_acceptable};
 use crate::serving::response_type::ResponseType;
-use crate::serving::responses::set_default_headers;
-use crate::serving::serialization::{
-    hash_model_to_hextuples, hash_model_to_ntriples, hash_model_to_turtle,
-};
+use crate::serving::responses::{set_default_headers, VARY};
+use crate::serving::serialization::serialize_model;
+use actix_web::dev::HttpResponseBuilder;
 use actix_web::error::BlockingError;
-use actix_web::http::{header, HeaderMap};
+use actix_web::http::header::{self, EntityTag, Header, HttpDate};
 use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Timelike, Utc};
+use fasthash::murmur3;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::SystemTime;
+
+const ACCEPT_DATETIME: &str = "Accept-Datetime";
+
+#[derive(Deserialize)]
+struct RevisionQuery {
+    version: Option<i64>,
+}
 
 #[get("/random")]
 pub(crate) async fn random_resource<'a>(
     req: actix_web::HttpRequest,
-    pool: web::Data<DbPool>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<AppConfig>,
 ) -> impl Responder {
-    let response_type = match negotiate(req.headers(), &None) {
+    let response_type = match negotiate(req.headers()) {
         Some(s) => s,
-        None => return HttpResponse::NotAcceptable().finish(),
+        None => return not_acceptable(),
     };
 
-    let pl = pool.into_inner();
+    let storage = Arc::clone(&storage);
 
     let random_doc = web::block(move || {
-        let mut ctx = DbContext::new(&pl);
+        let mut ctx = storage.open(None);
 
-        match random_doc(&mut ctx) {
-            Ok((_, model)) => Ok((model, ctx.lookup_table)),
+        match ctx.random_document() {
+            Ok((_, model)) => Ok((model, ctx.into_lookup_table())),
             Err(e) => Err(e),
         }
     })
     .await;
 
     match random_doc {
-        Ok(doc) => set_default_headers(&mut HttpResponse::Ok(), &response_type)
-            .body(hash_model_to_hextuples((doc.0, &doc.1))),
+        Ok(doc) => {
+            match serialize_model(&response_type, (doc.0, &doc.1), &config.turtle_prefixes) {
+                Some(body) => {
+                    set_default_headers(&mut HttpResponse::Ok(), &response_type).body(body)
+                }
+                None => not_acceptable(),
+            }
+        }
         Err(BlockingError::Error(ErrorKind::EmptyDocument)) => HttpResponse::NoContent().finish(),
         Err(e) => {
             error!(target: "apex", "Unknown error: {}", e);
@@ -49,74 +70,101 @@ pub(crate) async fn random_resource<'a>(
 #[get("/{id}.{ext}")]
 pub(crate) async fn show_resource_ext<'a>(
     req: actix_web::HttpRequest,
-    pool: web::Data<DbPool>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<AppConfig>,
     info: web::Path<(String, String)>,
 ) -> HttpResponse {
     if let Ok(response_type) = ResponseType::from_ext(&info.1) {
         let path = info.into_inner().0;
-        let pl = pool.into_inner();
+        let storage = Arc::clone(&storage);
 
-        match iri_from_request(req, &path) {
-            Some(iri) => show(pl, &iri, response_type).await,
+        match iri_from_request(&req, &path) {
+            Some(iri) => show(&req, storage, &config, &iri, response_type).await,
             None => HttpResponse::BadRequest().finish(),
         }
     } else {
-        HttpResponse::NotAcceptable().finish()
+        not_acceptable()
     }
 }
 
 #[get("/{id}")]
 pub(crate) async fn show_resource<'a>(
     req: actix_web::HttpRequest,
-    pool: web::Data<DbPool>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<AppConfig>,
     info: web::Path<(String,)>,
 ) -> HttpResponse {
-    let response_type = match negotiate(req.headers(), &None) {
+    let response_type = match negotiate(req.headers()) {
         Some(s) => s,
-        None => return HttpResponse::NotAcceptable().finish(),
+        None => return not_acceptable(),
     };
     let path = info.into_inner().0;
-    let pl = pool.into_inner();
+    let storage = Arc::clone(&storage);
 
-    match iri_from_request(req, &path) {
-        Some(iri) => show(pl, &iri, response_type).await,
+    match iri_from_request(&req, &path) {
+        Some(iri) => show(&req, storage, &config, &iri, response_type).await,
         None => HttpResponse::BadRequest().finish(),
     }
 }
 
-#[allow(clippy::borrow_interior_mutable_const)]
-async fn show<'a>(pl: Arc<DbPool>, iri: &str, response_type: ResponseType) -> HttpResponse {
+async fn show<'a>(
+    req: &actix_web::HttpRequest,
+    storage: Arc<dyn Storage>,
+    config: &AppConfig,
+    iri: &str,
+    response_type: ResponseType,
+) -> HttpResponse {
+    match revision_selector(req) {
+        Ok(Some(selector)) => {
+            return show_revision(storage, config, iri, response_type, selector).await
+        }
+        Ok(None) => (),
+        Err(res) => return res,
+    }
+
     let iri_move = String::from(iri);
 
     let doc = web::block(move || {
-        let mut ctx = DbContext::new(&pl);
+        let mut ctx = storage.open(None);
 
-        match doc_by_iri(&mut ctx, &iri_move) {
-            Ok((_, model)) => Ok((model, ctx.lookup_table)),
+        match ctx.get_document(&iri_move) {
+            Ok((doc, model)) => Ok((doc, model, ctx.into_lookup_table())),
             Err(_) => Err(404),
         }
     })
     .await;
 
     if doc.is_err() {
-        return HttpResponse::NotFound().finish();
+        return HttpResponse::NotFound()
+            .set_header(header::VARY, VARY)
+            .finish();
     }
 
-    let (model, lookup_table) = doc.unwrap();
-    let serialization = match response_type {
-        ResponseType::HEXTUPLE => hash_model_to_hextuples((model, &lookup_table)),
-        ResponseType::NTRIPLES | ResponseType::NQUADS => {
-            hash_model_to_ntriples((model, &lookup_table))
-        }
-        ResponseType::TURTLE => hash_model_to_turtle((model, &lookup_table)),
-        _ => return HttpResponse::NotAcceptable().finish(),
+    let (doc, model, lookup_table) = doc.unwrap();
+    let cache_control = CacheControl::from(doc.cache_control);
+    let etag = entity_tag(&model, &response_type);
+    let last_modified = last_modified(&doc.updated_at);
+
+    if !is_modified(req, &etag, last_modified) {
+        let mut res = HttpResponse::NotModified();
+        set_cache_headers(&mut res, cache_control, etag, last_modified);
+
+        return set_default_headers(&mut res, &response_type).finish();
+    }
+
+    let serialization = match serialize_model(
+        &response_type,
+        (model, &lookup_table),
+        &config.turtle_prefixes,
+    ) {
+        Some(serialization) => serialization,
+        None => return not_acceptable(),
     };
 
-    set_default_headers(&mut HttpResponse::Ok(), &response_type)
-        .set(header::CacheControl(vec![
-            header::CacheDirective::MaxAge(86400u32),
-            header::CacheDirective::Public,
-        ]))
+    let mut res = HttpResponse::Ok();
+    set_cache_headers(&mut res, cache_control, etag, last_modified);
+
+    set_default_headers(&mut res, &response_type)
         .set_header(
             "Content-Disposition",
             format!("inline; filename={}", iri_to_filename(&iri, &response_type)),
@@ -124,7 +172,167 @@ async fn show<'a>(pl: Arc<DbPool>, iri: &str, response_type: ResponseType) -> Ht
         .body(serialization)
 }
 
-fn iri_from_request(req: actix_web::HttpRequest, path: &str) -> Option<String> {
+/// Responds with a previous state of the document as a Memento (RFC 7089).
+#[allow(clippy::borrow_interior_mutable_const)]
+async fn show_revision(
+    storage: Arc<dyn Storage>,
+    config: &AppConfig,
+    iri: &str,
+    response_type: ResponseType,
+    selector: RevisionSelector,
+) -> HttpResponse {
+    let iri_move = String::from(iri);
+
+    let revision = web::block(move || -> Result<_, ErrorKind> {
+        let mut ctx = storage.open(None);
+        let (revision, model) = ctx.get_revision(&iri_move, &selector)?;
+
+        Ok((revision, model, ctx.into_lookup_table()))
+    })
+    .await;
+
+    let (revision, model, lookup_table) = match revision {
+        Ok(revision) => revision,
+        Err(BlockingError::Error(ErrorKind::NotFound)) => {
+            return HttpResponse::NotFound()
+                .set_header(header::VARY, VARY)
+                .finish()
+        }
+        Err(e) => {
+            error!(target: "apex", "Error fetching revision: {}", e);
+
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if revision.deleted {
+        return HttpResponse::Gone().set_header(header::VARY, VARY).finish();
+    }
+
+    let serialization = match serialize_model(
+        &response_type,
+        (model, &lookup_table),
+        &config.turtle_prefixes,
+    ) {
+        Some(serialization) => serialization,
+        None => return not_acceptable(),
+    };
+    let links = format!(
+        "<{}>; rel=\"original\", </history?iri={}>; rel=\"timemap\"",
+        iri,
+        utf8_percent_encode(iri, NON_ALPHANUMERIC)
+    );
+
+    let mut res = HttpResponse::Ok();
+    res.set(header::CacheControl(vec![header::CacheDirective::Private]));
+
+    set_default_headers(&mut res, &response_type)
+        .set_header(
+            "Memento-Datetime",
+            last_modified(&revision.recorded_at).to_string(),
+        )
+        .set_header(header::LINK, links)
+        .set_header(
+            "Content-Disposition",
+            format!("inline; filename={}", iri_to_filename(iri, &response_type)),
+        )
+        .body(serialization)
+}
+
+/// The revision requested with the `version` parameter or the Accept-Datetime header.
+fn revision_selector(
+    req: &actix_web::HttpRequest,
+) -> Result<Option<RevisionSelector>, HttpResponse> {
+    let query = web::Query::<RevisionQuery>::from_query(req.query_string())
+        .map_err(|_| HttpResponse::BadRequest().finish())?;
+    if let Some(version) = query.version {
+        return Ok(Some(RevisionSelector::Version(version)));
+    }
+
+    match req.headers().get(ACCEPT_DATETIME) {
+        Some(value) => {
+            let date = value
+                .to_str()
+                .ok()
+                .and_then(|value| HttpDate::from_str(value).ok())
+                .ok_or_else(|| HttpResponse::BadRequest().finish())?;
+            let moment = DateTime::<Utc>::from(SystemTime::from(date)).naive_utc();
+
+            Ok(Some(RevisionSelector::At(moment)))
+        }
+        None => Ok(None),
+    }
+}
+
+#[allow(clippy::borrow_interior_mutable_const)]
+fn set_cache_headers(
+    res: &mut HttpResponseBuilder,
+    cache_control: CacheControl,
+    etag: EntityTag,
+    last_modified: HttpDate,
+) {
+    let directives = match cache_control {
+        CacheControl::Public => vec![
+            header::CacheDirective::MaxAge(86400u32),
+            header::CacheDirective::Public,
+        ],
+        CacheControl::NoCache => vec![header::CacheDirective::NoCache],
+        CacheControl::Private => vec![header::CacheDirective::Private],
+    };
+
+    res.set(header::CacheControl(directives))
+        .set(header::ETag(etag))
+        .set(header::LastModified(last_modified));
+}
+
+/// A strong validator for the representation of `model` as `response_type`.
+fn entity_tag(model: &HashModel, response_type: &ResponseType) -> EntityTag {
+    let mut statements = model.clone();
+    statements.sort();
+
+    let mut bytes = response_type.to_mime().into_bytes();
+    for s in statements {
+        for hash in &[
+            s.subject,
+            s.predicate,
+            s.value,
+            s.datatype,
+            s.language,
+            s.graph,
+        ] {
+            bytes.extend_from_slice(&hash.to_be_bytes());
+        }
+    }
+
+    EntityTag::strong(format!("{:032x}", murmur3::hash128(&bytes)))
+}
+
+/// HTTP dates have whole seconds, so the fraction is dropped to match the date clients echo.
+fn last_modified(updated_at: &NaiveDateTime) -> HttpDate {
+    let updated_at = updated_at.with_nanosecond(0).unwrap();
+
+    HttpDate::from(SystemTime::from(Utc.from_utc_datetime(&updated_at)))
+}
+
+/// Evaluates the conditional request headers, If-None-Match takes precedence over
+/// If-Modified-Since.
+fn is_modified(req: &actix_web::HttpRequest, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        return match header::IfNoneMatch::parse(req) {
+            Ok(header::IfNoneMatch::Any) => false,
+            Ok(header::IfNoneMatch::Items(tags)) => !tags.iter().any(|tag| tag.weak_eq(etag)),
+            Err(_) => true,
+        };
+    }
+
+    match header::IfModifiedSince::parse(req) {
+        Ok(header::IfModifiedSince(since)) => {
+            SystemTime::from(last_modified) > SystemTime::from(since)
+        }
+        Err(_) => true,
+    }
+}
+
+fn iri_from_request(req: &actix_web::HttpRequest, path: &str) -> Option<String> {
     let host = match req.headers().get("Host")?.to_str() {
         Ok(v) => v,
         Err(_) => return None,
@@ -141,30 +349,122 @@ fn iri_to_filename(iri: &str, response_type: &ResponseType) -> String {
     )
 }
 
-fn negotiate(headers: &HeaderMap, ext: &Option<String>) -> Option<ResponseType> {
-    if ext.is_some() {
-        let extention = ext.as_ref().unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_config::HistoryRetention;
+    use crate::db::memory::MemoryStorage;
+    use crate::hashtuple::{LookupTable, Statement};
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+    use chrono::NaiveDate;
+
+    fn model(table: &mut LookupTable, values: &[&str]) -> HashModel {
+        values
+            .iter()
+            .map(|value| {
+                Statement::new(
+                    table.ensure_value("https://example.com/a"),
+                    table.ensure_value("http://schema.org/name"),
+                    table.ensure_value(value),
+                    table.ensure_value("http://www.w3.org/2001/XMLSchema#string"),
+                    table.ensure_value(""),
+                    table.ensure_value(""),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_entity_tag() {
+        let mut table = LookupTable::new(0);
+        let ab = model(&mut table, &["A", "B"]);
+        let ba = model(&mut table, &["B", "A"]);
+        let a = model(&mut table, &["A"]);
 
-        return if let Ok(response_type) = ResponseType::from_ext(extention) {
-            Some(response_type)
-        } else {
-            None
+        let etag = entity_tag(&ab, &ResponseType::TURTLE);
+        assert!(!etag.weak);
+        assert_eq!(etag, entity_tag(&ba, &ResponseType::TURTLE));
+        assert_ne!(etag, entity_tag(&a, &ResponseType::TURTLE));
+        assert_ne!(etag, entity_tag(&ab, &ResponseType::NTRIPLES));
+    }
+
+    #[test]
+    fn test_is_modified() {
+        let updated_at = NaiveDate::from_ymd_opt(2020, 11, 4)
+            .and_then(|date| date.and_hms_micro_opt(9, 30, 15, 500_000))
+            .unwrap();
+        let modified = last_modified(&updated_at);
+        let etag = EntityTag::strong(String::from("abc"));
+        let check = |name, value: &str| {
+            let req = TestRequest::default().header(name, value).to_http_request();
+
+            is_modified(&req, &etag, modified)
         };
+
+        assert!(check(header::IF_NONE_MATCH, "\"xyz\""));
+        assert!(!check(header::IF_NONE_MATCH, "\"xyz\", \"abc\""));
+        assert!(!check(header::IF_NONE_MATCH, "W/\"abc\""));
+        assert!(!check(header::IF_NONE_MATCH, "*"));
+
+        assert!(!check(
+            header::IF_MODIFIED_SINCE,
+            "Wed, 04 Nov 2020 09:30:15 GMT"
+        ));
+        assert!(check(
+            header::IF_MODIFIED_SINCE,
+            "Wed, 04 Nov 2020 09:30:14 GMT"
+        ));
+        assert!(check(header::IF_MODIFIED_SINCE, "invalid"));
+
+        let req = TestRequest::default()
+            .header(header::IF_NONE_MATCH, "\"xyz\"")
+            .header(header::IF_MODIFIED_SINCE, "Wed, 04 Nov 2020 09:30:15 GMT")
+            .to_http_request();
+        assert!(is_modified(&req, &etag, modified));
     }
 
-    match headers.get(header::ACCEPT) {
-        None => None,
-        Some(h) => match h.to_str() {
-            Err(_) => None,
-            Ok(accept) => {
-                if accept.is_empty() || accept.contains("*/*") {
-                    Some(ResponseType::HEXTUPLE)
-                } else if let Ok(m) = ResponseType::from_str(accept) {
-                    Some(m)
-                } else {
-                    None
-                }
+    #[actix_rt::test]
+    async fn test_memento_responses_vary() {
+        let storage = Arc::new(MemoryStorage::new(HistoryRetention {
+            max_versions: 2,
+            max_age_days: None,
+        }));
+        let mut ctx = storage.open(Some(String::from("en")));
+        let statements = model(ctx.lookup_table(), &["A"]);
+        ctx.update_document("https://example.com/a", &mut |_, _| statements.clone())
+            .unwrap();
+        ctx.delete_document("https://example.com/a").unwrap();
+        drop(ctx);
+
+        let storage: Arc<dyn Storage> = storage;
+        let mut app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(storage))
+                .data(AppConfig::default())
+                .service(show_resource),
+        )
+        .await;
+        let cases = [
+            ("/a?version=1", None, 200),
+            ("/a?version=2", None, 410),
+            ("/a?version=3", None, 404),
+            ("/a", Some("Wed, 04 Nov 2020 09:30:15 GMT"), 404),
+            ("/a", None, 404),
+        ];
+        for (uri, accept_datetime, status) in cases.iter() {
+            let mut req = TestRequest::get()
+                .uri(uri)
+                .header(header::HOST, "example.com")
+                .header(header::ACCEPT, "application/n-triples");
+            if let Some(moment) = accept_datetime {
+                req = req.header(ACCEPT_DATETIME, *moment);
             }
-        },
+            let res = test::call_service(&mut app, req.to_request()).await;
+
+            assert_eq!(res.status().as_u16(), *status, "{}", uri);
+            let vary = res.headers().get(header::VARY).unwrap().to_str().unwrap();
+            assert!(vary.contains("Accept-Datetime"), "{}", uri);
+        }
     }
 }
@@ -0,0 +1,136 @@
+use crate::app_config::AppConfig;
+use crate::db::db_context::{DbContext, DbPool};
+use crate::db::sparql::SPARQLQuery;
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, LookupTable};
+use crate::serving::negotiation::{negotiate_from, not_acceptable};
+use crate::serving::response_type::{ResponseType, RDF_RESPONSE_TYPES};
+use crate::serving::responses::set_default_headers;
+use crate::serving::serialization::{
+    bindings_to_sparql_json, bindings_to_sparql_xml, serialize_bulk, Binding,
+};
+use actix_web::error::BlockingError;
+use actix_web::http::header;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use humantime::format_duration;
+use serde::Deserialize;
+use std::time::Instant;
+
+const SPARQL_QUERY_MIME: &str = "application/sparql-query";
+
+#[derive(Deserialize)]
+pub(crate) struct SPARQLRequest {
+    query: String,
+}
+
+enum Results {
+    Bindings(Vec<String>, Vec<Binding>, LookupTable),
+    Graph(HashModel, LookupTable),
+}
+
+/// Evaluates a read-only SPARQL query passed as the `query` parameter.
+#[get("/sparql")]
+pub(crate) async fn sparql(
+    req: actix_web::HttpRequest,
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    payload: web::Query<SPARQLRequest>,
+) -> impl Responder {
+    evaluate(req, pool, config, payload.into_inner().query).await
+}
+
+/// Evaluates a read-only SPARQL query, either passed directly in the body or url encoded.
+#[post("/sparql")]
+pub(crate) async fn sparql_post(
+    req: actix_web::HttpRequest,
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    body: String,
+) -> impl Responder {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    let query = if content_type.starts_with(SPARQL_QUERY_MIME) {
+        body
+    } else {
+        match serde_qs::from_str::<SPARQLRequest>(&body) {
+            Ok(request) => request.query,
+            Err(_) => return HttpResponse::BadRequest().body("Missing query parameter"),
+        }
+    };
+
+    evaluate(req, pool, config, query).await
+}
+
+async fn evaluate(
+    req: actix_web::HttpRequest,
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    query: String,
+) -> HttpResponse {
+    let query = match SPARQLQuery::parse(&query) {
+        Ok(query) => query,
+        Err(ErrorKind::ParserError(msg)) => return HttpResponse::BadRequest().body(msg),
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    let available = if query.is_construct() {
+        RDF_RESPONSE_TYPES.to_vec()
+    } else {
+        vec![ResponseType::SPARQLJSON, ResponseType::SPARQLXML]
+    };
+    let response_type = match negotiate_from(req.headers(), &available) {
+        Some(response_type) => response_type,
+        None => return not_acceptable(),
+    };
+    let pl = pool.into_inner();
+
+    let res = web::block(move || -> Result<Results, ErrorKind> {
+        let mut ctx = DbContext::new(&pl);
+
+        let fetch_start = Instant::now();
+        let results = if query.is_construct() {
+            let model = query.construct(&mut ctx)?;
+            Results::Graph(model, ctx.lookup_table)
+        } else {
+            let (variables, bindings) = query.select(&mut ctx)?;
+            Results::Bindings(variables, bindings, ctx.lookup_table)
+        };
+        let fetch_time = Instant::now().duration_since(fetch_start);
+        debug!(target: "apex", "Fetching cost: {}", format_duration(fetch_time));
+
+        Ok(results)
+    })
+    .await;
+
+    let results = match res {
+        Ok(results) => results,
+        Err(err) => {
+            error!(target: "apex", "Caught error: {:?}", err);
+            return match err {
+                BlockingError::Error(ErrorKind::ParserError(msg)) => {
+                    HttpResponse::BadRequest().body(msg)
+                }
+                _ => HttpResponse::InternalServerError().finish(),
+            };
+        }
+    };
+
+    let body = match results {
+        Results::Bindings(variables, bindings, table) => match response_type {
+            ResponseType::SPARQLXML => bindings_to_sparql_xml(&variables, &bindings, &table),
+            _ => bindings_to_sparql_json(&variables, &bindings, &table),
+        },
+        Results::Graph(model, table) => serialize_bulk(
+            &response_type,
+            (vec![Some(model)], table),
+            &config.turtle_prefixes,
+        )
+        .unwrap(),
+    };
+
+    set_default_headers(&mut HttpResponse::Ok(), &response_type).body(body)
+}
@@ -0,0 +1,293 @@
+//! Streaming response bodies
+//!
+//! Fragments are serialized in chunks on the blocking thread pool while they are being read from
+//! the database, so the first bytes are sent before the last statement is loaded and the full
+//! serialization never has to be kept in memory.
+//!
+//! Bulk responses send the public documents while they are loaded, the others follow once they are
+//! authorized.
+
+use crate::errors::ErrorKind;
+use crate::hashtuple::{HashModel, LookupTable};
+use crate::serving::response_type::ResponseType;
+use crate::serving::responses::set_default_headers;
+use crate::serving::serialization::serialize_lines;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use futures::channel::{mpsc, oneshot};
+use futures::task::{waker, ArcWake};
+use futures::{Future, SinkExt};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+/// The amount of chunks buffered before the task waits for the client to catch up.
+const CHANNEL_CAPACITY: usize = 8;
+/// How long the task waits for the client to read a chunk before aborting the response.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Chunk = Result<Bytes, io::Error>;
+
+pub(crate) struct ChunkSender {
+    response_type: ResponseType,
+    sender: mpsc::Sender<Chunk>,
+    started: Option<oneshot::Sender<Result<(), ErrorKind>>>,
+}
+
+impl ChunkSender {
+    /// Commits to a successful response, errors after this abort the response body.
+    pub fn start(&mut self) {
+        if let Some(started) = self.started.take() {
+            let _ = started.send(Ok(()));
+        }
+    }
+
+    /// Serializes the model and sends it to the client, waiting up to `SEND_TIMEOUT` while the
+    /// client is behind.
+    pub fn send(&mut self, model: HashModel, table: &LookupTable) -> Result<(), ErrorKind> {
+        self.start();
+        if model.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = serialize_lines(&self.response_type, (model, table))
+            .ok_or_else(|| ErrorKind::Unexpected("Response type can't be streamed".into()))?;
+
+        match block_on_timeout(self.sender.send(Ok(Bytes::from(chunk))), SEND_TIMEOUT) {
+            Some(Ok(())) => Ok(()),
+            Some(Err(_)) => Err(ErrorKind::ClientDisconnected),
+            None => Err(ErrorKind::Timeout),
+        }
+    }
+
+    /// Ends the response with the result of the task which sent the chunks.
+    pub fn finish(mut self, result: Result<(), ErrorKind>) {
+        match result {
+            Ok(()) => self.start(),
+            Err(ErrorKind::ClientDisconnected) => {
+                debug!(target: "apex", "Client disconnected during streaming response");
+            }
+            Err(ErrorKind::Timeout) => {
+                warn!(target: "apex", "Timeout during streaming response");
+                self.fail(ErrorKind::Timeout);
+            }
+            Err(e) => {
+                error!(target: "apex", "Error while streaming response: {}", e);
+                self.fail(e);
+            }
+        }
+    }
+
+    fn fail(mut self, error: ErrorKind) {
+        match self.started.take() {
+            Some(started) => {
+                let _ = started.send(Err(error));
+            }
+            None => {
+                let abort = io::Error::new(io::ErrorKind::Other, error.to_string());
+                // A new sender has a slot of its own, so this doesn't wait for a client which is
+                // behind.
+                let _ = self.sender.clone().try_send(Err(abort));
+            }
+        }
+    }
+}
+
+/// Wakes the thread blocked in `block_on_timeout`.
+struct ThreadWaker(Thread);
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+/// Like `futures::executor::block_on`, but gives up after `timeout`.
+fn block_on_timeout<F: Future + Unpin>(mut future: F, timeout: Duration) -> Option<F::Output> {
+    let deadline = Instant::now() + timeout;
+    let waker = waker(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+            return Some(output);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
+/// The sender for the chunks of a streamed response, and the response.
+///
+/// The response resolves once the sender is started, or into an error response like
+/// `error_response` when it fails before that. Errors after it started abort the response body.
+pub(crate) fn response_channel(
+    response_type: ResponseType,
+) -> (ChunkSender, impl Future<Output = HttpResponse>) {
+    let (sender, body) = mpsc::channel(CHANNEL_CAPACITY);
+    let (started, is_started) = oneshot::channel();
+    let chunks = ChunkSender {
+        response_type: response_type.clone(),
+        sender,
+        started: Some(started),
+    };
+
+    (chunks, started_response(response_type, is_started, body))
+}
+
+async fn started_response(
+    response_type: ResponseType,
+    is_started: oneshot::Receiver<Result<(), ErrorKind>>,
+    body: mpsc::Receiver<Chunk>,
+) -> HttpResponse {
+    match is_started.await {
+        Ok(Ok(())) => set_default_headers(&mut HttpResponse::Ok(), &response_type).streaming(body),
+        Ok(Err(e)) => error_response(e),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Runs `task` on the blocking thread pool, streaming the chunks it sends as the response body.
+pub(crate) async fn stream_response<F>(response_type: ResponseType, task: F) -> HttpResponse
+where
+    F: FnOnce(&mut ChunkSender) -> Result<(), ErrorKind> + Send + 'static,
+{
+    let (mut chunks, response) = response_channel(response_type);
+
+    actix_rt::spawn(async move {
+        let res = web::block(move || -> Result<(), ()> {
+            let result = task(&mut chunks);
+            chunks.finish(result);
+
+            Ok(())
+        })
+        .await;
+
+        if let Err(e) = res {
+            error!(target: "apex", "Streaming task failed: {:?}", e);
+        }
+    });
+
+    response.await
+}
+
+/// The response for an error which occurred before any of the body was sent.
+pub(crate) fn error_response(error: ErrorKind) -> HttpResponse {
+    match error {
+        ErrorKind::ParserError(msg) => HttpResponse::BadRequest().body(msg),
+        ErrorKind::BackendUnavailable => HttpResponse::BadGateway().finish(),
+        ErrorKind::Timeout => HttpResponse::GatewayTimeout().finish(),
+        e => {
+            error!(target: "apex", "Caught error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashtuple::{Statement, STRING_IRI};
+    use actix_web::http::StatusCode;
+    use futures::{future, StreamExt};
+
+    fn name(table: &mut LookupTable, subject: &str, value: &str) -> HashModel {
+        vec![Statement::new(
+            table.ensure_value(subject),
+            table.ensure_value("http://schema.org/name"),
+            table.ensure_value(value),
+            table.ensure_value(STRING_IRI),
+            table.ensure_value(""),
+            table.ensure_value(""),
+        )]
+    }
+
+    async fn chunks(mut response: HttpResponse) -> Vec<Result<String, String>> {
+        let mut body = response.take_body();
+        let mut chunks = vec![];
+        while let Some(chunk) = body.next().await {
+            chunks.push(
+                chunk
+                    .map(|bytes| String::from_utf8(bytes.to_vec()).unwrap())
+                    .map_err(|e| e.to_string()),
+            );
+        }
+
+        chunks
+    }
+
+    #[test]
+    fn test_block_on_timeout() {
+        let timeout = Duration::from_millis(10);
+
+        assert_eq!(block_on_timeout(future::ready(1), timeout), Some(1));
+        assert_eq!(block_on_timeout(future::pending::<i32>(), timeout), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_stream_response() {
+        let response = stream_response(ResponseType::NTRIPLES, |chunks| {
+            let mut table = LookupTable::new(0);
+            let first = name(&mut table, "https://example.com/a", "A");
+            chunks.send(first, &table)?;
+            let second = name(&mut table, "https://example.com/b", "B");
+            chunks.send(second, &table)
+        })
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            chunks(response).await,
+            vec![
+                Ok(String::from(
+                    "<https://example.com/a> <http://schema.org/name> \"A\"^^<http://www.w3.org/2001/XMLSchema#string> .\n"
+                )),
+                Ok(String::from(
+                    "<https://example.com/b> <http://schema.org/name> \"B\"^^<http://www.w3.org/2001/XMLSchema#string> .\n"
+                )),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_stream_response_errors() {
+        let response = stream_response(ResponseType::NTRIPLES, |_| {
+            Err(ErrorKind::ParserError(String::from("Invalid object")))
+        })
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            chunks(response).await,
+            vec![Ok(String::from("Invalid object"))]
+        );
+
+        let response = stream_response(ResponseType::NTRIPLES, |_| {
+            Err(ErrorKind::Unexpected(String::from("Database unavailable")))
+        })
+        .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            error_response(ErrorKind::Unexpected(String::new())).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+
+        let response = stream_response(ResponseType::NTRIPLES, |chunks| {
+            let mut table = LookupTable::new(0);
+            let model = name(&mut table, "https://example.com/a", "A");
+            chunks.send(model, &table)?;
+            Err(ErrorKind::Unexpected(String::from("Connection lost")))
+        })
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let chunks = chunks(response).await;
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_ok());
+        assert!(chunks[1].as_ref().unwrap_err().contains("Connection lost"));
+    }
+}
@@ -0,0 +1,334 @@
+//! Human readable Turtle
+//!
+//! Statements are grouped per subject and predicate, IRIs are compacted with the configured
+//! prefixes and blank nodes which are referenced exactly once are nested in their referrer.
+
+use crate::hashtuple::{
+    LookupTable, Statement, BLANK_NODE_IRI, LANG_STRING_IRI, NAMED_NODE_IRI, STRING_IRI,
+};
+use crate::rdf::sparql::{XSD_BOOLEAN_IRI, XSD_INTEGER_IRI};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+const RDF_TYPE_IRI: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const INDENT: &str = "    ";
+
+pub(crate) fn statements_to_turtle(
+    statements: &[Statement],
+    table: &LookupTable,
+    prefixes: &BTreeMap<String, String>,
+) -> Vec<u8> {
+    TurtleWriter::new(statements, table, prefixes).finish()
+}
+
+struct TurtleWriter<'a> {
+    table: &'a LookupTable,
+    prefixes: &'a BTreeMap<String, String>,
+    /// The subjects in order of appearance.
+    subjects: Vec<u128>,
+    /// The objects per predicate of each subject, predicates in order of appearance.
+    properties: HashMap<u128, Vec<(u128, Vec<Statement>)>>,
+    /// The amount of times each blank node is used as an object.
+    references: HashMap<u128, usize>,
+    written: HashSet<u128>,
+    used_prefixes: BTreeSet<String>,
+    body: String,
+}
+
+impl<'a> TurtleWriter<'a> {
+    fn new(
+        statements: &[Statement],
+        table: &'a LookupTable,
+        prefixes: &'a BTreeMap<String, String>,
+    ) -> TurtleWriter<'a> {
+        let blank_node = table.calculate_hash(BLANK_NODE_IRI);
+        let rdf_type = table.calculate_hash(RDF_TYPE_IRI);
+
+        let mut subjects = vec![];
+        let mut properties: HashMap<u128, Vec<(u128, Vec<Statement>)>> = HashMap::new();
+        let mut references = HashMap::new();
+
+        // Turtle has no graphs, so statements differing only in their graph are duplicates.
+        let mut seen = HashSet::new();
+        for s in statements {
+            if !seen.insert(Statement { graph: 0, ..*s }) {
+                continue;
+            }
+
+            let predicates = properties.entry(s.subject).or_insert_with(|| {
+                subjects.push(s.subject);
+                vec![]
+            });
+            match predicates.iter_mut().find(|(p, _)| *p == s.predicate) {
+                Some((_, objects)) => objects.push(*s),
+                None if s.predicate == rdf_type => predicates.insert(0, (s.predicate, vec![*s])),
+                None => predicates.push((s.predicate, vec![*s])),
+            }
+
+            if s.datatype == blank_node {
+                *references.entry(s.value).or_insert(0) += 1;
+            }
+        }
+
+        TurtleWriter {
+            table,
+            prefixes,
+            subjects,
+            properties,
+            references,
+            written: HashSet::new(),
+            used_prefixes: BTreeSet::new(),
+            body: String::new(),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let subjects = self.subjects.clone();
+        // Nested nodes are written by their referrer, unless they're part of a cycle.
+        for pass in &[false, true] {
+            for subject in &subjects {
+                if !self.written.contains(subject) && (*pass || !self.is_nested(*subject)) {
+                    self.write_subject(*subject);
+                }
+            }
+        }
+
+        let mut output = String::new();
+        for prefix in &self.used_prefixes {
+            output.push_str(&format!(
+                "@prefix {}: <{}> .\n",
+                prefix, self.prefixes[prefix]
+            ));
+        }
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output.push_str(&self.body);
+
+        output.into_bytes()
+    }
+
+    fn is_nested(&self, node: u128) -> bool {
+        self.references.get(&node) == Some(&1)
+    }
+
+    fn write_subject(&mut self, subject: u128) {
+        self.written.insert(subject);
+        let term = self.node(subject);
+        self.body.push_str(&term);
+        self.write_properties(subject, 1);
+        self.body.push_str(" .\n");
+    }
+
+    fn write_properties(&mut self, subject: u128, depth: usize) {
+        let properties = self.properties.get(&subject).cloned().unwrap_or_default();
+
+        for (i, (predicate, objects)) in properties.iter().enumerate() {
+            if i == 0 && depth == 1 {
+                self.body.push(' ');
+            } else {
+                if i > 0 {
+                    self.body.push_str(" ;");
+                }
+                self.body.push('\n');
+                self.body.push_str(&INDENT.repeat(depth));
+            }
+            let predicate = if self.value(*predicate) == RDF_TYPE_IRI {
+                String::from("a")
+            } else {
+                self.iri(*predicate)
+            };
+            self.body.push_str(&predicate);
+            self.body.push(' ');
+
+            for (j, object) in objects.iter().enumerate() {
+                if j > 0 {
+                    self.body.push_str(" , ");
+                }
+                self.write_object(object, depth);
+            }
+        }
+    }
+
+    fn write_object(&mut self, statement: &Statement, depth: usize) {
+        let datatype = self.value(statement.datatype);
+
+        match datatype {
+            BLANK_NODE_IRI
+                if self.is_nested(statement.value) && !self.written.contains(&statement.value) =>
+            {
+                self.written.insert(statement.value);
+                if self.properties.contains_key(&statement.value) {
+                    self.body.push('[');
+                    self.write_properties(statement.value, depth + 1);
+                    self.body.push('\n');
+                    self.body.push_str(&INDENT.repeat(depth));
+                    self.body.push(']');
+                } else {
+                    self.body.push_str("[]");
+                }
+            }
+            BLANK_NODE_IRI => {
+                let term = blank_node(self.value(statement.value));
+                self.body.push_str(&term);
+            }
+            NAMED_NODE_IRI => {
+                let term = self.iri(statement.value);
+                self.body.push_str(&term);
+            }
+            _ => {
+                let term = self.literal(statement);
+                self.body.push_str(&term);
+            }
+        }
+    }
+
+    fn node(&mut self, id: u128) -> String {
+        let value = self.value(id);
+        if value.starts_with("_:") || !value.contains(':') {
+            blank_node(value)
+        } else {
+            self.iri(id)
+        }
+    }
+
+    /// Compacts the IRI with the longest matching prefix, if it results in a valid prefixed name.
+    fn iri(&mut self, id: u128) -> String {
+        let iri = self.value(id);
+        let compacted = self
+            .prefixes
+            .iter()
+            .filter(|(_, namespace)| iri.starts_with(namespace.as_str()))
+            .max_by_key(|(_, namespace)| namespace.len())
+            .filter(|(_, namespace)| is_local_name(&iri[namespace.len()..]));
+
+        match compacted {
+            Some((prefix, namespace)) => {
+                self.used_prefixes.insert(prefix.clone());
+                format!("{}:{}", prefix, &iri[namespace.len()..])
+            }
+            None => format!("<{}>", iri),
+        }
+    }
+
+    fn literal(&mut self, statement: &Statement) -> String {
+        let value = self.value(statement.value);
+        let datatype = self.value(statement.datatype);
+
+        match datatype {
+            STRING_IRI => quote(value),
+            LANG_STRING_IRI => format!("{}@{}", quote(value), self.value(statement.language)),
+            XSD_BOOLEAN_IRI if value == "true" || value == "false" => value.to_string(),
+            XSD_INTEGER_IRI if is_integer(value) => value.to_string(),
+            _ => format!("{}^^{}", quote(value), self.iri(statement.datatype)),
+        }
+    }
+
+    fn value(&self, id: u128) -> &'a str {
+        self.table.get_by_hash(id).unwrap()
+    }
+}
+
+fn blank_node(value: &str) -> String {
+    format!("_:{}", value.trim_start_matches("_:"))
+}
+
+/// Whether `value` can be written as the local part of a prefixed name without escaping.
+fn is_local_name(value: &str) -> bool {
+    let valid_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '.';
+
+    value.is_empty()
+        || (value.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+            && !value.ends_with('.')
+            && value.chars().all(valid_char))
+}
+
+fn is_integer(value: &str) -> bool {
+    let digits = value.trim_start_matches(&['-', '+'][..]);
+
+    !digits.is_empty()
+        && value.len() - digits.len() <= 1
+        && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements_to_turtle() {
+        let mut table = LookupTable::new(0);
+        let mut statement = |subject: &str, predicate: &str, value: &str, datatype: &str| {
+            Statement::new(
+                table.ensure_value(subject),
+                table.ensure_value(predicate),
+                table.ensure_value(value),
+                table.ensure_value(datatype),
+                table.ensure_value(""),
+                table.ensure_value(""),
+            )
+        };
+        let statements = vec![
+            statement(
+                "https://example.com/a",
+                "http://schema.org/name",
+                "A \"quoted\" name",
+                STRING_IRI,
+            ),
+            statement(
+                "https://example.com/a",
+                "http://schema.org/author",
+                "_:b0",
+                BLANK_NODE_IRI,
+            ),
+            statement(
+                "https://example.com/a",
+                RDF_TYPE_IRI,
+                "http://schema.org/Thing",
+                NAMED_NODE_IRI,
+            ),
+            statement("_:b0", "http://schema.org/age", "42", XSD_INTEGER_IRI),
+            statement(
+                "https://example.com/a",
+                "http://schema.org/url",
+                "https://example.com/a?b",
+                NAMED_NODE_IRI,
+            ),
+        ];
+        let mut prefixes = BTreeMap::new();
+        prefixes.insert(String::from("schema"), String::from("http://schema.org/"));
+        prefixes.insert(String::from("ex"), String::from("https://example.com/"));
+
+        let output = TurtleWriter::new(&statements, &table, &prefixes).finish();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "@prefix ex: <https://example.com/> .\n\
+             @prefix schema: <http://schema.org/> .\n\
+             \n\
+             ex:a a schema:Thing ;\n    \
+             schema:name \"A \\\"quoted\\\" name\" ;\n    \
+             schema:author [\n        \
+             schema:age 42\n    \
+             ] ;\n    \
+             schema:url <https://example.com/a?b> .\n"
+        );
+    }
+}
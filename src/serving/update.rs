@@ -1,16 +1,22 @@
-use crate::db::db_context::{DbContext, DbPool};
+use crate::app_config::AppConfig;
+use crate::db::storage::Storage;
 use crate::errors::ErrorKind;
 use crate::hashtuple::LookupTable;
 use crate::importing::importer::process_message;
-use crate::importing::parsing::{parse_hndjson, DocumentSet};
+use crate::importing::parsing::{
+    parse_hndjson, parse_jsonld, parse_nquads, parse_ntriples, parse_trig, parse_turtle,
+    DocumentSet,
+};
 use crate::serving::response_type::ResponseType;
 use crate::serving::responses::set_default_headers;
+use actix_web::http::header;
 use actix_web::{post, web, HttpResponse, Responder};
 use futures::StreamExt;
 
 #[post("/update")]
 pub(crate) async fn update<'a>(
-    pool: web::Data<DbPool>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<AppConfig>,
     req: actix_web::HttpRequest,
     payload: web::Payload,
 ) -> impl Responder {
@@ -19,15 +25,20 @@ pub(crate) async fn update<'a>(
     } else {
         String::from("en")
     };
-    let mut ctx = DbContext::new_with_lang(&pool, Some(lang));
-    let delta = match parse_payload(&mut ctx.lookup_table, payload).await {
+    let content_type = match body_type(&req) {
+        Some(content_type) => content_type,
+        None => return HttpResponse::UnsupportedMediaType().finish(),
+    };
+    let mut ctx = storage.open(Some(lang));
+    let delta = match parse_payload(ctx.lookup_table(), &content_type, payload).await {
         Ok(delta) => delta,
+        Err(ErrorKind::ParserError(msg)) => return HttpResponse::BadRequest().body(msg),
         Err(_) => return HttpResponse::BadRequest().finish(),
     };
 
     let total: usize = delta.iter().map(|(_, ds)| ds.len()).sum();
     debug!(target: "apex", "Received {} statements from body", total);
-    let mut res = match process_message(&mut ctx, delta).await {
+    let mut res = match process_message(&mut *ctx, delta, &config).await {
         Ok(_) => HttpResponse::Ok(),
         Err(e) => {
             warn!(target: "apex", "Processing delta message failed: {}", e);
@@ -38,8 +49,28 @@ pub(crate) async fn update<'a>(
     set_default_headers(&mut res, &ResponseType::HEXTUPLE).finish()
 }
 
+/// The format of the body, hextuples when no content type is given.
+fn body_type(req: &actix_web::HttpRequest) -> Option<ResponseType> {
+    let content_type = match req.headers().get(header::CONTENT_TYPE) {
+        Some(value) => value.to_str().ok()?,
+        None => return Some(ResponseType::HEXTUPLE),
+    };
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+    match ResponseType::from_mime(mime) {
+        Ok(ResponseType::HEXTUPLE) => Some(ResponseType::HEXTUPLE),
+        Ok(ResponseType::NQUADS) => Some(ResponseType::NQUADS),
+        Ok(ResponseType::TRIG) => Some(ResponseType::TRIG),
+        Ok(ResponseType::JSONLD) => Some(ResponseType::JSONLD),
+        Ok(ResponseType::TURTLE) => Some(ResponseType::TURTLE),
+        Ok(ResponseType::NTRIPLES) => Some(ResponseType::NTRIPLES),
+        _ => None,
+    }
+}
+
 async fn parse_payload(
-    mut lookup_table: &mut LookupTable,
+    lookup_table: &mut LookupTable,
+    content_type: &ResponseType,
     mut payload: web::Payload,
 ) -> Result<DocumentSet, ErrorKind> {
     let mut bytes = web::BytesMut::new();
@@ -47,5 +78,16 @@ async fn parse_payload(
         bytes.extend_from_slice(&item.map_err(|e| ErrorKind::Unexpected(e.to_string()))?);
     }
 
-    parse_hndjson(&mut lookup_table, bytes.as_ref())
+    match content_type {
+        ResponseType::NQUADS => {
+            let body = String::from_utf8(bytes.to_vec())
+                .map_err(|e| ErrorKind::ParserError(e.to_string()))?;
+            parse_nquads(lookup_table, &body)
+        }
+        ResponseType::TRIG => parse_trig(lookup_table, bytes.as_ref()),
+        ResponseType::JSONLD => parse_jsonld(lookup_table, bytes.as_ref()),
+        ResponseType::TURTLE => parse_turtle(lookup_table, bytes.as_ref()),
+        ResponseType::NTRIPLES => parse_ntriples(lookup_table, bytes.as_ref()),
+        _ => parse_hndjson(lookup_table, bytes.as_ref()),
+    }
 }